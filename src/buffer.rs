@@ -3,8 +3,10 @@ use encoding;
 use encoding::label::encoding_from_whatwg_label;
 use encoding::EncodingRef;
 use encoding::{DecoderTrap, EncoderTrap};
+use regex::Regex;
 use ropey;
 use ropey::Rope;
+use std::collections::BTreeSet;
 use std::fmt;
 use std::fs::File;
 use std::fs::OpenOptions;
@@ -13,6 +15,174 @@ use std::io::prelude::Write;
 use std::io::Read;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+/// Number of lines scanned to detect the line ending of a buffer
+const LINE_ENDING_SCAN_LINES: usize = 1000;
+
+/// Number of bytes scanned for a null byte to detect a binary file
+const BINARY_SCAN_BYTES: usize = 8000;
+
+/// Byte order marks recognized when a file is loaded, with the label of their encoding
+const BOMS: [(&[u8], &str); 3] = [
+    (b"\xEF\xBB\xBF", "utf-8"),
+    (b"\xFF\xFE", "utf-16le"),
+    (b"\xFE\xFF", "utf-16be"),
+];
+
+/// return the encoding given by the byte order mark at the start of bytes, and the length of the mark
+fn detect_bom(bytes: &[u8]) -> Option<(EncodingRef, usize)> {
+    BOMS.iter()
+        .find(|(bom, _)| bytes.starts_with(bom))
+        .and_then(|(bom, label)| encoding_from_whatwg_label(label).map(|e| (e, bom.len())))
+}
+
+/// return the UTF-16 encoding of bytes without byte order mark. It is recognized by its printable
+/// ASCII chars, a text byte next to a null high byte, that make at least half of the scanned chars.
+/// A text with few ASCII chars is not recognized, and is then rejected as a binary file
+fn detect_utf16(bytes: &[u8]) -> Option<EncodingRef> {
+    if bytes.is_empty() || bytes.len() % 2 != 0 {
+        return None;
+    }
+    let scanned = &bytes[..std::cmp::min(bytes.len(), BINARY_SCAN_BYTES)];
+    let is_text = |b: u8| b.is_ascii_graphic() || b" \t\r\n".contains(&b);
+    // low is the offset of the low byte in a char, 0 in little endian
+    let looks_like = |low: usize| {
+        let units = scanned.chunks_exact(2);
+        let ascii = units.clone().filter(|u| u[1 - low] == 0 && is_text(u[low])).count();
+        units.clone().all(|u| u[low] != 0) && ascii * 2 >= units.len()
+    };
+    if looks_like(0) {
+        Some(encoding::all::UTF_16LE)
+    } else if looks_like(1) {
+        Some(encoding::all::UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// return the byte order mark of the given encoding, if it has one
+fn bom_of(encoding: EncodingRef) -> Option<&'static [u8]> {
+    BOMS.iter().find(|(_, label)| *label == encoding.name()).map(|(bom, _)| *bom)
+}
+
+/// Last revision given to a buffer content, shared by all buffers so a revision is never reused
+static REVISION: AtomicUsize = AtomicUsize::new(0);
+
+fn next_revision() -> usize {
+    REVISION.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Line break style of a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    /// return the platform default line ending
+    pub fn native() -> Self {
+        #[cfg(target_os = "windows")]
+        return LineEnding::CrLf;
+        #[cfg(not(target_os = "windows"))]
+        return LineEnding::Lf;
+    }
+
+    /// return the line ending as a string
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// detect the dominant line ending of the first lines of the rope
+    fn detect(rope: &Rope) -> Self {
+        let mut lf = 0;
+        let mut crlf = 0;
+        for line in rope.lines().take(LINE_ENDING_SCAN_LINES) {
+            let len = line.len_chars();
+            if len > 0 && line.char(len - 1) == '\n' {
+                if len > 1 && line.char(len - 2) == '\r' {
+                    crlf += 1;
+                } else {
+                    lf += 1;
+                }
+            }
+        }
+        if crlf > lf {
+            LineEnding::CrLf
+        } else if lf > crlf {
+            LineEnding::Lf
+        } else {
+            LineEnding::native()
+        }
+    }
+}
+
+/// return true if the char is part of a word ([A-Za-z0-9_])
+pub fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Ranges of the chars drawn on two columns: CJK ideographs, hangul, fullwidth forms and emojis
+const WIDE_CHARS: [(u32, u32); 15] = [
+    (0x1100, 0x115F),
+    (0x2E80, 0x303E),
+    (0x3041, 0x33FF),
+    (0x3400, 0x4DBF),
+    (0x4E00, 0x9FFF),
+    (0xA000, 0xA4CF),
+    (0xAC00, 0xD7A3),
+    (0xF900, 0xFAFF),
+    (0xFE30, 0xFE4F),
+    (0xFF00, 0xFF60),
+    (0xFFE0, 0xFFE6),
+    (0x1F300, 0x1F64F),
+    (0x1F900, 0x1F9FF),
+    (0x20000, 0x2FFFD),
+    (0x30000, 0x3FFFD),
+];
+
+/// return the number of columns taken by the char when drawn, 2 for the wide chars, 1 otherwise
+pub fn char_width(c: char) -> usize {
+    let c = c as u32;
+    // the chars before the first wide range are all one column
+    if c < WIDE_CHARS[0].0 {
+        return 1;
+    }
+    if WIDE_CHARS.iter().any(|(start, end)| c >= *start && c <= *end) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Options used by Buffer::find_with
+#[derive(Debug, Clone, Copy)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            case_sensitive: true,
+            whole_word: false,
+        }
+    }
+}
+
+/// Counts of a buffer content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferStats {
+    pub chars: usize,
+    pub lines: usize,
+    pub words: usize,
+}
 
 /// A text Buffer
 #[derive(Clone)]
@@ -21,17 +191,30 @@ pub struct Buffer {
     filename: Option<PathBuf>,
     is_dirty: bool,
     encoding: EncodingRef,
+    // true if the file started with a byte order mark, it is written back on save
+    bom: bool,
+    line_ending: LineEnding,
+    revision: usize,
+    // last modification time of the file when it was loaded or saved
+    modified: Option<SystemTime>,
+    changed_on_disk: bool,
+    // number of nested transactions, and true if the content changed in the current one
+    edit_depth: usize,
+    edit_touched: bool,
+    // bookmarked lines, moved with their content when lines are inserted or removed above them
+    bookmarks: BTreeSet<usize>,
 }
 
 impl fmt::Debug for Buffer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Buffer {{rope: {:?}, filename: {:?}, is_dirty: {}, encoding: {} }}",
+            "Buffer {{rope: {:?}, filename: {:?}, is_dirty: {}, encoding: {}, line_ending: {:?} }}",
             self.rope,
             self.filename,
             self.is_dirty,
-            self.encoding.name()
+            self.encoding.name(),
+            self.line_ending
         )
     }
 }
@@ -44,40 +227,103 @@ impl Buffer {
             filename: None,
             is_dirty: false,
             encoding: encoding_from_whatwg_label("utf8").unwrap(),
+            bom: false,
+            line_ending: LineEnding::native(),
+            revision: next_revision(),
+            modified: None,
+            changed_on_disk: false,
+            edit_depth: 0,
+            edit_touched: false,
+            bookmarks: BTreeSet::new(),
         }
     }
     /// create a buffer from the given string
     pub fn from_str(text: &str) -> Self {
+        let rope = Rope::from_str(text);
+        let line_ending = LineEnding::detect(&rope);
         Buffer {
-            rope: Rope::from_str(text),
+            rope,
             filename: None,
             is_dirty: false,
             encoding: encoding_from_whatwg_label("utf8").unwrap(),
+            bom: false,
+            line_ending,
+            revision: next_revision(),
+            modified: None,
+            changed_on_disk: false,
+            edit_depth: 0,
+            edit_touched: false,
+            bookmarks: BTreeSet::new(),
         }
     }
-    /// create a buffer from the give file
+    /// create a buffer from the give file, its encoding is guessed from the content
     pub fn from_file(filename: &Path) -> Result<Self, io::Error> {
+        Buffer::load(filename, None)
+    }
+
+    /// create a buffer from the give file, or an empty buffer if the file doesn't exist.
+    /// The filename is set in both cases, so the first save creates the file
+    pub fn from_file_or_new(filename: &Path) -> Result<Self, io::Error> {
+        match Buffer::from_file(filename) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut buf = Buffer::new();
+                buf.set_filename(filename);
+                Ok(buf)
+            }
+            result => result,
+        }
+    }
+
+    /// create a buffer from the give file. The encoding is given by the byte order mark of the file,
+    /// or is the given one if there is no mark
+    pub fn from_file_with_encoding(filename: &Path, encoding: EncodingRef) -> Result<Self, io::Error> {
+        Buffer::load(filename, Some(encoding))
+    }
+
+    fn load(filename: &Path, fallback: Option<EncodingRef>) -> Result<Self, io::Error> {
         let mut fh = io::BufReader::new(File::open(filename)?);
+        let modified = fh.get_ref().metadata().and_then(|m| m.modified()).ok();
         let mut reader: Vec<u8> = Vec::new();
 
         // read file
         fh.read_to_end(&mut reader)?;
 
         // detect charset of the file
-        let result = chardet::detect(&reader);
+        let (coder, bom_len) = match (detect_bom(&reader), fallback) {
+            (Some(bom), _) => bom,
+            (None, Some(encoding)) => (encoding, 0),
+            (None, None) => {
+                if let Some(encoding) = detect_utf16(&reader) {
+                    (encoding, 0)
+                } else if reader.iter().take(BINARY_SCAN_BYTES).any(|b| *b == 0) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Binary file"));
+                } else {
+                    let result = chardet::detect(&reader);
+                    let encoding = chardet::charset2encoding(&result.0);
+                    println!("Detected Encoding: {}", encoding);
+                    (encoding_from_whatwg_label(encoding).unwrap_or(encoding::all::UTF_8), 0)
+                }
+            }
+        };
 
         // decode file into utf-8
-        let encoding = chardet::charset2encoding(&result.0);
-        println!("Detected Encoding: {}", encoding);
-        let coder = encoding_from_whatwg_label(encoding).unwrap_or(encoding::all::UTF_8);
-        let utf8reader = coder.decode(&reader, DecoderTrap::Replace).expect("Error");
+        let utf8reader = coder.decode(&reader[bom_len..], DecoderTrap::Replace).expect("Error");
 
         let r = Rope::from_str(&utf8reader);
+        let line_ending = LineEnding::detect(&r);
         Ok(Buffer {
             rope: r,
             filename: Some(filename.to_owned()),
             is_dirty: false,
             encoding: coder,
+            bom: bom_len > 0,
+            line_ending,
+            revision: next_revision(),
+            modified,
+            changed_on_disk: false,
+            edit_depth: 0,
+            edit_touched: false,
+            bookmarks: BTreeSet::new(),
         })
     }
 
@@ -86,6 +332,49 @@ impl Buffer {
         self.encoding
     }
 
+    /// return the line ending detected when the buffer was loaded
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// return the buffer content with every line break converted to the buffer line ending,
+    /// or None if the content already use it everywhere
+    fn normalized_text(&self) -> Option<String> {
+        let mut foreign = false;
+        let mut chars = self.rope.chars().peekable();
+        while let Some(c) = chars.next() {
+            let found = match c {
+                '\r' if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                    LineEnding::CrLf
+                }
+                '\n' => LineEnding::Lf,
+                _ => continue,
+            };
+            if found != self.line_ending {
+                foreign = true;
+                break;
+            }
+        }
+        if !foreign {
+            return None;
+        }
+
+        let mut text = String::with_capacity(self.byte_len());
+        let mut chars = self.rope.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                    text.push_str(self.line_ending.as_str());
+                }
+                '\n' => text.push_str(self.line_ending.as_str()),
+                _ => text.push(c),
+            }
+        }
+        Some(text)
+    }
+
     /// return the filename
     pub fn get_filename(&self) -> Option<&Path> {
         match &self.filename {
@@ -96,30 +385,95 @@ impl Buffer {
 
     /// save the current buffer to disk
     pub fn save(&mut self) -> io::Result<()> {
-        if let Some(filename) = &self.filename {
-            if let Ok(r) = self.encoding.encode(&self.rope.to_string(), EncoderTrap::Replace) {
-                let mut file = OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(filename)?;
-                file.write_all(&r)?;
-                Ok(())
-            } else {
-                return Err(io::Error::new(io::ErrorKind::Other, "Error while encoding buffer"));
+        let filename = match &self.filename {
+            Some(f) => f,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "No filename associated")),
+        };
+        let mut writer = io::BufWriter::new(
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(filename)?,
+        );
+        if self.bom {
+            if let Some(bom) = bom_of(self.encoding) {
+                writer.write_all(bom)?;
+            }
+        }
+        let normalized = self.normalized_text();
+        if self.encoding.name() == "utf-8" {
+            match normalized {
+                Some(text) => writer.write_all(text.as_bytes())?,
+                None => self.rope.write_to(&mut writer)?,
             }
         } else {
-            return Err(io::Error::new(io::ErrorKind::Other, "No filename associated"));
+            let text = normalized.unwrap_or_else(|| self.rope.to_string());
+            match self.encoding.encode(&text, EncoderTrap::Replace) {
+                Ok(r) => writer.write_all(&r)?,
+                Err(_) => return Err(io::Error::new(io::ErrorKind::Other, "Error while encoding buffer")),
+            }
         }
+        writer.flush()?;
+        self.is_dirty = false;
+        self.modified = writer.get_ref().metadata().and_then(|m| m.modified()).ok();
+        self.changed_on_disk = false;
+        Ok(())
     }
 
     /// save the current buffer to disk with the given filename
     pub fn save_as<P: AsRef<Path>>(&mut self, filename: P) -> io::Result<()> {
         self.set_filename(filename.as_ref());
-        self.save()?;
+        self.save()
+    }
+
+    /// read the file again, discarding the unsaved changes.
+    /// It is decoded with the encoding it was opened with, unless it now starts with a byte order mark
+    pub fn reload(&mut self) -> io::Result<()> {
+        let filename = match &self.filename {
+            Some(f) => f,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "No filename associated")),
+        };
+        let reloaded = Buffer::from_file_with_encoding(filename, self.encoding)?;
+        self.rope = reloaded.rope;
+        self.encoding = reloaded.encoding;
+        self.bom = reloaded.bom;
+        self.line_ending = reloaded.line_ending;
+        self.modified = reloaded.modified;
+        self.changed_on_disk = false;
+        self.touch();
+        self.is_dirty = false;
         Ok(())
     }
 
+    /// return true if the file was modified on disk since it was loaded or saved
+    pub fn external_change_detected(&self) -> io::Result<bool> {
+        match (&self.filename, self.modified) {
+            (Some(filename), Some(modified)) => Ok(filename.metadata()?.modified()? != modified),
+            _ => Ok(false),
+        }
+    }
+
+    /// return true if the file was flagged as modified by another program
+    pub fn is_changed_on_disk(&self) -> bool {
+        self.changed_on_disk
+    }
+
+    /// flag the file as modified by another program, until it is saved or reloaded
+    pub fn mark_changed_on_disk(&mut self) {
+        self.changed_on_disk = true;
+    }
+
+    /// return true if the buffer has been modified since the last save
+    pub fn is_dirty(&self) -> bool {
+        self.is_dirty
+    }
+
+    /// mark the buffer as unmodified
+    pub fn mark_clean(&mut self) {
+        self.is_dirty = false;
+    }
+
     /// set filename
     pub fn set_filename(&mut self, filename: &Path) {
         self.filename = Some(filename.to_owned());
@@ -132,34 +486,179 @@ impl Buffer {
     pub fn lines(&self) -> ropey::iter::Lines<'_> {
         self.rope.lines()
     }
+    /// Iterate over the text chunks of the buffer, in order, without copying them.
+    /// The chunk boundaries depend on the rope layout, not on the content
+    pub fn chunks(&self) -> ropey::iter::Chunks<'_> {
+        self.rope.chunks()
+    }
+    /// return the given line, with its line feed
+    pub fn line(&self, line_idx: usize) -> ropey::RopeSlice<'_> {
+        self.rope.line(line_idx)
+    }
 
     /// iterate over chars of the given line
     pub fn chars_on_line(&self,line: usize) -> ropey::iter::Chars<'_> {
         self.rope.line(line).chars()
     }
 
+    /// return the char at the given position
+    pub fn char(&self, char_idx: usize) -> char {
+        self.rope.char(char_idx)
+    }
+
     /// Total number of chars in the buffer
     pub fn len_chars(&self) -> usize {
         self.rope.len_chars()
     }
+    /// Total number of bytes of the buffer in utf-8. It differs from the number of chars
+    /// as soon as the text has a multibyte char, so a byte offset is not a char index
+    pub fn byte_len(&self) -> usize {
+        self.rope.len_bytes()
+    }
     /// Total number of lines in the buffer
     pub fn len_lines(&self) -> usize {
         self.rope.len_lines()
     }
     /// insert ch at the given position
     pub fn insert_char(&mut self, char_idx: usize, ch: char) {
+        let len_lines = self.rope.len_lines();
         self.rope.insert_char(char_idx, ch);
-        self.is_dirty = true;
+        self.shift_bookmarks_on_insert(char_idx, len_lines);
+        self.touch();
     }
-    /// Insert the string at the given position
+    /// Insert the string at the given position, an empty string leaves the buffer untouched
     pub fn insert<S: AsRef<str>>(&mut self, char_idx: usize, text: S) {
+        if text.as_ref().is_empty() {
+            return;
+        }
+        let len_lines = self.rope.len_lines();
         self.rope.insert(char_idx, text.as_ref());
-        self.is_dirty = true;
+        self.shift_bookmarks_on_insert(char_idx, len_lines);
+        self.touch();
     }
-    /// remove the given range from the buffer
+    /// remove the given range from the buffer, an empty range leaves the buffer untouched
     pub fn remove<R: Into<Range<usize>>>(&mut self, char_range: R) {
-        self.rope.remove(char_range.into());
+        let char_range = char_range.into();
+        if char_range.start == char_range.end {
+            return;
+        }
+        self.shift_bookmarks_on_remove(&char_range);
+        self.rope.remove(char_range);
+        self.touch();
+    }
+
+    /// move the bookmarks below the text inserted at char_idx, given the number of lines before the insertion.
+    /// A bookmark on the line of the insertion moves if the text is inserted at the start of the line
+    fn shift_bookmarks_on_insert(&mut self, char_idx: usize, len_lines: usize) {
+        let added = self.rope.len_lines() - len_lines;
+        if added == 0 || self.bookmarks.is_empty() {
+            return;
+        }
+        let line = self.rope.char_to_line(char_idx);
+        let first_moved = if char_idx == self.rope.line_to_char(line) { line } else { line + 1 };
+        self.bookmarks = self
+            .bookmarks
+            .iter()
+            .map(|b| if *b >= first_moved { b + added } else { *b })
+            .collect();
+    }
+
+    /// move the bookmarks below the range up, before it is removed.
+    /// The bookmarks on the removed lines are merged on the first line of the range
+    fn shift_bookmarks_on_remove(&mut self, char_range: &Range<usize>) {
+        if self.bookmarks.is_empty() {
+            return;
+        }
+        let start = self.rope.char_to_line(char_range.start);
+        let end = self.rope.char_to_line(char_range.end);
+        self.bookmarks = self
+            .bookmarks
+            .iter()
+            .map(|b| match *b {
+                b if b <= start => b,
+                b if b <= end => start,
+                b => b - (end - start),
+            })
+            .collect();
+    }
+
+    /// add or remove a bookmark on the given line
+    pub fn toggle_bookmark(&mut self, line: usize) {
+        if !self.bookmarks.remove(&line) {
+            self.bookmarks.insert(line);
+        }
+    }
+
+    /// return true if the given line is bookmarked
+    pub fn is_bookmarked(&self, line: usize) -> bool {
+        self.bookmarks.contains(&line)
+    }
+
+    /// return the bookmarked lines, in order
+    pub fn bookmarks(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bookmarks.iter().cloned()
+    }
+
+    /// replace the bookmarks, the lines past the end of the buffer are moved to the last line
+    pub fn set_bookmarks(&mut self, lines: impl IntoIterator<Item = usize>) {
+        let last = self.len_lines().saturating_sub(1);
+        self.bookmarks = lines.into_iter().map(|l| std::cmp::min(l, last)).collect();
+    }
+
+    /// return the first bookmark after the given line, wrapping at the end of the buffer
+    pub fn next_bookmark(&self, line: usize) -> Option<usize> {
+        self.bookmarks
+            .range(line + 1..)
+            .next()
+            .or_else(|| self.bookmarks.iter().next())
+            .cloned()
+    }
+
+    /// return the last bookmark before the given line, wrapping at the beginning of the buffer
+    pub fn prev_bookmark(&self, line: usize) -> Option<usize> {
+        self.bookmarks
+            .range(..line)
+            .next_back()
+            .or_else(|| self.bookmarks.iter().next_back())
+            .cloned()
+    }
+
+    /// mark the content as modified, once at the end of the current transaction if there is one
+    fn touch(&mut self) {
+        if self.edit_depth > 0 {
+            self.edit_touched = true;
+            return;
+        }
         self.is_dirty = true;
+        self.revision = next_revision();
+    }
+
+    /// start a transaction: the edits done until end_edit are a single change of the content.
+    /// Transactions can be nested, the outermost one ends the change
+    pub fn begin_edit(&mut self) {
+        self.edit_depth += 1;
+    }
+
+    /// end the current transaction
+    pub fn end_edit(&mut self) {
+        self.edit_depth = self.edit_depth.saturating_sub(1);
+        if self.edit_depth == 0 && self.edit_touched {
+            self.edit_touched = false;
+            self.touch();
+        }
+    }
+
+    /// run f in a transaction
+    pub fn edit<F: FnOnce(&mut Buffer) -> R, R>(&mut self, f: F) -> R {
+        self.begin_edit();
+        let result = f(self);
+        self.end_edit();
+        result
+    }
+
+    /// return a number identifying the current content, it changes on every modification
+    pub fn revision(&self) -> usize {
+        self.revision
     }
 
     /// Returns the entire buffer as a newly allocated String.
@@ -170,6 +669,212 @@ impl Buffer {
         self.rope.slice(r.into()).to_string()
     }
 
+    /// iterate over the chars of the given range, without copying them
+    pub fn slice_chars<R: Into<Range<usize>>>(&self, r: R) -> impl Iterator<Item = char> + '_ {
+        self.rope.slice(r.into()).chars()
+    }
+
+    /// return the char index of the first occurrence of needle at or after from
+    pub fn find(&self, needle: &str, from: usize) -> Option<usize> {
+        if needle.is_empty() || from > self.len_chars() {
+            return None;
+        }
+        let needle_len = needle.chars().count();
+
+        // a match may straddle two chunks, so keep the tail of the previous chunks in a window
+        let mut window = String::new();
+        let mut window_start = from;
+        for chunk in self.rope.slice(from..).chunks() {
+            window.push_str(chunk);
+            if let Some(b) = window.find(needle) {
+                return Some(window_start + window[..b].chars().count());
+            }
+            let window_len = window.chars().count();
+            if window_len >= needle_len {
+                let drop = window_len - needle_len + 1;
+                let b = window.char_indices().nth(drop).map(|(b, _)| b).unwrap_or_else(|| window.len());
+                window.drain(..b);
+                window_start += drop;
+            }
+        }
+        None
+    }
+
+    /// return the char index of the first occurrence of needle at or after from, using the given options
+    pub fn find_with(&self, needle: &str, from: usize, opts: SearchOptions) -> Option<usize> {
+        use std::collections::VecDeque;
+        if opts.case_sensitive && !opts.whole_word {
+            return self.find(needle, from);
+        }
+        if needle.is_empty() || from > self.len_chars() {
+            return None;
+        }
+        let needle: Vec<char> = needle.chars().collect();
+        let same = |a: char, b: char| {
+            if opts.case_sensitive {
+                a == b
+            } else {
+                a == b || a.to_lowercase().eq(b.to_lowercase())
+            }
+        };
+
+        let mut chars = self.rope.slice(from..).chars();
+        let mut window: VecDeque<char> = chars.by_ref().take(needle.len()).collect();
+        let mut prev = if from > 0 { Some(self.char(from - 1)) } else { None };
+        let mut start = from;
+        while window.len() == needle.len() {
+            let next = chars.next();
+            if window.iter().zip(needle.iter()).all(|(a, b)| same(*a, *b))
+                && (!opts.whole_word
+                    || (!prev.map_or(false, is_word_char) && !next.map_or(false, is_word_char)))
+            {
+                return Some(start);
+            }
+            prev = window.pop_front();
+            if let Some(c) = next {
+                window.push_back(c);
+            }
+            start += 1;
+        }
+        None
+    }
+
+    /// return the char ranges of the non empty matches of the regular expression starting at or after from,
+    /// in order. The text before from is still seen by the anchors and the word boundaries.
+    /// The regex crate needs a contiguous str, so the whole buffer is copied, which is slow on large files
+    pub fn find_regex(&self, re: &Regex, from: usize) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        if from > self.len_chars() {
+            return matches;
+        }
+        let text = self.to_string();
+        let mut at = self.rope.char_to_byte(from);
+        while let Some(m) = re.find_at(&text, at) {
+            if m.start() == m.end() {
+                // skip the empty match, to the next char
+                match text[m.end()..].chars().next() {
+                    Some(c) => at = m.end() + c.len_utf8(),
+                    None => break,
+                }
+                continue;
+            }
+            matches.push(self.rope.byte_to_char(m.start())..self.rope.byte_to_char(m.end()));
+            at = m.end();
+        }
+        matches
+    }
+
+    /// return the char index of the last occurrence of needle ending at or before from
+    pub fn rfind(&self, needle: &str, from: usize) -> Option<usize> {
+        use std::cmp::min;
+        let from = min(from, self.len_chars());
+        if needle.is_empty() {
+            return None;
+        }
+        let needle_len = needle.chars().count();
+
+        // walk the chunks backward, keeping the head of the following chunks in a window
+        let mut window = String::new();
+        let mut window_end = from;
+        loop {
+            let window_start = window_end - window.chars().count();
+            if window_start == 0 {
+                break;
+            }
+            let (chunk, _, chunk_start, _) = self.rope.chunk_at_char(window_start - 1);
+            let chunk_len = min(chunk.chars().count(), window_start - chunk_start);
+            let chunk_end = chunk.char_indices().nth(chunk_len).map(|(b, _)| b).unwrap_or_else(|| chunk.len());
+            window.insert_str(0, &chunk[..chunk_end]);
+            let window_start = window_start - chunk_len;
+            if let Some(b) = window.rfind(needle) {
+                return Some(window_start + window[..b].chars().count());
+            }
+            let window_len = window.chars().count();
+            if window_len >= needle_len {
+                let keep = needle_len - 1;
+                let b = window.char_indices().nth(keep).map(|(b, _)| b).unwrap_or_else(|| window.len());
+                window.truncate(b);
+                window_end = window_start + keep;
+            }
+        }
+        None
+    }
+
+    /// replace every occurrence of needle by replacement, return the number of replacements
+    pub fn replace_all(&mut self, needle: &str, replacement: &str) -> usize {
+        let len = needle.chars().count();
+        let mut matches = Vec::new();
+        let mut from = 0;
+        while let Some(start) = self.find(needle, from) {
+            matches.push(start);
+            from = start + len;
+        }
+        // replace from the end so the positions of the remaining matches stay valid
+        for start in matches.iter().rev() {
+            self.shift_bookmarks_on_remove(&(*start..*start + len));
+            self.rope.remove(*start..*start + len);
+            let len_lines = self.rope.len_lines();
+            self.rope.insert(*start, replacement);
+            self.shift_bookmarks_on_insert(*start, len_lines);
+        }
+        if !matches.is_empty() {
+            self.touch();
+        }
+        matches.len()
+    }
+
+    /// remove the spaces and tabs at the end of every line, the line endings are kept.
+    /// Return the number of lines changed
+    pub fn trim_trailing_whitespace(&mut self) -> usize {
+        let mut changed = 0;
+        // from the last line so the positions of the remaining lines stay valid
+        for line in (0..self.len_lines()).rev() {
+            let start = self.rope.line_to_char(line);
+            let end = start + self.line_len_no_eol(line);
+            let mut trimmed = end;
+            while trimmed > start && (self.rope.char(trimmed - 1) == ' ' || self.rope.char(trimmed - 1) == '\t') {
+                trimmed -= 1;
+            }
+            if trimmed < end {
+                self.rope.remove(trimmed..end);
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            self.touch();
+        }
+        changed
+    }
+
+    /// return the number of chars, lines and words of the buffer.
+    /// A word is a run of alphanumeric (in any script) or underscore chars
+    pub fn stats(&self) -> BufferStats {
+        let mut words = 0;
+        let mut in_word = false;
+        for c in self.rope.chars() {
+            let is_word = c.is_alphanumeric() || c == '_';
+            if is_word && !in_word {
+                words += 1;
+            }
+            in_word = is_word;
+        }
+        BufferStats {
+            chars: self.rope.len_chars(),
+            lines: self.rope.len_lines(),
+            words,
+        }
+    }
+
+    /// append a line ending if the buffer doesn't end with one. An empty buffer is left empty
+    pub fn ensure_trailing_newline(&mut self) {
+        let len = self.rope.len_chars();
+        if len == 0 || self.rope.char(len - 1) == '\n' {
+            return;
+        }
+        self.rope.insert(len, self.line_ending.as_str());
+        self.touch();
+    }
+
     /// return the line of the given char
     pub fn char_to_line(&self, char_idx: usize) -> usize {
         self.rope.char_to_line(char_idx)
@@ -184,6 +889,24 @@ impl Buffer {
         l.chars().filter(|c| *c != '\n' && *c != '\r').count()
     }
 
+    /// iterate over the chars of the given line without its line ending, without copying them.
+    /// A line past the end of the buffer is empty
+    pub fn line_chars(&self, line_idx: usize) -> impl Iterator<Item = char> + '_ {
+        let range = if line_idx < self.len_lines() {
+            let start = self.rope.line_to_char(line_idx);
+            start..start + self.line_len_no_eol(line_idx)
+        } else {
+            0..0
+        };
+        self.slice_chars(range)
+    }
+
+    /// return the content of the given line without its line ending.
+    /// A line past the end of the buffer is empty
+    pub fn line_str(&self, line_idx: usize) -> String {
+        self.line_chars(line_idx).collect()
+    }
+
     /// return the last char of the given line
     pub fn line_to_last_char(&self, line_idx: usize) -> usize {
         self.rope.line_to_char(line_idx) + self.line_len_no_eol(line_idx)
@@ -193,25 +916,54 @@ impl Buffer {
     pub fn line_len(&self, line_idx: usize) -> usize {
         self.rope.line(line_idx).len_chars()
     }
-    // /// convert an index to a point (line, column)
-    // pub fn index_to_point(&self, char_idx: usize) -> (usize, usize) {
-    //     let l = self.char_to_line(char_idx);
-    //     let c = char_idx - self.line_to_char(l);
-    //     (l, c)
-    // }
-    // /// Convert a point (line, column) to an index
-    // pub fn point_to_index(&self, line: usize, col: usize) -> usize {
-    //     use std::cmp::min;
-    //     let l = min(line, self.len_lines() - 1);
-
-    //     let c = min(col, self.line_len_no_eol(l));
-    //     self.line_to_char(l) + c
-    // }
+    /// convert an index to a point (line, column), the index is clamped to the end of the buffer.
+    /// The column is counted in chars, tabs are not expanded
+    pub fn index_to_point(&self, char_idx: usize) -> (usize, usize) {
+        let char_idx = std::cmp::min(char_idx, self.len_chars());
+        let l = self.char_to_line(char_idx);
+        let c = char_idx - self.line_to_char(l);
+        (l, c)
+    }
+    /// Convert a point (line, column) to an index, the line and the column are clamped to the buffer
+    pub fn point_to_index(&self, line: usize, col: usize) -> usize {
+        use std::cmp::min;
+        let l = min(line, self.len_lines() - 1);
+
+        let c = min(col, self.line_len_no_eol(l));
+        self.line_to_char(l) + c
+    }
+    /// Convert a point (line, column) to an index, or None if the line or the column are out of range.
+    /// The column just after the last char of the line is valid
+    pub fn try_point_to_index(&self, line: usize, col: usize) -> Option<usize> {
+        if line >= self.len_lines() || col > self.line_len_no_eol(line) {
+            return None;
+        }
+        Some(self.line_to_char(line) + col)
+    }
 }
 
 #[cfg(test)]
-mod tests {
-    use crate::buffer::Buffer;
+pub(crate) mod tests {
+    use crate::buffer::{char_width, Buffer, BufferStats, LineEnding, SearchOptions};
+    use regex::Regex;
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn temp_file(name: &str) -> PathBuf {
+        let mut p = env::temp_dir();
+        p.push(format!("nonedit_{}_{}", std::process::id(), name));
+        p
+    }
+
+    /// write a file with a modification time of secs after the epoch, the changes are then seen
+    /// whatever the timestamp resolution of the file system
+    pub(crate) fn write_with_mtime(path: &Path, content: &str, secs: u64) {
+        fs::write(path, content).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+    }
 
     #[test]
     fn chars_iterators() {
@@ -239,33 +991,68 @@ mod tests {
         assert_eq!(buf.len_lines(), 2);
     }
     #[test]
+    fn chunks() {
+        let text: String = (0..2000).map(|i| format!("N\u{f6}el {}\n", i)).collect();
+        let buf = Buffer::from_str(&text);
+        assert!(buf.chunks().count() > 1);
+        assert_eq!(buf.chunks().collect::<String>(), buf.to_string());
+        assert_eq!(buf.byte_len(), text.len());
+        assert_eq!(buf.byte_len(), buf.len_chars() + 2000);
+        assert_eq!(Buffer::new().chunks().collect::<String>(), "");
+    }
+    #[test]
+    fn slice_chars() {
+        let text = "Hello N\u{f6}el\r\nWorld";
+        let buf = Buffer::from_str(text);
+        let chars: Vec<char> = text.chars().collect();
+        for r in &[0..0, 0..5, 6..12, 8..14, 0..17] {
+            let expected: String = chars[r.clone()].iter().collect();
+            assert_eq!(buf.slice_chars(r.clone()).collect::<String>(), expected);
+            assert_eq!(buf.slice(r.clone()), expected);
+        }
+        assert_eq!(buf.slice_chars(6..10).count(), 4);
+    }
+    #[test]
     fn remove() {
         let mut buf = Buffer::from_str("Hello World");
         buf.remove(1..3);
         assert_eq!(buf.to_string(), "Hlo World");
     }
-    // #[test]
-    // fn index_to_point() {
-    //     let buf = Buffer::from_str("text\nplops\ntoto  ");
-    //     assert_eq!(buf.index_to_point(3), (0, 3));
-    //     assert_eq!(buf.index_to_point(4), (0, 4));
-    //     assert_eq!(buf.index_to_point(5), (1, 0));
-    //     assert_eq!(buf.index_to_point(12), (2, 1));
-    // }
-    // #[test]
-    // fn point_to_index() {
-    //     let buf = Buffer::from_str("text\nplops\ntoto  ");
-    //     // Normal case
-    //     assert_eq!(buf.point_to_index(0, 3), 3);
-    //     assert_eq!(buf.point_to_index(0, 4), 4);
-    //     assert_eq!(buf.point_to_index(1, 0), 5);
-    //     assert_eq!(buf.point_to_index(2, 1), 12);
-
-    //     // oob case
-    //     assert_eq!(buf.point_to_index(0, 5), 4); // col too far
-    //     assert_eq!(buf.point_to_index(4, 1), 12); // line too far
-    //     assert_eq!(buf.point_to_index(4, 6), 17); // line too far, EOF is treated like a char
-    // }
+    #[test]
+    fn index_to_point() {
+        let buf = Buffer::from_str("text\nplops\ntoto  ");
+        assert_eq!(buf.index_to_point(3), (0, 3));
+        assert_eq!(buf.index_to_point(4), (0, 4));
+        assert_eq!(buf.index_to_point(5), (1, 0));
+        assert_eq!(buf.index_to_point(12), (2, 1));
+        assert_eq!(buf.index_to_point(30), (2, 6));
+    }
+    #[test]
+    fn point_to_index() {
+        let buf = Buffer::from_str("text\nplops\ntoto  ");
+        // Normal case
+        assert_eq!(buf.point_to_index(0, 3), 3);
+        assert_eq!(buf.point_to_index(0, 4), 4);
+        assert_eq!(buf.point_to_index(1, 0), 5);
+        assert_eq!(buf.point_to_index(2, 1), 12);
+
+        // oob case
+        assert_eq!(buf.point_to_index(0, 5), 4); // col too far
+        assert_eq!(buf.point_to_index(4, 1), 12); // line too far
+        assert_eq!(buf.point_to_index(4, 6), 17); // line too far, EOF is treated like a char
+    }
+    #[test]
+    fn try_point_to_index() {
+        let buf = Buffer::from_str("text\r\nplops\ntoto  ");
+        assert_eq!(buf.try_point_to_index(0, 3), Some(3));
+        assert_eq!(buf.try_point_to_index(0, 4), Some(4));
+        assert_eq!(buf.try_point_to_index(1, 0), Some(6));
+        assert_eq!(buf.try_point_to_index(2, 6), Some(18));
+
+        assert_eq!(buf.try_point_to_index(0, 5), None);
+        assert_eq!(buf.try_point_to_index(2, 7), None);
+        assert_eq!(buf.try_point_to_index(3, 0), None);
+    }
     #[test]
     fn line_to_last_char() {
         let buf = Buffer::from_str("text\nplops\ntoto  ");
@@ -280,4 +1067,448 @@ mod tests {
         assert_eq!(buf.line_len_no_eol(1), 5);
         assert_eq!(buf.line_len_no_eol(2), 6);
     }
+    #[test]
+    fn save_as_roundtrip() {
+        let path = temp_file("save_as_roundtrip.txt");
+        let mut buf = Buffer::from_str("Hello\nWorld");
+        buf.insert_char(0, '>');
+        buf.save_as(&path).unwrap();
+        assert!(!buf.is_dirty);
+        let reloaded = Buffer::from_file(&path).unwrap();
+        assert_eq!(reloaded.to_string(), ">Hello\nWorld");
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn save_roundtrip() {
+        let path = temp_file("save_roundtrip.txt");
+        fs::write(&path, "Hello").unwrap();
+        let mut buf = Buffer::from_file(&path).unwrap();
+        buf.insert(5, " World");
+        assert!(buf.is_dirty);
+        buf.save().unwrap();
+        assert!(!buf.is_dirty);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Hello World");
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn reload() {
+        let path = temp_file("reload.txt");
+        fs::write(&path, "Hello").unwrap();
+        let mut buf = Buffer::from_file(&path).unwrap();
+        buf.insert(5, " World");
+        fs::write(&path, "Bye\r\n").unwrap();
+        let revision = buf.revision();
+        buf.reload().unwrap();
+        assert_eq!(buf.to_string(), "Bye\r\n");
+        assert_eq!(buf.line_ending(), LineEnding::CrLf);
+        assert!(!buf.is_dirty());
+        assert_ne!(buf.revision(), revision);
+        fs::remove_file(&path).unwrap();
+        assert!(buf.reload().is_err());
+        assert_eq!(buf.to_string(), "Bye\r\n");
+
+        let err = Buffer::from_str("Hello").reload().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+    #[test]
+    fn external_change() {
+        let path = temp_file("external_change.txt");
+        fs::write(&path, "Hello").unwrap();
+        let mut buf = Buffer::from_file(&path).unwrap();
+        assert!(!buf.external_change_detected().unwrap());
+        assert!(!Buffer::from_str("Hello").external_change_detected().unwrap());
+
+        write_with_mtime(&path, "Hello World", 1);
+        assert!(buf.external_change_detected().unwrap());
+        buf.mark_changed_on_disk();
+        assert!(buf.is_changed_on_disk());
+        buf.reload().unwrap();
+        assert!(!buf.external_change_detected().unwrap());
+        assert!(!buf.is_changed_on_disk());
+
+        write_with_mtime(&path, "Bye", 2);
+        buf.save().unwrap();
+        assert!(!buf.external_change_detected().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(buf.external_change_detected().is_err());
+    }
+    #[test]
+    fn utf16_bom() {
+        let path = temp_file("utf16_bom.txt");
+        let content = b"\xFF\xFEN\x00\xF6\x00e\x00l\x00\n\x00";
+        fs::write(&path, &content[..]).unwrap();
+        let mut buf = Buffer::from_file_with_encoding(&path, encoding::all::ISO_8859_1).unwrap();
+        assert_eq!(buf.to_string(), "N\u{f6}el\n");
+        assert_eq!(buf.get_encoding().name(), "utf-16le");
+        buf.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), &content[..]);
+        assert_eq!(Buffer::from_file(&path).unwrap().to_string(), "N\u{f6}el\n");
+
+        fs::write(&path, b"\xFE\xFF\x00N\x00\xF6").unwrap();
+        let buf = Buffer::from_file_with_encoding(&path, encoding::all::ISO_8859_1).unwrap();
+        assert_eq!(buf.to_string(), "N\u{f6}");
+        assert_eq!(buf.get_encoding().name(), "utf-16be");
+
+        fs::write(&path, b"\xEF\xBB\xBFN\xC3\xB6el").unwrap();
+        let buf = Buffer::from_file_with_encoding(&path, encoding::all::ISO_8859_1).unwrap();
+        assert_eq!(buf.to_string(), "N\u{f6}el");
+        assert_eq!(buf.get_encoding().name(), "utf-8");
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn line_str() {
+        let b = Buffer::from_str("first\r\n\n  third\nlast");
+        assert_eq!(b.line_str(0), "first");
+        assert_eq!(b.line_str(1), "");
+        assert_eq!(b.line_str(2), "  third");
+        assert_eq!(b.line_str(3), "last");
+        assert_eq!(b.line_str(4), "");
+        assert_eq!(b.line_str(100), "");
+        assert_eq!(b.line_chars(2).count(), b.line_len_no_eol(2));
+        assert_eq!(b.line_chars(3).last(), Some('t'));
+
+        let b = Buffer::from_str("a\n");
+        assert_eq!(b.line_str(0), "a");
+        assert_eq!(b.line_str(1), "");
+        assert_eq!(Buffer::new().line_str(0), "");
+    }
+    #[test]
+    fn bookmarks_shift() {
+        let mut b = Buffer::from_str("a\nb\nc\nd\ne\n");
+        b.toggle_bookmark(1);
+        b.toggle_bookmark(3);
+        // on the line, after its start
+        b.insert(b.line_to_char(1) + 1, "x\ny");
+        assert_eq!(b.bookmarks().collect::<Vec<_>>(), vec![1, 4]);
+        // lines above
+        b.insert(0, "1\n2\n");
+        assert_eq!(b.bookmarks().collect::<Vec<_>>(), vec![3, 6]);
+        // at the start of a bookmarked line
+        b.insert_char(b.line_to_char(6), '\n');
+        assert_eq!(b.bookmarks().collect::<Vec<_>>(), vec![3, 7]);
+        // no new line
+        b.insert(0, "abc");
+        assert_eq!(b.bookmarks().collect::<Vec<_>>(), vec![3, 7]);
+
+        // removed lines above
+        b.remove(0..b.line_to_char(2));
+        assert_eq!(b.bookmarks().collect::<Vec<_>>(), vec![1, 5]);
+        // a bookmarked line joined to a line above
+        b.remove(b.line_to_char(3) + 1..b.line_to_char(5) + 1);
+        assert_eq!(b.to_string(), "a\nbx\ny\nc\ne\n");
+        assert_eq!(b.bookmarks().collect::<Vec<_>>(), vec![1, 3]);
+
+        b.toggle_bookmark(1);
+        assert!(!b.is_bookmarked(1));
+        assert!(b.is_bookmarked(3));
+    }
+    #[test]
+    fn bookmark_navigation() {
+        let mut b = Buffer::from_str("a\nb\nc\nd\ne\n");
+        assert_eq!(b.next_bookmark(0), None);
+        b.toggle_bookmark(1);
+        b.toggle_bookmark(3);
+        assert_eq!(b.next_bookmark(0), Some(1));
+        assert_eq!(b.next_bookmark(1), Some(3));
+        assert_eq!(b.next_bookmark(3), Some(1));
+        assert_eq!(b.prev_bookmark(3), Some(1));
+        assert_eq!(b.prev_bookmark(1), Some(3));
+        assert_eq!(b.prev_bookmark(5), Some(3));
+    }
+    #[test]
+    fn wide_chars() {
+        for c in &['a', '~', 'é', 'ß', 'Ω', '→', '\u{10FF}'] {
+            assert_eq!(char_width(*c), 1, "{:?}", c);
+        }
+        for c in &['中', 'あ', 'カ', '한', 'Ｆ', '😀', '\u{20000}'] {
+            assert_eq!(char_width(*c), 2, "{:?}", c);
+        }
+    }
+    #[test]
+    fn binary_file() {
+        let path = temp_file("binary_file.bin");
+        fs::write(&path, b"\x7FELF\x02\x01\x01\x00\x00").unwrap();
+        let err = Buffer::from_file(&path).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn utf16_without_bom() {
+        let path = temp_file("utf16_without_bom.txt");
+        let content = b"N\x00\xF6\x00e\x00l\x00\n\x00";
+        fs::write(&path, &content[..]).unwrap();
+        let mut buf = Buffer::from_file(&path).unwrap();
+        assert_eq!(buf.to_string(), "N\u{f6}el\n");
+        assert_eq!(buf.get_encoding().name(), "utf-16le");
+        // no byte order mark is added
+        buf.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), &content[..]);
+
+        fs::write(&path, b"\x00N\x00\xF6\x00e\x00l").unwrap();
+        let buf = Buffer::from_file(&path).unwrap();
+        assert_eq!(buf.to_string(), "N\u{f6}el");
+        assert_eq!(buf.get_encoding().name(), "utf-16be");
+
+        // nulls at both offsets
+        fs::write(&path, b"\x00\x00\x01\x00").unwrap();
+        assert_eq!(Buffer::from_file(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn latin1() {
+        let path = temp_file("latin1.txt");
+        fs::write(&path, b"N\xF6el").unwrap();
+        let mut buf = Buffer::from_file_with_encoding(&path, encoding::all::ISO_8859_1).unwrap();
+        assert_eq!(buf.to_string(), "N\u{f6}el");
+        buf.insert(4, "!");
+        buf.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"N\xF6el!");
+        // reloaded with the same encoding
+        fs::write(&path, b"N\xF6el \xE9t\xE9").unwrap();
+        buf.reload().unwrap();
+        assert_eq!(buf.to_string(), "N\u{f6}el \u{e9}t\u{e9}");
+        assert_eq!(buf.get_encoding().name(), "iso-8859-1");
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn from_file_or_new() {
+        let path = temp_file("from_file_or_new.txt");
+        let mut buf = Buffer::from_file_or_new(&path).unwrap();
+        assert_eq!(buf.len_chars(), 0);
+        assert_eq!(buf.get_filename(), Some(path.as_path()));
+        assert!(!buf.is_dirty());
+        buf.insert(0, "Hello");
+        buf.save().unwrap();
+
+        let buf = Buffer::from_file_or_new(&path).unwrap();
+        assert_eq!(buf.to_string(), "Hello");
+        fs::remove_file(&path).unwrap();
+
+        let mut dir = env::temp_dir();
+        dir.push(format!("nonedit_{}_missing_dir", std::process::id()));
+        dir.push("file.txt");
+        assert!(Buffer::from_file_or_new(&dir).is_ok());
+        assert!(Buffer::from_file_or_new(&env::temp_dir()).is_err());
+    }
+    #[test]
+    fn save_without_filename() {
+        let mut buf = Buffer::from_str("Hello");
+        let err = buf.save().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+    #[test]
+    fn revision() {
+        let mut buf = Buffer::from_str("abc");
+        let r0 = buf.revision();
+        assert_ne!(r0, Buffer::from_str("abc").revision());
+        buf.insert_char(0, 'x');
+        let r1 = buf.revision();
+        assert_ne!(r0, r1);
+        let copy = buf.clone();
+        buf.remove(0..1);
+        assert_ne!(r1, buf.revision());
+        assert_eq!(copy.revision(), r1);
+        assert_eq!(buf.replace_all("z", "y"), 0);
+        assert_ne!(r0, buf.revision());
+    }
+    #[test]
+    fn is_dirty() {
+        let mut buf = Buffer::from_str("Hello");
+        assert!(!buf.is_dirty());
+        buf.insert_char(0, 'a');
+        assert!(buf.is_dirty());
+        buf.mark_clean();
+        assert!(!buf.is_dirty());
+    }
+    #[test]
+    fn transaction() {
+        let mut buf = Buffer::from_str("Hello");
+        let revision = buf.revision();
+        buf.begin_edit();
+        buf.insert(5, " World");
+        buf.edit(|b| b.remove(0..1));
+        assert!(!buf.is_dirty());
+        assert_eq!(buf.revision(), revision);
+        buf.end_edit();
+        assert!(buf.is_dirty());
+        assert_ne!(buf.revision(), revision);
+        assert_eq!(buf.to_string(), "ello World");
+
+        let mut buf = Buffer::from_str("Hello");
+        let revision = buf.revision();
+        let len = buf.edit(|b| b.len_chars());
+        assert_eq!(len, 5);
+        assert!(!buf.is_dirty());
+        assert_eq!(buf.revision(), revision);
+        buf.insert(0, ">");
+        assert!(buf.is_dirty());
+    }
+    #[test]
+    fn empty_edit() {
+        let mut buf = Buffer::from_str("Hello");
+        let revision = buf.revision();
+        buf.insert(2, "");
+        assert!(!buf.is_dirty());
+        buf.remove(2..2);
+        assert!(!buf.is_dirty());
+        assert_eq!(buf.revision(), revision);
+        assert_eq!(buf.to_string(), "Hello");
+        buf.remove(2..3);
+        assert!(buf.is_dirty());
+        assert_eq!(buf.to_string(), "Helo");
+    }
+    #[test]
+    fn detect_line_ending() {
+        let path = temp_file("detect_line_ending_crlf.txt");
+        fs::write(&path, "Hello\r\nWorld\r\n").unwrap();
+        assert_eq!(Buffer::from_file(&path).unwrap().line_ending(), LineEnding::CrLf);
+        fs::write(&path, "Hello\nWorld\n").unwrap();
+        assert_eq!(Buffer::from_file(&path).unwrap().line_ending(), LineEnding::Lf);
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn save_normalize_line_ending() {
+        let path = temp_file("save_normalize_line_ending.txt");
+        fs::write(&path, "a\r\nb\r\nc").unwrap();
+        let mut buf = Buffer::from_file(&path).unwrap();
+        buf.insert(1, "\n");
+        buf.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\r\n\r\nb\r\nc");
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn find() {
+        let buf = Buffer::from_str("Hello Hello World");
+        assert_eq!(buf.find("Hello", 0), Some(0));
+        assert_eq!(buf.find("Hello", 1), Some(6));
+        assert_eq!(buf.find("Hello", 7), None);
+        assert_eq!(buf.find("", 0), None);
+        let buf = Buffer::from_str("Nöel Nöel");
+        assert_eq!(buf.find("el", 3), Some(7));
+    }
+    #[test]
+    fn rfind() {
+        let buf = Buffer::from_str("Hello Hello World");
+        assert_eq!(buf.rfind("Hello", 17), Some(6));
+        assert_eq!(buf.rfind("Hello", 10), Some(0));
+        assert_eq!(buf.rfind("Hello", 4), None);
+        assert_eq!(buf.rfind("", 17), None);
+        let buf = Buffer::from_str("Nöel Nöel");
+        assert_eq!(buf.rfind("Nö", 9), Some(5));
+    }
+    #[test]
+    fn find_across_chunks() {
+        let text = format!("{}needle{}needle", "a".repeat(5000), "b".repeat(5000));
+        let buf = Buffer::from_str(&text);
+        assert_eq!(buf.find("needle", 0), Some(5000));
+        assert_eq!(buf.find("needle", 5001), Some(10006));
+        assert_eq!(buf.rfind("needle", buf.len_chars()), Some(10006));
+        assert_eq!(buf.rfind("needle", 10006), Some(5000));
+    }
+    #[test]
+    fn find_regex() {
+        let buf = Buffer::from_str("Nöel 2018, nöel 24\nno digits\n7");
+        let re = |pattern| Regex::new(pattern).unwrap();
+        assert_eq!(buf.find_regex(&re(r"\d+"), 0), vec![5..9, 16..18, 29..30]);
+        assert_eq!(buf.find_regex(&re(r"\d+"), 7), vec![7..9, 16..18, 29..30]);
+        assert_eq!(buf.find_regex(&re(r"\d+"), 9), vec![16..18, 29..30]);
+        assert!(buf.find_regex(&re(r"^\d+$"), 0).is_empty());
+        assert_eq!(buf.find_regex(&re(r"(?m)^\d+$"), 0), vec![29..30]);
+        assert!(buf.find_regex(&re(r"\d+"), 30).is_empty());
+        assert!(buf.find_regex(&re(r"\d+"), 31).is_empty());
+        // the text before from is seen by the word boundaries
+        assert_eq!(buf.find_regex(&re(r"\b\d"), 6), vec![16..17, 29..30]);
+        // the empty matches are skipped
+        assert!(buf.find_regex(&re(r"x*"), 0).is_empty());
+        assert_eq!(buf.find_regex(&re(r"ö*e"), 0), vec![1..3, 12..14]);
+    }
+    #[test]
+    fn find_with() {
+        let buf = Buffer::from_str("catalog cat Nöel NÖEL");
+        let whole_word = SearchOptions {
+            case_sensitive: true,
+            whole_word: true,
+        };
+        assert_eq!(buf.find_with("cat", 0, whole_word), Some(8));
+        assert_eq!(buf.find_with("cat", 9, whole_word), None);
+        let case_insensitive = SearchOptions {
+            case_sensitive: false,
+            whole_word: false,
+        };
+        assert_eq!(buf.find_with("CAT", 1, case_insensitive), Some(8));
+        assert_eq!(buf.find_with("nÖel", 0, case_insensitive), Some(12));
+        assert_eq!(buf.find_with("nöel", 13, case_insensitive), Some(17));
+    }
+    #[test]
+    fn trim_trailing_whitespace() {
+        let mut buf = Buffer::from_str("a  \n\tb \t\r\n  \nc\n d \t");
+        assert_eq!(buf.trim_trailing_whitespace(), 4);
+        assert_eq!(buf.to_string(), "a\n\tb\r\n\nc\n d");
+        assert!(buf.is_dirty());
+        assert_eq!(buf.trim_trailing_whitespace(), 0);
+
+        let mut buf = Buffer::from_str("a\r\nb\n");
+        assert_eq!(buf.trim_trailing_whitespace(), 0);
+        assert_eq!(buf.to_string(), "a\r\nb\n");
+        assert!(!buf.is_dirty());
+    }
+    #[test]
+    fn stats() {
+        let buf = Buffer::from_str("Hello  wor_ld, N\u{f6}el \n\n  x2 y-z\t\r\n  ");
+        assert_eq!(
+            buf.stats(),
+            BufferStats {
+                chars: 35,
+                lines: 4,
+                words: 6
+            }
+        );
+        assert_eq!(
+            Buffer::new().stats(),
+            BufferStats {
+                chars: 0,
+                lines: 1,
+                words: 0
+            }
+        );
+    }
+    #[test]
+    fn ensure_trailing_newline() {
+        let mut buf = Buffer::from_str("a\nb");
+        buf.ensure_trailing_newline();
+        assert_eq!(buf.to_string(), "a\nb\n");
+        assert!(buf.is_dirty());
+
+        let mut buf = Buffer::from_str("a\r\nb");
+        buf.ensure_trailing_newline();
+        assert_eq!(buf.to_string(), "a\r\nb\r\n");
+
+        let mut buf = Buffer::from_str("a\nb\n");
+        buf.ensure_trailing_newline();
+        assert_eq!(buf.to_string(), "a\nb\n");
+        assert!(!buf.is_dirty());
+
+        let mut buf = Buffer::from_str("a\nb\n\n");
+        buf.ensure_trailing_newline();
+        assert_eq!(buf.to_string(), "a\nb\n\n");
+        assert!(!buf.is_dirty());
+
+        let mut buf = Buffer::new();
+        buf.ensure_trailing_newline();
+        assert_eq!(buf.len_chars(), 0);
+        assert!(!buf.is_dirty());
+    }
+    #[test]
+    fn replace_all() {
+        let mut buf = Buffer::from_str("aaa");
+        assert_eq!(buf.replace_all("a", "bb"), 3);
+        assert_eq!(buf.to_string(), "bbbbbb");
+        assert!(buf.is_dirty());
+        let mut buf = Buffer::from_str("Nöel Nöel");
+        assert_eq!(buf.replace_all("ö", "o"), 2);
+        assert_eq!(buf.to_string(), "Noel Noel");
+        let mut buf = Buffer::from_str("Hello");
+        assert_eq!(buf.replace_all("x", "y"), 0);
+        assert!(!buf.is_dirty());
+    }
 }
@@ -1,7 +1,7 @@
 use std::sync::Mutex;
 use lazy_static::lazy_static;
 use clipboard2::*;
-use crate::keybinding::KeyBinding;
+use crate::keybinding::KeySequence;
 use crate::view::{Direction, View, ViewCmd};
 use crate::window::EditorWindow;
 use crate::window::WindowCmd;
@@ -10,23 +10,25 @@ use crate::window::WindowCmd;
 struct GenericViewCommand {
     name: &'static str,
     desc: &'static str,
-    keybinding: Vec<KeyBinding>,
+    keybinding: Vec<KeySequence>,
     execute: fn(&mut View<'_>),
+    interactive: bool,
 }
 
 impl GenericViewCommand {
-    pub fn new(name: &'static str, desc: &'static str, keybinding: Vec<KeyBinding>, execute: fn(&mut View<'_>)) -> Self {
+    pub fn new(name: &'static str, desc: &'static str, keybinding: Vec<KeySequence>, execute: fn(&mut View<'_>)) -> Self {
         GenericViewCommand {
             name,
             desc,
             keybinding,
             execute,
+            interactive: false,
         }
     }
     pub fn new_box<K>(name: &'static str, desc: &'static str, keybinding: &[K], execute: fn(&mut View<'_>)) -> Box<Self>
     where
         K: Clone,
-        KeyBinding: From<K>,
+        KeySequence: From<K>,
     {
         Box::new(GenericViewCommand::new(
             name,
@@ -35,6 +37,11 @@ impl GenericViewCommand {
             execute,
         ))
     }
+    /// mark the command as waiting for an input, see is_interactive
+    pub fn interactive(mut self: Box<Self>) -> Box<Self> {
+        self.interactive = true;
+        self
+    }
 }
 
 impl ViewCmd for GenericViewCommand {
@@ -44,9 +51,12 @@ impl ViewCmd for GenericViewCommand {
     fn desc(&self) -> &'static str {
         self.desc
     }
-    fn keybinding(&self) -> Vec<KeyBinding> {
+    fn keybinding(&self) -> Vec<KeySequence> {
         self.keybinding.clone()
     }
+    fn is_interactive(&self) -> bool {
+        self.interactive
+    }
     fn run(&mut self, view: &mut View<'_>) {
         (self.execute)(view);
     }
@@ -55,15 +65,16 @@ impl ViewCmd for GenericViewCommand {
 struct GenericWindowCommand {
     name: &'static str,
     desc: &'static str,
-    keybinding: Vec<KeyBinding>,
+    keybinding: Vec<KeySequence>,
     execute: fn(&mut EditorWindow<'_>),
+    interactive: bool,
 }
 
 impl GenericWindowCommand {
     pub fn new(
         name: &'static str,
         desc: &'static str,
-        keybinding: Vec<KeyBinding>,
+        keybinding: Vec<KeySequence>,
         execute: fn(&mut EditorWindow<'_>),
     ) -> Self {
         GenericWindowCommand {
@@ -71,6 +82,7 @@ impl GenericWindowCommand {
             desc,
             keybinding,
             execute,
+            interactive: false,
         }
     }
     pub fn new_box<K>(
@@ -81,7 +93,7 @@ impl GenericWindowCommand {
     ) -> Box<Self>
     where
         K: Clone,
-        KeyBinding: From<K>,
+        KeySequence: From<K>,
     {
         Box::new(GenericWindowCommand::new(
             name,
@@ -90,6 +102,11 @@ impl GenericWindowCommand {
             execute,
         ))
     }
+    /// mark the command as waiting for an input, see is_interactive
+    pub fn interactive(mut self: Box<Self>) -> Box<Self> {
+        self.interactive = true;
+        self
+    }
 }
 
 impl WindowCmd for GenericWindowCommand {
@@ -99,9 +116,12 @@ impl WindowCmd for GenericWindowCommand {
     fn desc(&self) -> &'static str {
         self.desc
     }
-    fn keybinding(&self) -> Vec<KeyBinding> {
+    fn keybinding(&self) -> Vec<KeySequence> {
         self.keybinding.clone()
     }
+    fn is_interactive(&self) -> bool {
+        self.interactive
+    }
     fn run(&mut self, window: &mut EditorWindow<'_>) {
         (self.execute)(window);
     }
@@ -114,40 +134,9 @@ lazy_static! {
 pub mod view {
     use crate::commands::*;
     use crate::view::ViewCmd;
-    use crate::SETTINGS;
 
     pub fn get_all() -> Vec<Box<dyn ViewCmd>> {
         let mut v = Vec::<Box<dyn ViewCmd>>::new();
-        v.push(GenericViewCommand::new_box(
-            "Cut",
-            "Cut the current selection to clipboard",
-            &["Ctrl-X"],
-            |v| {
-                if let Some(s) = v.get_selection() {
-                    CLIPBOARD.lock().unwrap().set_string_contents(s).unwrap();
-                    v.delete_at_cursor();
-                }
-            },
-        ));
-        v.push(GenericViewCommand::new_box(
-            "Copy",
-            "Copy the current selection to clipboard",
-            &["Ctrl-C"],
-            |v| {
-                if let Some(s) = v.get_selection() {
-                    CLIPBOARD.lock().unwrap().set_string_contents(s).unwrap();
-                }
-            },
-        ));
-        v.push(GenericViewCommand::new_box(
-            "Paste",
-            "Paste the content of clipboard",
-            &["Ctrl-V"],
-            |v| {
-                let s = CLIPBOARD.lock().unwrap().get_string_contents().unwrap();
-                v.insert(&s);
-            },
-        ));
         v.push(GenericViewCommand::new_box(
             "End",
             "Go to the end of the line",
@@ -158,7 +147,7 @@ pub mod view {
             "EndSel",
             "Go to the end of the line expanding the selection",
             &["Shift-End"],
-            |v| v.end(true),
+            |v| v.select_end(),
         ));
         v.push(GenericViewCommand::new_box(
             "Home",
@@ -170,7 +159,19 @@ pub mod view {
             "HomeSel",
             "Go to the beginning of the line expanding the selection",
             &["Shift-Home"],
-            |v| v.home(true),
+            |v| v.select_home(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "DocumentStartSel",
+            "Go to the beginning of the buffer expanding the selection",
+            &["Ctrl-Shift-Home"],
+            |v| v.select_document_start(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "DocumentEndSel",
+            "Go to the end of the buffer expanding the selection",
+            &["Ctrl-Shift-End"],
+            |v| v.select_document_end(),
         ));
         v.push(GenericViewCommand::new_box(
             "Undo",
@@ -188,21 +189,26 @@ pub mod view {
             "Enter",
             "Insert the return char",
             &["NumpadEnter", "Return"],
-            |v| v.insert_linefeed(),
+            |v| v.insert_newline(),
         ));
-        v.push(GenericViewCommand::new_box("Tab", "Add a tabulation", &["Tab"], |v| {
-            if SETTINGS.read().unwrap().get("indentWithSpace").unwrap() {
-                let n = SETTINGS.read().unwrap().get::<usize>("tabSize").unwrap();
-                let p = v.col_idx();
-                let cible = ((p + n) / n) * n;
-
-                for _ in 0..cible - p {
-                    v.insert_char(' ');
+        v.push(GenericViewCommand::new_box(
+            "Tab",
+            "Add a tabulation, or indent the selected lines",
+            &["Tab"],
+            |v| {
+                if v.has_selection() {
+                    v.indent_selection()
+                } else {
+                    v.insert_tab()
                 }
-            } else {
-                v.insert_char('\t');
-            }
-        }));
+            },
+        ));
+        v.push(GenericViewCommand::new_box(
+            "Dedent",
+            "Remove one level of indentation from the selected lines",
+            &["Shift-Tab"],
+            |v| v.dedent_selection(),
+        ));
         v.push(GenericViewCommand::new_box(
             "Backspace",
             "delete the char at left  or the selection",
@@ -215,6 +221,156 @@ pub mod view {
             &["Delete"],
             |v| v.delete_at_cursor(),
         ));
+        v.push(GenericViewCommand::new_box(
+            "DeleteWordBackward",
+            "delete the word at left or the selection",
+            &["Ctrl-Back"],
+            |v| v.delete_word_backward(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "DeleteWordForward",
+            "delete the word under the cursor or the selection",
+            &["Ctrl-Delete"],
+            |v| v.delete_word_forward(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "JoinLines",
+            "Join the current line with the next one, or the selected lines",
+            &["Ctrl-J"],
+            |v| v.join_lines(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "DeleteToLineEnd",
+            "delete to the end of the line, or the line feed at the end of the line",
+            &["Ctrl-K"],
+            |v| v.delete_to_line_end(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "DeleteToLineStart",
+            "delete from the start of the line to the cursor",
+            &["Ctrl-Shift-Back"],
+            |v| v.delete_to_line_start(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "DeleteLine",
+            "delete the line under the cursor",
+            &["Ctrl-Shift-K"],
+            |v| v.delete_line(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "Duplicate",
+            "duplicate the selection or the line under the cursor",
+            &["Ctrl-D"],
+            |v| v.duplicate(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "MoveLineUp",
+            "swap the line under the cursor with the previous one",
+            &["Alt-Up"],
+            |v| v.move_line_up(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "MoveLineDown",
+            "swap the line under the cursor with the next one",
+            &["Alt-Down"],
+            |v| v.move_line_down(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "CenterCursor",
+            "Scroll to put the cursor line in the middle of the page",
+            &["Ctrl-L"],
+            |v| v.center_cursor(),
+        ));
+        v.push(Box::new(GenericViewCommand::new(
+            "CursorToTop",
+            "Scroll to put the cursor line at the top of the page",
+            Vec::new(),
+            |v| v.cursor_to_top(),
+        )));
+        v.push(Box::new(GenericViewCommand::new(
+            "CursorToBottom",
+            "Scroll to put the cursor line at the bottom of the page",
+            Vec::new(),
+            |v| v.cursor_to_bottom(),
+        )));
+        v.push(GenericViewCommand::new_box(
+            "ToggleOvertype",
+            "Switch between the insert and the overtype mode",
+            &["Insert"],
+            |v| v.toggle_overtype(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "UpperCase",
+            "Convert the selection to upper case",
+            &["Ctrl-U"],
+            |v| v.uppercase_selection(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "LowerCase",
+            "Convert the selection to lower case",
+            &["Ctrl-Shift-U"],
+            |v| v.lowercase_selection(),
+        ));
+        v.push(Box::new(GenericViewCommand::new(
+            "TitleCase",
+            "Convert the first letter of each selected word to upper case",
+            Vec::new(),
+            |v| v.titlecase_selection(),
+        )));
+        v.push(GenericViewCommand::new_box(
+            "TransposeChars",
+            "Swap the chars around the cursor",
+            &["Ctrl-T"],
+            |v| v.transpose_chars(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "ToggleComment",
+            "Comment or uncomment the selected lines",
+            &["Ctrl-Slash"],
+            |v| v.toggle_comment(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "ToggleBookmark",
+            "Add or remove a bookmark on the current line",
+            &["Ctrl-F2"],
+            |v| v.toggle_bookmark(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "NextBookmark",
+            "Move cursor to the next bookmarked line",
+            &["F2"],
+            |v| v.goto_next_bookmark(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "PrevBookmark",
+            "Move cursor to the previous bookmarked line",
+            &["Shift-F2"],
+            |v| v.goto_prev_bookmark(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "JumpBack",
+            "Move cursor back to where it was before the last jump",
+            &["Alt-Left"],
+            |v| v.jump_back(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "JumpForward",
+            "Move cursor forward in the jump list",
+            &["Alt-Right"],
+            |v| v.jump_forward(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "ParagraphUp",
+            "Move cursor to the previous blank line",
+            &["Ctrl-Up"],
+            |v| v.move_paragraph_up(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "ParagraphDown",
+            "Move cursor to the next blank line",
+            &["Ctrl-Down"],
+            |v| v.move_paragraph_down(),
+        ));
         v.push(GenericViewCommand::new_box(
             "Up",
             "Move cursor up",
@@ -265,6 +421,55 @@ pub mod view {
             |v| v.move_cursor(Direction::Right, true),
         ));
 
+        v.push(GenericViewCommand::new_box(
+            "WordLeft",
+            "Move cursor to the previous word",
+            &["Ctrl-Left"],
+            |v| v.move_word_left(false),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "WordRight",
+            "Move cursor to the next word",
+            &["Ctrl-Right"],
+            |v| v.move_word_right(false),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "WordLeftSel",
+            "Move cursor to the previous word expanding selection",
+            &["Ctrl-Shift-Left"],
+            |v| v.move_word_left(true),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "BlockSelectUp",
+            "Expand the block selection up",
+            &["Ctrl-Alt-Up"],
+            |v| v.move_block_selection(Direction::Up),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "BlockSelectDown",
+            "Expand the block selection down",
+            &["Ctrl-Alt-Down"],
+            |v| v.move_block_selection(Direction::Down),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "BlockSelectLeft",
+            "Expand the block selection left",
+            &["Ctrl-Alt-Left"],
+            |v| v.move_block_selection(Direction::Left),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "BlockSelectRight",
+            "Expand the block selection right",
+            &["Ctrl-Alt-Right"],
+            |v| v.move_block_selection(Direction::Right),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "WordRightSel",
+            "Move cursor to the next word expanding selection",
+            &["Ctrl-Shift-Right"],
+            |v| v.move_word_right(true),
+        ));
+
         v.push(GenericViewCommand::new_box(
             "PageUp",
             "Move page up",
@@ -290,9 +495,90 @@ pub mod view {
             &["Shift-PageDown"],
             |v| v.move_page(Direction::Down, true),
         ));
-        v.push(GenericViewCommand::new_box("Save", "Save file", &["Ctrl-S"], |v| {
-            v.save();
-        }));
+        v.push(GenericViewCommand::new_box(
+            "MatchingBracket",
+            "Go to the bracket matching the one under the cursor",
+            &["Ctrl-M"],
+            |v| v.goto_matching_bracket(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "ReverseLines",
+            "Reverse the order of the selected lines",
+            &[] as &[&str],
+            |v| v.reverse_lines(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "DedupeLines",
+            "Remove the lines repeating the line before them, in the selection or the whole buffer",
+            &[] as &[&str],
+            |v| v.dedupe_lines(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "DedupeAllLines",
+            "Remove the lines already seen above, in the selection or the whole buffer",
+            &[] as &[&str],
+            |v| v.dedupe_all_lines(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "ExpandSelectionToWords",
+            "Extend the selection to whole words",
+            &["Ctrl-Shift-W"],
+            |v| v.expand_selection_to_words(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "SelectAll",
+            "Select the whole buffer",
+            &["Ctrl-A"],
+            |v| v.select_all(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "Find",
+            "Search the selection or the word under the cursor",
+            &["Ctrl-F"],
+            |v| {
+                if v.get_selection().is_none() {
+                    v.select_word_under_cursor();
+                }
+                if let Some(s) = v.get_selection() {
+                    v.search(&s);
+                }
+            },
+        ));
+        v.push(GenericViewCommand::new_box(
+            "FindNext",
+            "Go to the next search match",
+            &["F3"],
+            |v| v.search_next(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "FindPrev",
+            "Go to the previous search match",
+            &["Shift-F3"],
+            |v| v.search_prev(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "ClearSearch",
+            "Clear the search highlights",
+            &[] as &[&str],
+            |v| v.clear_search(),
+        ));
+        v.push(GenericViewCommand::new_box(
+            "Reload",
+            "Read the file again, discarding the unsaved changes",
+            &[] as &[&str],
+            |v| {
+                if let Err(e) = v.reload() {
+                    println!("Unable to reload: {}", e);
+                }
+            },
+        ));
+        // an untitled buffer is saved through a dialog
+        v.push(
+            GenericViewCommand::new_box("Save", "Save file", &["Ctrl-S"], |v| {
+                v.save();
+            })
+            .interactive(),
+        );
         v
     }
 }
@@ -300,20 +586,220 @@ pub mod view {
 pub mod window {
     use crate::commands::*;
     use nfd;
-    use crate::window::WindowCmd;
+    use crate::window::{SplitDirection, WindowCmd};
 
     pub fn get_all() -> Vec<Box<dyn WindowCmd>> {
         let mut v = Vec::<Box<dyn WindowCmd>>::new();
         v.push(GenericWindowCommand::new_box(
-            "Open",
-            "Open an existing file",
-            &["Ctrl-O"],
-            |w| {
+            "Cut",
+            "Cut the current selection to clipboard",
+            &["Ctrl-X"],
+            |w| w.cut(),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "Copy",
+            "Copy the current selection to clipboard",
+            &["Ctrl-C"],
+            |w| w.copy(),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "Paste",
+            "Paste the content of clipboard",
+            &["Ctrl-V"],
+            |w| w.paste(),
+        ));
+        v.push(
+            GenericWindowCommand::new_box("Open", "Open an existing file", &["Ctrl-O"], |w| {
                 if let Ok(nfd::Response::Okay(file)) = nfd::open_file_dialog(None, None) {
-                    w.add_new_view(Some(file))
+                    if let Err(e) = w.open_file(&file) {
+                        println!("Unable to open {}: {}", file, e);
+                    }
                 }
+            })
+            .interactive(),
+        );
+        v.push(GenericWindowCommand::new_box(
+            "NextBuffer",
+            "Show the next buffer",
+            &["Ctrl-Tab"],
+            |w| w.cycle_buffer(true),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "PrevBuffer",
+            "Show the previous buffer",
+            &["Ctrl-Shift-Tab"],
+            |w| w.cycle_buffer(false),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "SplitHorizontal",
+            "Split the current view, stacking the views",
+            &["Ctrl-Alt-S"],
+            |w| w.split(SplitDirection::Horizontal),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "SplitVertical",
+            "Split the current view, side by side",
+            &["Ctrl-Alt-V"],
+            |w| w.split(SplitDirection::Vertical),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "NextSplit",
+            "Give the focus to the next split",
+            &["Ctrl-Alt-W"],
+            |w| w.focus_next_split(),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "Cancel",
+            "Leave the insert mode, or clear the selection or the search highlights",
+            &["Escape"],
+            |w| {
+                w.cancel();
+            },
+        ));
+        v.push(
+            GenericWindowCommand::new_box(
+                "Quit",
+                "Quit the editor, asking to save the modified buffers",
+                &["Ctrl-Q"],
+                |w| w.request_quit(),
+            )
+            .interactive(),
+        );
+        v.push(GenericWindowCommand::new_box(
+            "CloseSplit",
+            "Close the current split",
+            &["Ctrl-Alt-Q"],
+            |w| w.close_split(),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "ToggleLineNumbers",
+            "Show or hide the line numbers",
+            &["Ctrl-Shift-L"],
+            |w| w.toggle_line_numbers(),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "ToggleWhitespace",
+            "Show or hide the spaces, tabs and line endings",
+            &["Ctrl-Shift-Key8"],
+            |w| w.toggle_whitespace(),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "ToggleIndentGuides",
+            "Show or hide the indentation guides",
+            &[] as &[&str],
+            |w| w.toggle_indent_guides(),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "ToggleCurrentLineHighlight",
+            "Highlight the line of the cursor or stop highlighting it",
+            &[] as &[&str],
+            |w| w.toggle_current_line_highlight(),
+        ));
+        v.push(
+            GenericWindowCommand::new_box(
+                "CommandPalette",
+                "Search a command by its name or description",
+                &["Ctrl-Shift-P"],
+                |w| w.open_palette(),
+            )
+            .interactive(),
+        );
+        v.push(Box::new(GenericWindowCommand::new(
+            "ToggleModalEditing",
+            "Switch between the modal (vim like) and the normal editing",
+            Vec::new(),
+            |w| {
+                let modal = !w.is_modal_editing();
+                w.set_modal_editing(modal)
             },
+        )));
+        v.push(GenericWindowCommand::new_box(
+            "ToggleMacroRecording",
+            "Start or stop recording a macro",
+            &["Ctrl-Shift-R"],
+            |w| w.toggle_macro_recording(),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "PlayMacro",
+            "Play the last recorded macro",
+            &["Ctrl-Shift-Y"],
+            |w| w.play_macro(),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "ZoomIn",
+            "Increase the font size",
+            &["Ctrl-Equals", "Ctrl-Add"],
+            |w| w.zoom(1.0),
         ));
+        v.push(GenericWindowCommand::new_box(
+            "ZoomOut",
+            "Decrease the font size",
+            &["Ctrl-Minus", "Ctrl-Subtract"],
+            |w| w.zoom(-1.0),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "ResetZoom",
+            "Go back to the default font size",
+            &["Ctrl-Key0"],
+            |w| w.reset_zoom(),
+        ));
+        v.push(GenericWindowCommand::new_box(
+            "DocumentStats",
+            "Show the number of chars, lines and words of the document",
+            &["Ctrl-Shift-C"],
+            |w| w.show_stats(),
+        ));
+        v.push(
+            GenericWindowCommand::new_box(
+                "IncrementalSearch",
+                "Search the text as it is typed",
+                &["Ctrl-I"],
+                |w| w.open_incremental_search(),
+            )
+            .interactive(),
+        );
+        v.push(
+            GenericWindowCommand::new_box("GotoLine", "Go to the given line", &["Ctrl-G"], |w| {
+                w.open_prompt(
+                    "Go to line",
+                    |c| c.is_ascii_digit(),
+                    |w, input| {
+                        if let Ok(line) = input.parse::<usize>() {
+                            // a line past the end is reported, not clamped
+                            let b = w.get_current_view().get_buffer();
+                            let valid = b.borrow().try_point_to_index(line.saturating_sub(1), 0).is_some();
+                            if valid {
+                                w.get_current_view_mut().goto_line(line.saturating_sub(1));
+                            } else {
+                                w.show_message(format!("No line {}", line));
+                            }
+                        }
+                    },
+                )
+            })
+            .interactive(),
+        );
         v
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    #[test]
+    fn unique_names() {
+        let view_cmd = super::view::get_all();
+        let win_cmd = super::window::get_all();
+        let mut names = HashSet::new();
+        for (name, desc) in view_cmd
+            .iter()
+            .map(|c| (c.name(), c.desc()))
+            .chain(win_cmd.iter().map(|c| (c.name(), c.desc())))
+        {
+            assert!(!name.is_empty());
+            assert!(!desc.is_empty(), "{} has no description", name);
+            assert!(names.insert(name), "{} is registered twice", name);
+        }
+    }
+}
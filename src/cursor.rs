@@ -1,4 +1,4 @@
-use crate::buffer::Buffer;
+use crate::buffer::{char_width, is_word_char, Buffer};
 use crate::SETTINGS;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -16,26 +16,33 @@ pub struct Index {
     pub buffer: Rc<RefCell<Buffer>>,
 }
 
+/// number of columns from col to the next tab stop
+pub fn spaces_to_next_tab_stop(col: usize, tab_width: usize) -> usize {
+    tab_width - col % tab_width
+}
+
 impl Into<Index> for Point {
     fn into(self) -> Index {
-        let tabsize: u32 = SETTINGS.read().unwrap().get("tabSize").unwrap();
+        let tabsize: usize = SETTINGS.read().unwrap().get("tabSize").unwrap();
         let index = self.buffer.borrow().line_to_char(self.line);
         let mut col_idx = 0;
-        let mut col: u32 = 0;
+        let mut col = 0;
         for c in self.buffer.borrow().chars_on_line(self.line).take(self.col) {
             match c {
                 '\t' => {
-                    col = ((col + tabsize) / tabsize) * tabsize;
+                    col += spaces_to_next_tab_stop(col, tabsize);
                 }
-                '\r' | '\n' | '\0' => (),
+                // past the end of the line
+                '\r' | '\n' => break,
+                '\0' => (),
                 // Bom hiding. TODO: rework
                 '\u{feff}' | '\u{fffe}' => (),
                 _ => {
-                    col += 1;
+                    col += char_width(c);
                 }
             }
             col_idx += 1;
-            if col as usize == self.col {
+            if col >= self.col {
                 break;
             }
         }
@@ -48,26 +55,26 @@ impl Into<Index> for Point {
 
 impl Into<Point> for Index {
     fn into(self) -> Point {
-        let tabsize: u32 = SETTINGS.read().unwrap().get("tabSize").unwrap();
+        let tabsize: usize = SETTINGS.read().unwrap().get("tabSize").unwrap();
         let mut col = 0;
         let line = self.buffer.borrow().char_to_line(self.index);
         let maxc = self.index - self.buffer.borrow().line_to_char(line);
         for c in self.buffer.borrow().chars_on_line(line).take(maxc) {
             match c {
                 '\t' => {
-                    col = ((col + tabsize) / tabsize) * tabsize;
+                    col += spaces_to_next_tab_stop(col, tabsize);
                 }
                 '\r' | '\n' | '\0' => (),
                 // Bom hiding. TODO: rework
                 '\u{feff}' | '\u{fffe}' => (),
                 _ => {
-                    col += 1;
+                    col += char_width(c);
                 }
             }
         }
         Point {
             line,
-            col: col as usize,
+            col,
             buffer: self.buffer,
         }
     }
@@ -102,13 +109,13 @@ impl Cursor {
         for c in self.buffer.borrow().chars_on_line(line) {
             match c {
                 '\t' => {
-                    col = ((col + tabsize) / tabsize) * tabsize;
+                    col += spaces_to_next_tab_stop(col, tabsize);
                 }
                 '\r' | '\n' | '\0' => (),
                 // Bom hiding. TODO: rework
                 '\u{feff}' | '\u{fffe}' => (),
                 _ => {
-                    col += 1;
+                    col += char_width(c);
                 }
             }
         }
@@ -119,7 +126,7 @@ impl Cursor {
         use std::cmp::min;
         self.line = min(line, self.buffer.borrow().len_lines() - 1);
 
-        // Update col if the virtual column index is too far
+        // The column the cursor had before the vertical moves, limited to the length of the line
         let line_last_col = self.line_last_col(self.line);
         self.col = min(line_last_col,self.vcol);
 
@@ -214,13 +221,68 @@ impl Cursor {
         }
     }
 
-    pub fn goto_line_start(&mut self) {
-        let idx = self.buffer.borrow().line_to_char(self.line);
-        self.set_index(idx);
-    }
-
+    /// Move the cursor to the end of the line, the following vertical moves stay at the end of the lines
     pub fn goto_line_end(&mut self) {
         let idx = self.buffer.borrow().line_to_last_char(self.line);
         self.set_index(idx);
+        self.vcol = usize::max_value();
+    }
+
+    /// Move the cursor to the beginning of the previous word
+    /// move line up if on the first char of the current line
+    pub fn word_left(&mut self) {
+        let idx = {
+            let b = self.buffer.borrow();
+            let start = b.line_to_char(self.line);
+            let mut idx = self.index;
+            if idx == start {
+                None
+            } else {
+                while idx > start && (b.char(idx - 1) == ' ' || b.char(idx - 1) == '\t') {
+                    idx -= 1;
+                }
+                if idx > start && is_word_char(b.char(idx - 1)) {
+                    while idx > start && is_word_char(b.char(idx - 1)) {
+                        idx -= 1;
+                    }
+                } else if idx > start {
+                    idx -= 1;
+                }
+                Some(idx)
+            }
+        };
+        match idx {
+            Some(idx) => self.set_index(idx),
+            None => self.left(),
+        }
+    }
+
+    /// Move the cursor after the current word and the following spaces
+    /// move line down if on the last char of the current line
+    pub fn word_right(&mut self) {
+        let idx = {
+            let b = self.buffer.borrow();
+            let end = b.line_to_last_char(self.line);
+            let mut idx = self.index;
+            if idx >= end {
+                None
+            } else {
+                if is_word_char(b.char(idx)) {
+                    while idx < end && is_word_char(b.char(idx)) {
+                        idx += 1;
+                    }
+                } else if b.char(idx) != ' ' && b.char(idx) != '\t' {
+                    idx += 1;
+                }
+                while idx < end && (b.char(idx) == ' ' || b.char(idx) == '\t') {
+                    idx += 1;
+                }
+                Some(idx)
+            }
+        };
+        match idx {
+            Some(idx) => self.set_index(idx),
+            None => self.right(),
+        }
     }
 }
@@ -0,0 +1,95 @@
+/// Score given to each matched char
+const MATCH_SCORE: i32 = 1;
+/// Bonus for a char matched just after the previous one
+const CONTIGUITY_BONUS: i32 = 5;
+/// Bonus for a char matched at the start of a word
+const WORD_START_BONUS: i32 = 8;
+
+/// return true if the char at index i of the candidate starts a word:
+/// the first char, a char following a separator, or an uppercase char following a lowercase one
+fn is_word_start(candidate: &[char], i: usize) -> bool {
+    if i == 0 {
+        return true;
+    }
+    let (prev, c) = (candidate[i - 1], candidate[i]);
+    !prev.is_alphanumeric() && c.is_alphanumeric() || prev.is_lowercase() && c.is_uppercase()
+}
+
+/// score how well the pattern matches the candidate, ignoring case.
+/// Every char of the pattern must be found in order in the candidate. Matches at word starts
+/// and contiguous matches score higher, every char skipped between two matches costs one point.
+/// Return None if the pattern is not a subsequence of the candidate
+pub fn score(pattern: &str, candidate: &str) -> Option<i32> {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    if pattern.is_empty() {
+        return Some(0);
+    }
+
+    // best[j] is the best score of the pattern matched so far, its last char matched at j
+    let mut best: Vec<Option<i32>> = lower
+        .iter()
+        .enumerate()
+        .map(|(j, c)| {
+            if *c == pattern[0] {
+                Some(MATCH_SCORE + if is_word_start(&chars, j) { WORD_START_BONUS } else { 0 })
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    for p in &pattern[1..] {
+        let mut next = vec![None; lower.len()];
+        // best score of a previous match at k, plus k, so the gap penalty j - k - 1 is easy to apply
+        let mut best_before: Option<i32> = None;
+        for j in 1..lower.len() {
+            if let Some(s) = best[j - 1] {
+                let s = s + (j - 1) as i32;
+                best_before = Some(best_before.map_or(s, |b| std::cmp::max(b, s)));
+            }
+            if lower[j] != *p {
+                continue;
+            }
+            let bonus = MATCH_SCORE + if is_word_start(&chars, j) { WORD_START_BONUS } else { 0 };
+            let contiguous = best[j - 1].map(|s| s + bonus + CONTIGUITY_BONUS);
+            let gap = best_before.map(|b| b - (j - 1) as i32 + bonus);
+            next[j] = match (contiguous, gap) {
+                (Some(a), Some(b)) => Some(std::cmp::max(a, b)),
+                (a, b) => a.or(b),
+            };
+        }
+        best = next;
+    }
+    best.into_iter().filter_map(|s| s).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fuzzy::score;
+
+    #[test]
+    fn subsequence() {
+        assert!(score("sl", "select-line").is_some());
+        assert!(score("SL", "select-line").is_some());
+        assert!(score("sn", "select-line").is_some());
+        assert_eq!(score("ls", "select-line"), None);
+        assert_eq!(score("lx", "select-line"), None);
+        assert_eq!(score("selectlines", "select-line"), None);
+        assert_eq!(score("", "select-line"), Some(0));
+        assert_eq!(score("a", ""), None);
+    }
+    #[test]
+    fn ordering() {
+        assert!(score("sl", "select-line") > score("sl", "save-file-later"));
+        assert!(score("sa", "Save") > score("sa", "SelectAll"));
+        assert!(score("sa", "SelectAll") > score("sa", "ClearSearch"));
+        assert!(score("save", "Save") > score("save", "SelectAllVerticalEdges"));
+        assert!(score("dup", "Duplicate") > score("dup", "DeleteUp"));
+        assert!(score("line", "DeleteLine") > score("line", "PageDownSelection"));
+    }
+}
@@ -1,4 +1,6 @@
+use std::collections::{HashMap, HashSet};
 use std::convert::From;
+use std::time::{Duration, Instant};
 
 use bitflags::bitflags;
 use glutin::VirtualKeyCode;
@@ -21,6 +23,15 @@ impl KeyBinding {
     pub fn new(keycode: VirtualKeyCode, keymod: Mod) -> Self {
         KeyBinding { keycode, keymod }
     }
+
+    /// return true if the key is a modifier key (Ctrl, Shift, Alt or Logo) pressed alone
+    pub fn is_modifier(&self) -> bool {
+        use glutin::VirtualKeyCode::*;
+        match self.keycode {
+            LControl | RControl | LShift | RShift | LAlt | RAlt | LWin | RWin => true,
+            _ => false,
+        }
+    }
 }
 
 impl<'a> From<&'a str> for KeyBinding {
@@ -205,12 +216,97 @@ impl<'a> From<&'a str> for KeyBinding {
     }
 }
 
+/// Keys pressed one after the other, written "Ctrl-K Ctrl-C"
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct KeySequence(Vec<KeyBinding>);
+
+impl<'a> From<&'a str> for KeySequence {
+    fn from(sequence: &'a str) -> Self {
+        KeySequence(sequence.split_whitespace().map(KeyBinding::from).collect())
+    }
+}
+
+impl From<KeyBinding> for KeySequence {
+    fn from(keybinding: KeyBinding) -> Self {
+        KeySequence(vec![keybinding])
+    }
+}
+
+/// Result of a key press given to a KeyMap
+#[derive(PartialEq, Eq, Debug)]
+pub enum KeyMatch<T> {
+    /// the key ends a bound sequence
+    Bound(T),
+    /// the keys pressed so far start a sequence, the next key is awaited
+    Pending,
+    /// the key, or the sequence it ends, is not bound
+    Unbound,
+}
+
+/// Bind key sequences to values, keeping track of the keys of a sequence pressed so far.
+/// A sequence is aborted if the next key is not pressed before the timeout
+#[derive(Debug)]
+pub struct KeyMap<T> {
+    bindings: HashMap<Vec<KeyBinding>, T>,
+    prefixes: HashSet<Vec<KeyBinding>>,
+    pending: Vec<KeyBinding>,
+    last_key: Option<Instant>,
+    timeout: Duration,
+}
+
+impl<T: Clone> KeyMap<T> {
+    pub fn new(timeout: Duration) -> Self {
+        KeyMap {
+            bindings: HashMap::new(),
+            prefixes: HashSet::new(),
+            pending: Vec::new(),
+            last_key: None,
+            timeout,
+        }
+    }
+
+    /// bind a sequence, replacing the previous value bound to it
+    pub fn insert(&mut self, sequence: KeySequence, value: T) {
+        let keys = sequence.0;
+        for i in 1..keys.len() {
+            self.prefixes.insert(keys[..i].to_vec());
+        }
+        self.bindings.insert(keys, value);
+    }
+
+    /// handle a key pressed at the given instant.
+    /// A sequence bound to a value is matched before a longer sequence starting with it.
+    /// The modifier keys are ignored, they can be released and pressed again between the keys of a sequence
+    pub fn process(&mut self, key: KeyBinding, now: Instant) -> KeyMatch<T> {
+        if key.is_modifier() {
+            return if self.pending.is_empty() { KeyMatch::Unbound } else { KeyMatch::Pending };
+        }
+        if self.last_key.map_or(false, |t| now.duration_since(t) > self.timeout) {
+            self.pending.clear();
+        }
+        self.last_key = Some(now);
+        self.pending.push(key);
+
+        if let Some(value) = self.bindings.get(&self.pending) {
+            self.pending.clear();
+            KeyMatch::Bound(value.clone())
+        } else if self.prefixes.contains(&self.pending) {
+            KeyMatch::Pending
+        } else {
+            self.pending.clear();
+            KeyMatch::Unbound
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::KeyBinding;
     use super::Mod;
+    use super::{KeyMap, KeyMatch, KeySequence};
     use glutin::VirtualKeyCode;
     use std::convert::From;
+    use std::time::{Duration, Instant};
     #[test]
     fn from_str() {
         assert_eq!(KeyBinding::from("Ctrl-C"), KeyBinding::new(VirtualKeyCode::C, Mod::CTRL));
@@ -223,4 +319,46 @@ mod test {
             KeyBinding::new(VirtualKeyCode::Return, Mod::CTRL)
         );
     }
+    #[test]
+    fn key_sequence() {
+        let ctrl_k = KeyBinding::new(VirtualKeyCode::K, Mod::CTRL);
+        let ctrl_c = KeyBinding::new(VirtualKeyCode::C, Mod::CTRL);
+        assert_eq!(KeySequence::from("Ctrl-K  Ctrl-C"), KeySequence(vec![ctrl_k, ctrl_c]));
+        assert_eq!(KeySequence::from("Ctrl-C"), KeySequence::from(ctrl_c));
+    }
+    #[test]
+    fn key_map() {
+        let ctrl_k = KeyBinding::from("Ctrl-K");
+        let ctrl_c = KeyBinding::from("Ctrl-C");
+        let ctrl_u = KeyBinding::from("Ctrl-U");
+        let mut map = KeyMap::new(Duration::from_millis(500));
+        map.insert(KeySequence::from("Ctrl-K Ctrl-C"), 1);
+        map.insert(KeySequence::from("Ctrl-K Ctrl-U"), 2);
+        map.insert(KeySequence::from("Ctrl-C"), 3);
+
+        let t = Instant::now();
+        assert_eq!(map.process(ctrl_c, t), KeyMatch::Bound(3));
+        assert_eq!(map.process(ctrl_k, t), KeyMatch::Pending);
+        assert_eq!(map.process(ctrl_c, t + Duration::from_millis(100)), KeyMatch::Bound(1));
+        assert_eq!(map.process(ctrl_k, t), KeyMatch::Pending);
+        assert_eq!(map.process(ctrl_u, t), KeyMatch::Bound(2));
+
+        // a key not continuing the sequence aborts it
+        assert_eq!(map.process(ctrl_k, t), KeyMatch::Pending);
+        assert_eq!(map.process(ctrl_k, t), KeyMatch::Unbound);
+        assert_eq!(map.process(KeyBinding::from("Ctrl-Z"), t), KeyMatch::Unbound);
+
+        // the sequence is aborted after the timeout, the key is handled alone
+        assert_eq!(map.process(ctrl_k, t), KeyMatch::Pending);
+        assert_eq!(map.process(ctrl_c, t + Duration::from_millis(600)), KeyMatch::Bound(3));
+        assert_eq!(map.process(ctrl_u, t), KeyMatch::Unbound);
+
+        // pressing Ctrl again between the keys keeps the sequence pending
+        let ctrl = KeyBinding::new(VirtualKeyCode::LControl, Mod::CTRL);
+        assert_eq!(map.process(ctrl, t), KeyMatch::Unbound);
+        assert_eq!(map.process(ctrl_k, t), KeyMatch::Pending);
+        assert_eq!(map.process(ctrl, t), KeyMatch::Pending);
+        assert_eq!(map.process(KeyBinding::new(VirtualKeyCode::RShift, Mod::CTRL | Mod::SHIFT), t), KeyMatch::Pending);
+        assert_eq!(map.process(ctrl_c, t), KeyMatch::Bound(1));
+    }
 }
@@ -1,7 +1,11 @@
 mod buffer;
 mod commands;
+mod fuzzy;
 mod keybinding;
+mod recent;
+mod session;
 mod styling;
+mod theme;
 mod view;
 mod window;
 mod nanovg;
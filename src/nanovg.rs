@@ -1,7 +1,17 @@
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
 use gl;
 use glutin;
 use glutin::GlContext;
 use nanovg;
+use stb_truetype::FontInfo;
+use syntect::highlighting::FontStyle;
+
+/// The monospace font, used for the glyphs of the regular style
+const MONO_FONT: &[u8] = include_bytes!("monofont/UbuntuMono-B.ttf");
 
 pub struct MonoFontMetrics {
     pub advance: f32,
@@ -10,11 +20,40 @@ pub struct MonoFontMetrics {
     pub line_height: f32,
 }
 
+/// Weight and slant of a glyph, each combination is drawn with its own font face
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphStyle {
+    pub bold: bool,
+    pub italic: bool,
+}
+
+impl GlyphStyle {
+    /// name of the font face registered for this style
+    pub fn font_name(self) -> &'static str {
+        match (self.bold, self.italic) {
+            (false, false) => "Mono",
+            (true, false) => "Mono-Bold",
+            (false, true) => "Mono-Italic",
+            (true, true) => "Mono-BoldItalic",
+        }
+    }
+}
+
+impl From<FontStyle> for GlyphStyle {
+    fn from(style: FontStyle) -> Self {
+        GlyphStyle {
+            bold: style.contains(FontStyle::BOLD),
+            italic: style.contains(FontStyle::ITALIC),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum DisplayList {
     Move(f32, f32),
     Color(nanovg::Color),
     Char(char),
+    StyledChar(char, GlyphStyle),
     Rect(f32, f32),
     Clear,
 }
@@ -25,7 +64,7 @@ pub struct Canvas {
 }
 
 impl Canvas {
-    fn new(font_metrics: MonoFontMetrics) -> Self {
+    pub(crate) fn new(font_metrics: MonoFontMetrics) -> Self {
 
         Canvas{
             cmdlist: Vec::new(),
@@ -52,10 +91,83 @@ impl Canvas {
         self.cmdlist.push(DisplayList::Char(c));
     }
 
+    /// Draw a char with the given style
+    pub fn draw_styled_char(&mut self, c: char, style: GlyphStyle) {
+        self.cmdlist.push(DisplayList::StyledChar(c, style));
+    }
+
+    /// Draw a string, one char after the other
+    pub fn draw_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.draw_char(c);
+        }
+    }
+
     /// move the pointer to x,y
     pub fn move_to(&mut self, x: f32, y: f32) {
         self.cmdlist.push(DisplayList::Move(x, y));
     }
+
+    /// return the commands drawn since the last clear
+    pub fn display_list(&self) -> &[DisplayList] {
+        &self.cmdlist
+    }
+}
+
+/// return the files that exist, in order
+fn existing_files<P: AsRef<Path>>(files: &[P]) -> Vec<&Path> {
+    files.iter().map(AsRef::as_ref).filter(|f| f.is_file()).collect()
+}
+
+/// A font telling whether it has a glyph for a char
+trait GlyphLookup {
+    fn has_glyph(&self, c: char) -> bool;
+}
+
+impl<D: Deref<Target = [u8]>> GlyphLookup for FontInfo<D> {
+    fn has_glyph(&self, c: char) -> bool {
+        self.find_glyph_index(c as u32) != 0
+    }
+}
+
+/// return the index of the first fallback font having the glyph of a char missing from the primary font.
+/// Return None if the char is drawn with the primary font: it has the glyph, or no fallback font has it
+fn fallback_for<P: GlyphLookup, F: GlyphLookup>(c: char, primary: &P, fallbacks: &[F]) -> Option<usize> {
+    if primary.has_glyph(c) {
+        return None;
+    }
+    fallbacks.iter().position(|f| f.has_glyph(c))
+}
+
+/// read the glyphs of a font file, the first font of a collection
+fn load_font_info(file: &Path) -> io::Result<FontInfo<Vec<u8>>> {
+    let data = fs::read(file)?;
+    let offset = stb_truetype::get_font_offset_for_index(&data, 0).unwrap_or(0);
+    FontInfo::new(data, offset as usize).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a font"))
+}
+
+/// measure the advance and the vertical metrics of the monospace font drawn with the text options
+fn measure_font(
+    nvgcontext: &nanovg::Context,
+    window: &glutin::GlWindow,
+    font: nanovg::Font<'_>,
+    text_option: nanovg::TextOptions,
+) -> MonoFontMetrics {
+    let mut advance: f32 = 0.0;
+    let mut text_metrics: nanovg::TextMetrics = nanovg::TextMetrics {ascender: 0.0,descender: 0.0,line_height: 0.0};
+
+    let hidpi_factor = window.get_current_monitor().get_hidpi_factor();
+    nvgcontext.frame((window.get_inner_size().unwrap().width as _, window.get_inner_size().unwrap().height as _), hidpi_factor as _, |frame| {
+        advance = frame.text_bounds(font, (0.0, 0.0), "_", text_option).0;
+        text_metrics = frame.text_metrics(font, text_option);
+    });
+
+    MonoFontMetrics {
+        advance,
+        ascender: text_metrics.ascender,
+        descender: text_metrics.descender,
+        line_height: text_metrics.line_height,
+    }
 }
 
 pub struct System {
@@ -63,11 +175,16 @@ pub struct System {
     pub window: glutin::GlWindow,
     nvgcontext: nanovg::Context,
     text_option: nanovg::TextOptions,
+    // glyphs of the monospace font and of the fallback fonts, the fallback fonts are registered as "Fallback-<index>"
+    mono_glyphs: FontInfo<&'static [u8]>,
+    fallback_glyphs: Vec<FontInfo<Vec<u8>>>,
     pub canvas: Canvas,
 }
 
 impl System {
-    pub fn new(title: &str, width: f32, height: f32, font_size: f32) -> Self {
+    /// create the window. The glyphs missing from the monospace font are drawn with the first
+    /// of the fallback fonts having them
+    pub fn new(title: &str, width: f32, height: f32, font_size: f32, fallback_fonts: &[PathBuf]) -> Self {
         let mut events_loop = glutin::EventsLoop::new();
         let window = glutin::WindowBuilder::new()
             .with_title(title)
@@ -85,8 +202,30 @@ impl System {
             .build()
             .expect("Initialization of NanoVG failed!");
 
-        let mono_font = nanovg::Font::from_memory(&nvgcontext, "Mono", include_bytes!("monofont/UbuntuMono-B.ttf"))
+        let mono_font = nanovg::Font::from_memory(&nvgcontext, "Mono", MONO_FONT).expect("Failed to load font");
+        let mono_glyphs = FontInfo::new(MONO_FONT, 0).expect("Failed to load font");
+        nanovg::Font::from_memory(&nvgcontext, "Mono-Bold", include_bytes!("monofont/UbuntuMono-Bold.ttf"))
+            .expect("Failed to load font");
+        nanovg::Font::from_memory(&nvgcontext, "Mono-Italic", include_bytes!("monofont/UbuntuMono-Italic.ttf"))
             .expect("Failed to load font");
+        nanovg::Font::from_memory(
+            &nvgcontext,
+            "Mono-BoldItalic",
+            include_bytes!("monofont/UbuntuMono-BoldItalic.ttf"),
+        )
+        .expect("Failed to load font");
+
+        let mut fallback_glyphs = Vec::new();
+        for file in existing_files(fallback_fonts) {
+            let name = format!("Fallback-{}", fallback_glyphs.len());
+            match load_font_info(file) {
+                Ok(glyphs) => match nanovg::Font::from_file(&nvgcontext, name, file) {
+                    Ok(_) => fallback_glyphs.push(glyphs),
+                    Err(e) => println!("Unable to load the font {}: {:?}", file.display(), e),
+                },
+                Err(e) => println!("Unable to load the font {}: {}", file.display(), e),
+            }
+        }
 
         let text_option = nanovg::TextOptions {
             color: nanovg::Color::new(1.0, 1.0, 1.0, 1.0),
@@ -103,30 +242,30 @@ impl System {
             window.get_inner_size().unwrap().to_physical(hidpi_factor)
         );
 
-        let mut advance: f32 = 0.0;
-        let mut text_metrics: nanovg::TextMetrics = nanovg::TextMetrics {ascender: 0.0,descender: 0.0,line_height: 0.0};
-
-        nvgcontext.frame((window.get_inner_size().unwrap().width as _, window.get_inner_size().unwrap().height as _), hidpi_factor as _, |frame| {
-            advance = frame.text_bounds(mono_font, (0.0, 0.0), "_", text_option).0;
-            text_metrics = frame.text_metrics(mono_font, text_option);
-        });
-
-        let font_metrics = MonoFontMetrics {
-            advance,
-            ascender: text_metrics.ascender,
-            descender: text_metrics.descender,
-            line_height: text_metrics.line_height,
-        };
+        let font_metrics = measure_font(&nvgcontext, &window, mono_font, text_option);
 
         System {
             events_loop,
             window,
             nvgcontext,
             text_option,
+            mono_glyphs,
+            fallback_glyphs,
             canvas: Canvas::new(font_metrics),
         }
     }
 
+    pub fn font_size(&self) -> f32 {
+        self.text_option.size
+    }
+
+    /// draw the text with the given font size, the font metrics are measured again
+    pub fn set_font_size(&mut self, font_size: f32) {
+        self.text_option.size = font_size;
+        let font = nanovg::Font::find(&self.nvgcontext, "Mono").unwrap();
+        self.canvas.font_metrics = measure_font(&self.nvgcontext, &self.window, font, self.text_option);
+    }
+
     pub fn log_width(&self) -> f64 {
         self.window.get_inner_size().unwrap().width
     }
@@ -153,6 +292,12 @@ impl System {
         let mut color = nanovg::Color::from_rgb(0, 0, 0);
 
         let font = nanovg::Font::find(&self.nvgcontext, "Mono").unwrap();
+        let styled_font = |style: GlyphStyle| nanovg::Font::find(&self.nvgcontext, style.font_name()).unwrap();
+        // a glyph missing from the monospace font is drawn with the first fallback font having it
+        let fallback_font = |c: char| {
+            fallback_for(c, &self.mono_glyphs, &self.fallback_glyphs)
+                .map(|i| nanovg::Font::find(&self.nvgcontext, format!("Fallback-{}", i)).unwrap())
+        };
         let mut text_option = self.text_option;
 
         let phy_width = self.phy_width();
@@ -177,6 +322,12 @@ impl System {
                     }
                     DisplayList::Char(c) => {
                         text_option.color = color;
+                        frame.text(fallback_font(c).unwrap_or(font), (x, y), c.to_string(), text_option);
+                        x += self.canvas.font_metrics.advance;
+                    }
+                    DisplayList::StyledChar(c, style) => {
+                        text_option.color = color;
+                        let font = fallback_font(c).unwrap_or_else(|| styled_font(style));
                         frame.text(font, (x, y), c.to_string(), text_option);
                         x += self.canvas.font_metrics.advance;
                     }
@@ -193,3 +344,130 @@ impl System {
         self.window.swap_buffers().unwrap();
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::nanovg::{
+        existing_files, fallback_for, Canvas, DisplayList, GlyphLookup, GlyphStyle, MonoFontMetrics, MONO_FONT,
+    };
+    use nanovg::Color;
+    use stb_truetype::FontInfo;
+    use std::{env, fs};
+    use syntect::highlighting::FontStyle;
+
+    /// a canvas drawing glyphs 10 pixels wide on lines 10 pixels high, without a window
+    pub fn test_canvas() -> Canvas {
+        Canvas::new(MonoFontMetrics {
+            advance: 10.0,
+            ascender: 8.0,
+            descender: -2.0,
+            line_height: 10.0,
+        })
+    }
+
+    /// return the rects drawn, in order, as (x, y, width, height).
+    /// With a color, only the rects drawn with it are returned, its alpha is ignored
+    pub fn drawn_rects(canvas: &Canvas, color: Option<Color>) -> Vec<(f32, f32, f32, f32)> {
+        let rgb = |c: &Color| (c.red(), c.green(), c.blue());
+        let (mut pos, mut current) = ((0.0, 0.0), None);
+        let mut rects = Vec::new();
+        for cmd in canvas.display_list() {
+            match cmd {
+                DisplayList::Move(x, y) => pos = (*x, *y),
+                DisplayList::Color(c) => current = Some(rgb(c)),
+                DisplayList::Rect(w, h) if color.map_or(true, |c| current == Some(rgb(&c))) => {
+                    rects.push((pos.0, pos.1, *w, *h))
+                }
+                _ => (),
+            }
+        }
+        rects
+    }
+
+    /// return the chars drawn, styled or not, in order, as (x, y, char).
+    /// As when rendering, each char moves the position by an advance
+    pub fn drawn_chars(canvas: &Canvas) -> Vec<(f32, f32, char)> {
+        let mut pos = (0.0, 0.0);
+        let mut chars = Vec::new();
+        for cmd in canvas.display_list() {
+            match cmd {
+                DisplayList::Move(x, y) => pos = (*x, *y),
+                DisplayList::Char(c) | DisplayList::StyledChar(c, _) => {
+                    chars.push((pos.0, pos.1, *c));
+                    pos.0 += canvas.font_metrics.advance;
+                }
+                _ => (),
+            }
+        }
+        chars
+    }
+
+    /// a font having the glyphs of the chars of its str
+    struct StubFont(&'static str);
+
+    impl GlyphLookup for StubFont {
+        fn has_glyph(&self, c: char) -> bool {
+            self.0.contains(c)
+        }
+    }
+
+    #[test]
+    fn fallback_fonts() {
+        let mut font = env::temp_dir();
+        font.push(format!("nonedit_{}_fallback.ttf", std::process::id()));
+        fs::write(&font, b"").unwrap();
+        let missing = env::temp_dir().join("nonedit_missing_font.ttf");
+        assert_eq!(existing_files(&[&missing, &font]), vec![font.as_path()]);
+        fs::remove_file(&font).unwrap();
+    }
+
+    #[test]
+    fn fallback_glyphs() {
+        let primary = StubFont("abc");
+        let fallbacks = [StubFont("a\u{e9}"), StubFont("\u{e9}\u{4e2d}")];
+        assert_eq!(fallback_for('a', &primary, &fallbacks), None);
+        // the first fallback font having the glyph
+        assert_eq!(fallback_for('\u{e9}', &primary, &fallbacks), Some(0));
+        assert_eq!(fallback_for('\u{4e2d}', &primary, &fallbacks), Some(1));
+        // a glyph found nowhere is drawn with the primary font
+        assert_eq!(fallback_for('z', &primary, &fallbacks), None);
+        // without fallback fonts
+        let none: [StubFont; 0] = [];
+        assert_eq!(fallback_for('\u{4e2d}', &primary, &none), None);
+
+        let mono = FontInfo::new(MONO_FONT, 0).unwrap();
+        assert!(mono.has_glyph('a'));
+        assert!(!mono.has_glyph('\u{4e2d}'));
+    }
+
+    #[test]
+    fn glyph_style() {
+        let bold = GlyphStyle::from(FontStyle::BOLD);
+        assert_eq!(bold, GlyphStyle { bold: true, italic: false });
+        assert_eq!(GlyphStyle::from(FontStyle::empty()), GlyphStyle::default());
+        assert_eq!(GlyphStyle::from(FontStyle::BOLD | FontStyle::ITALIC).font_name(), "Mono-BoldItalic");
+        assert_ne!(bold.font_name(), GlyphStyle::default().font_name());
+
+        let mut canvas = test_canvas();
+        canvas.draw_char('a');
+        canvas.draw_styled_char('a', bold);
+        match canvas.display_list() {
+            [DisplayList::Char('a'), DisplayList::StyledChar('a', style)] => assert_eq!(*style, bold),
+            l => panic!("unexpected display list {:?}", l),
+        }
+    }
+    #[test]
+    fn drawn_positions() {
+        let mut canvas = test_canvas();
+        canvas.set_color(Color::from_rgb(1, 2, 3));
+        canvas.move_to(5.0, 10.0);
+        canvas.draw_str("ab");
+        canvas.draw_rect(3.0, 4.0);
+        canvas.set_color(Color::from_rgba(4, 5, 6, 0x80));
+        canvas.move_to(0.0, 20.0);
+        canvas.draw_rect(1.0, 2.0);
+        assert_eq!(drawn_chars(&canvas), vec![(5.0, 10.0, 'a'), (15.0, 10.0, 'b')]);
+        assert_eq!(drawn_rects(&canvas, None), vec![(5.0, 10.0, 3.0, 4.0), (0.0, 20.0, 1.0, 2.0)]);
+        assert_eq!(drawn_rects(&canvas, Some(Color::from_rgb(4, 5, 6))), vec![(0.0, 20.0, 1.0, 2.0)]);
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+
+/// Maximum number of recent files remembered
+const MAX_RECENT_FILES: usize = 10;
+
+/// The files opened recently, most recent first, saved to the config directory
+#[derive(Debug, Default)]
+pub struct RecentFiles {
+    files: Vec<PathBuf>,
+    // file the list is saved to, None to keep it in memory only
+    path: Option<PathBuf>,
+}
+
+impl RecentFiles {
+    /// load the list saved in the user config directory.
+    /// A missing or unreadable file gives an empty list
+    pub fn load_user() -> Self {
+        let path = ProjectDirs::from("com", "pepone42", "nonedit").map(|d| d.config_dir().join("recent.txt"));
+        match path {
+            Some(path) => RecentFiles::load(path),
+            None => RecentFiles::default(),
+        }
+    }
+
+    /// load the list saved in the file, one path per line.
+    /// A missing or unreadable file gives an empty list
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let mut recent = RecentFiles {
+            files: Vec::new(),
+            path: Some(path.as_ref().to_owned()),
+        };
+        if let Ok(content) = fs::read_to_string(path) {
+            for line in content.lines().rev().map(str::trim).filter(|l| !l.is_empty()) {
+                recent.push(line);
+            }
+        }
+        recent
+    }
+
+    /// the recent files, most recent first
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// move the file at the top of the list, dropping the oldest one if the list is full
+    pub fn push<P: AsRef<Path>>(&mut self, file: P) {
+        let file = file.as_ref();
+        let file = file.canonicalize().unwrap_or_else(|_| file.to_owned());
+        self.files.retain(|f| *f != file);
+        self.files.insert(0, file);
+        self.files.truncate(MAX_RECENT_FILES);
+    }
+
+    /// add the file to the list and save it
+    pub fn add<P: AsRef<Path>>(&mut self, file: P) {
+        self.push(file);
+        if let Err(e) = self.save() {
+            println!("Unable to save the recent files: {}", e);
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content: Vec<_> = self.files.iter().map(|f| f.to_string_lossy()).collect();
+        fs::write(path, content.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::recent::{RecentFiles, MAX_RECENT_FILES};
+    use std::path::PathBuf;
+    use std::{env, fs};
+
+    #[test]
+    fn dedup() {
+        let mut r = RecentFiles::default();
+        r.push("a.txt");
+        r.push("b.txt");
+        r.push("a.txt");
+        assert_eq!(r.files(), &[PathBuf::from("a.txt"), PathBuf::from("b.txt")][..]);
+    }
+    #[test]
+    fn cap() {
+        let mut r = RecentFiles::default();
+        for i in 0..MAX_RECENT_FILES + 5 {
+            r.push(format!("{}.txt", i));
+        }
+        assert_eq!(r.files().len(), MAX_RECENT_FILES);
+        assert_eq!(r.files()[0], PathBuf::from(format!("{}.txt", MAX_RECENT_FILES + 4)));
+        assert_eq!(r.files()[MAX_RECENT_FILES - 1], PathBuf::from("5.txt"));
+    }
+    #[test]
+    fn save_and_load() {
+        let mut path = env::temp_dir();
+        path.push(format!("nonedit_{}_recent", std::process::id()));
+        path.push("recent.txt");
+        assert!(RecentFiles::load(&path).files().is_empty());
+
+        let mut r = RecentFiles::load(&path);
+        r.add("a.txt");
+        r.add("b.txt");
+        let r = RecentFiles::load(&path);
+        assert_eq!(r.files(), &[PathBuf::from("b.txt"), PathBuf::from("a.txt")][..]);
+
+        fs::write(&path, b"\xFF\xFE\x00garbage").unwrap();
+        assert!(RecentFiles::load(&path).files().is_empty());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
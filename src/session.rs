@@ -0,0 +1,79 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use serde_derive::{Deserialize, Serialize};
+
+/// A view saved in a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewSession {
+    pub file: PathBuf,
+    /// cursor position, in chars
+    pub cursor: usize,
+    pub first_visible_line: usize,
+}
+
+/// The files opened in the window, with the cursor and the scroll position of their view
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub views: Vec<ViewSession>,
+    /// index of the active view in views
+    pub current_view: usize,
+}
+
+impl Session {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let content = serde_json::to_string_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, content)
+    }
+}
+
+/// return the file the session is saved to on exit, in the user config directory
+pub fn user_session_file() -> Option<PathBuf> {
+    let user_dir = ProjectDirs::from("com", "pepone42", "nonedit")?;
+    Some(user_dir.config_dir().join("session.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::session::{Session, ViewSession};
+    use std::path::PathBuf;
+    use std::{env, fs};
+
+    #[test]
+    fn round_trip() {
+        let session = Session {
+            views: vec![
+                ViewSession {
+                    file: PathBuf::from("first.rs"),
+                    cursor: 12,
+                    first_visible_line: 0,
+                },
+                ViewSession {
+                    file: PathBuf::from("second.rs"),
+                    cursor: 345,
+                    first_visible_line: 20,
+                },
+            ],
+            current_view: 1,
+        };
+        let mut path = env::temp_dir();
+        path.push(format!("nonedit_{}_session.json", std::process::id()));
+        session.save(&path).unwrap();
+        assert_eq!(Session::load(&path).unwrap(), session);
+
+        fs::write(&path, "{ not json").unwrap();
+        assert!(Session::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
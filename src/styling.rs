@@ -148,3 +148,38 @@ impl<'a> StylingCache<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::Buffer;
+    use crate::styling::{StylingCache, SYNTAXSET};
+    use syntect::highlighting::Style;
+
+    #[test]
+    fn rust_keyword_and_comment() {
+        let b = Buffer::from_str("fn main() {} // done\nlet x = 1;\nlet s = \"a b\";\n");
+        let mut cache = StylingCache::new(SYNTAXSET.find_syntax_by_extension("rs").unwrap());
+        cache.expand(b.len_lines(), &b);
+        assert_eq!(cache.result.len(), b.len_lines());
+
+        let styles: Vec<Style> = cache.result[0].iter().collect();
+        assert_eq!(styles.len(), "fn main() {} // done\n".len());
+        let keyword = styles[0].foreground;
+        let comment = styles[16].foreground;
+        assert_eq!(styles[1].foreground, keyword);
+        assert_ne!(keyword, comment);
+        assert_eq!(styles[19].foreground, comment);
+
+        let styles: Vec<Style> = cache.result[1].iter().collect();
+        assert_ne!(styles[0].foreground, comment);
+        assert_ne!(styles[8].foreground, styles[4].foreground);
+
+        // the text of a string has one color
+        let styles: Vec<Style> = cache.result[2].iter().collect();
+        let string = styles[9].foreground;
+        assert!(styles[9..12].iter().all(|s| s.foreground == string));
+        assert_ne!(string, styles[4].foreground);
+        assert_ne!(string, keyword);
+        assert_ne!(string, styles[13].foreground);
+    }
+}
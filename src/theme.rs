@@ -0,0 +1,211 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use config::{Config, ConfigError, FileFormat};
+use directories::ProjectDirs;
+use syntect::highlighting::{self, Color};
+
+use crate::styling::THEMESET;
+
+/// Colors used to draw the editor, outside of the syntax highlighting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub background: Color,
+    pub foreground: Color,
+    pub selection: Color,
+    pub caret: Color,
+    pub gutter_foreground: Color,
+    pub find_highlight: Color,
+    pub bracket_highlight: Color,
+    pub line_highlight: Color,
+    pub ruler: Color,
+    pub indent_guide: Color,
+}
+
+impl Theme {
+    /// the default dark theme
+    pub fn solarized_dark() -> Self {
+        Theme::from_syntect(&THEMESET.themes["Solarized (dark)"])
+    }
+
+    /// a light theme
+    pub fn solarized_light() -> Self {
+        Theme::from_syntect(&THEMESET.themes["Solarized (light)"])
+    }
+
+    /// load a theme from a toml file, colors are written as "#RRGGBB".
+    /// background and foreground are mandatory, the other colors default to the dark theme
+    pub fn from_toml_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Theme::from_toml_str(&contents)
+    }
+
+    fn from_toml_str(contents: &str) -> io::Result<Self> {
+        let mut conf = Config::default();
+        conf.merge(config::File::from_str(contents, FileFormat::Toml))
+            .map_err(|e| invalid_data(e.to_string()))?;
+
+        let color = |key: &str, default: Option<Color>| match conf.get::<String>(key) {
+            Ok(value) => {
+                parse_hex_color(&value).ok_or_else(|| invalid_data(format!("invalid color for {}: {}", key, value)))
+            }
+            Err(ConfigError::NotFound(_)) => default.ok_or_else(|| invalid_data(format!("missing color {}", key))),
+            Err(e) => Err(invalid_data(format!("{}: {}", key, e))),
+        };
+        let default = Theme::default();
+        Ok(Theme {
+            background: color("background", None)?,
+            foreground: color("foreground", None)?,
+            selection: color("selection", Some(default.selection))?,
+            caret: color("caret", Some(default.caret))?,
+            gutter_foreground: color("gutter_foreground", Some(default.gutter_foreground))?,
+            find_highlight: color("find_highlight", Some(default.find_highlight))?,
+            bracket_highlight: color("bracket_highlight", Some(default.bracket_highlight))?,
+            line_highlight: color("line_highlight", Some(default.line_highlight))?,
+            ruler: color("ruler", Some(default.ruler))?,
+            indent_guide: color("indent_guide", Some(default.indent_guide))?,
+        })
+    }
+
+    /// load the user theme from the config directory, if there is one
+    pub fn load_user_theme() -> Option<Self> {
+        let user_dir = ProjectDirs::from("com", "pepone42", "nonedit")?;
+        let mut theme_file = PathBuf::from(user_dir.config_dir());
+        theme_file.push("theme.toml");
+        if !theme_file.exists() {
+            return None;
+        }
+        match Theme::from_toml_file(&theme_file) {
+            Ok(theme) => Some(theme),
+            Err(e) => {
+                println!("Unable to load {}: {}", theme_file.display(), e);
+                None
+            }
+        }
+    }
+
+    fn from_syntect(theme: &highlighting::Theme) -> Self {
+        let settings = &theme.settings;
+        Theme {
+            background: settings.background.unwrap_or(Color::BLACK),
+            foreground: settings.foreground.unwrap_or(Color::WHITE),
+            selection: settings.selection.unwrap_or(Color::WHITE),
+            caret: settings.caret.unwrap_or(Color::WHITE),
+            gutter_foreground: settings.gutter_foreground.unwrap_or(Color::WHITE),
+            find_highlight: settings.find_highlight.unwrap_or(Color {
+                r: 0x80,
+                g: 0x80,
+                b: 0x00,
+                a: 0xFF,
+            }),
+            bracket_highlight: settings
+                .brackets_background
+                .or(settings.line_highlight)
+                .unwrap_or(Color {
+                    r: 0x50,
+                    g: 0x50,
+                    b: 0x50,
+                    a: 0xFF,
+                }),
+            line_highlight: settings.line_highlight.unwrap_or(Color {
+                r: 0x30,
+                g: 0x30,
+                b: 0x30,
+                a: 0xFF,
+            }),
+            ruler: settings.guide.unwrap_or(Color {
+                r: 0x60,
+                g: 0x60,
+                b: 0x60,
+                a: 0xFF,
+            }),
+            indent_guide: settings.stack_guide.or(settings.guide).unwrap_or(Color {
+                r: 0x40,
+                g: 0x40,
+                b: 0x40,
+                a: 0xFF,
+            }),
+        }
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// parse a color written as "#RRGGBB"
+fn parse_hex_color(s: &str) -> Option<Color> {
+    if s.len() != 7 || !s.starts_with('#') || !s[1..].chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let component = |i: usize| s.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+    Some(Color {
+        r: component(1)?,
+        g: component(3)?,
+        b: component(5)?,
+        a: 0xFF,
+    })
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::solarized_dark()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::theme::{parse_hex_color, Theme};
+    use syntect::highlighting::Color;
+
+    #[test]
+    fn default_theme() {
+        let theme = Theme::default();
+        assert_eq!(
+            theme.background,
+            Color {
+                r: 0x00,
+                g: 0x2B,
+                b: 0x36,
+                a: 0xFF
+            }
+        );
+        assert_eq!(theme, Theme::solarized_dark());
+        assert_ne!(theme.background, Theme::solarized_light().background);
+    }
+    #[test]
+    fn hex_color() {
+        assert_eq!(
+            parse_hex_color("#002b36"),
+            Some(Color {
+                r: 0x00,
+                g: 0x2B,
+                b: 0x36,
+                a: 0xFF
+            })
+        );
+        assert_eq!(parse_hex_color("#FFFFFF"), Some(Color::WHITE));
+        assert_eq!(parse_hex_color("002b36"), None);
+        assert_eq!(parse_hex_color("#002b3"), None);
+        assert_eq!(parse_hex_color("#002b366"), None);
+        assert_eq!(parse_hex_color("#00gg36"), None);
+        assert_eq!(parse_hex_color("#+1+2+3"), None);
+        assert_eq!(parse_hex_color("#\u{e9}1234"), None);
+    }
+    #[test]
+    fn from_toml() {
+        let theme = Theme::from_toml_str("background = \"#000000\"\nforeground = \"#ffffff\"\ncaret = \"#ff0000\"").unwrap();
+        assert_eq!(theme.background, Color::BLACK);
+        assert_eq!(theme.foreground, Color::WHITE);
+        assert_eq!(theme.caret.r, 0xFF);
+        assert_eq!(theme.caret.g, 0);
+        assert_eq!(theme.selection, Theme::default().selection);
+
+        let err = Theme::from_toml_str("foreground = \"#ffffff\"").unwrap_err();
+        assert!(err.to_string().contains("background"));
+        let err = Theme::from_toml_str("background = \"#000000\"\nforeground = \"white\"").unwrap_err();
+        assert!(err.to_string().contains("foreground"));
+        assert!(Theme::from_toml_file("/nonexistent/theme.toml").is_err());
+    }
+}
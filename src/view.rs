@@ -5,18 +5,47 @@ use std::rc::Rc;
 
 use crate::styling::SYNTAXSET;
 
-use syntect::highlighting;
-
-use crate::buffer::Buffer;
-use crate::cursor::Cursor;
-use crate::keybinding::KeyBinding;
+use crate::buffer::{char_width, is_word_char, Buffer};
+use crate::cursor::{spaces_to_next_tab_stop, Cursor, Index, Point};
+use crate::keybinding::KeySequence;
 use crate::styling::StylingCache;
-use crate::styling::STYLE;
+use crate::theme::Theme;
 use crate::window::Geometry;
 use crate::SETTINGS;
 
-use crate::nanovg::Canvas;
+use crate::nanovg::{Canvas, GlyphStyle};
 use nanovg::Color;
+use regex::Regex;
+
+/// number of columns kept visible between the cursor and the side of the view
+const HSCROLL_MARGIN: usize = 4;
+/// number of positions remembered by the jump list
+const MAX_JUMPS: usize = 100;
+/// glyphs drawn over the spaces, the tabs and the line endings when the whitespace is shown
+const SPACE_MARKER: char = '\u{b7}';
+const TAB_MARKER: char = '\u{2192}';
+const EOL_MARKER: char = '\u{ac}';
+
+/// What is drawn by a view, compared between two frames to skip useless redraws
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ViewDrawState {
+    revision: usize,
+    is_dirty: bool,
+    changed_on_disk: bool,
+    cursor: usize,
+    selection: Option<Range<usize>>,
+    line_start: usize,
+    col_start: usize,
+    search_query: Option<String>,
+    show_line_numbers: bool,
+    overtype: bool,
+    caret_visible: bool,
+    bookmarks: Vec<usize>,
+    block_selection: Option<BlockSelection>,
+    show_whitespace: bool,
+    show_indent_guides: bool,
+    highlight_current_line: bool,
+}
 
 #[derive(Debug, Clone, Copy)]
 pub enum Indentation {
@@ -31,6 +60,29 @@ pub enum LineFeed {
     CRLF,
 }
 
+/// How the caret is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorStyle {
+    /// a thin vertical line before the char
+    Bar,
+    /// a translucent rectangle over the whole char
+    Block,
+    /// a thin line under the char
+    Underline,
+}
+
+impl CursorStyle {
+    /// return the caret rectangle as (y offset from the top of the line, width, height),
+    /// for a char of the given width
+    fn rect(self, char_width: f32, line_height: f32) -> (f32, f32, f32) {
+        match self {
+            CursorStyle::Bar => (0.0, 2.0, line_height),
+            CursorStyle::Block => (0.0, char_width, line_height),
+            CursorStyle::Underline => (line_height - 2.0, char_width, 2.0),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Direction {
     Up,
@@ -44,16 +96,26 @@ struct State {
     buffer: Buffer,
     cursor: Cursor,
 }
+/// Kind of the edits that can be grouped in a single undo step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EditKind {
+    Insert,
+    Delete,
+}
+
 #[derive(Debug)]
 struct UndoStack {
     stack: Vec<State>,
     index: usize,
+    // kind of the current group and cursor index at the end of its last edit
+    group: Option<(EditKind, usize)>,
 }
 impl UndoStack {
     pub fn new() -> Self {
         UndoStack {
             stack: Vec::new(),
             index: 0,
+            group: None,
         }
     }
     pub fn is_on_top(&self) -> bool {
@@ -63,11 +125,30 @@ impl UndoStack {
         self.stack.truncate(self.index);
         self.stack.push(state.clone());
         self.index += 1;
+        self.group = None;
+    }
+    /// return true if an edit of the given kind at the given index belongs to the current group
+    pub fn continues_group(&self, kind: EditKind, index: usize) -> bool {
+        self.group == Some((kind, index))
     }
     pub fn push_only(&mut self, state: &State) {
         self.stack.push(state.clone());
     }
+    /// number of states that can be undone
+    pub fn undo_depth(&self) -> usize {
+        self.index
+    }
+    /// number of states that can be redone. When an undo is done from the top,
+    /// the current state is pushed on the stack so it can be redone
+    pub fn redo_depth(&self) -> usize {
+        if self.is_on_top() {
+            0
+        } else {
+            self.stack.len() - self.index - 1
+        }
+    }
     pub fn undo(&mut self) -> Option<State> {
+        self.group = None;
         if self.index == 0 {
             println!("undo stack empty");
             None
@@ -77,7 +158,8 @@ impl UndoStack {
         }
     }
     pub fn redo(&mut self) -> Option<State> {
-        if self.index >= self.stack.len() - 1 {
+        self.group = None;
+        if self.redo_depth() == 0 {
             println!("undo stack empty [redo]");
             None
         } else {
@@ -88,6 +170,59 @@ impl UndoStack {
     }
 }
 
+/// A cursor position left by a jump
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Jump {
+    index: usize,
+    line: usize,
+}
+
+/// The positions left by the large cursor moves, to go back and forth through them
+#[derive(Debug, Default)]
+struct JumpList {
+    jumps: Vec<Jump>,
+    // position in jumps while going back, jumps.len() when not
+    index: usize,
+}
+impl JumpList {
+    /// record the position left by a jump. The positions ahead are dropped, and a position
+    /// on the same line as the last one replaces it
+    fn push(&mut self, jump: Jump) {
+        self.jumps.truncate(self.index);
+        if self.jumps.last().map_or(false, |j| j.line == jump.line) {
+            self.jumps.pop();
+        }
+        self.jumps.push(jump);
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.index = self.jumps.len();
+    }
+    /// return the previous position. The current one is recorded first, to come back to it
+    fn back(&mut self, current: Jump) -> Option<usize> {
+        if self.jumps.is_empty() {
+            return None;
+        }
+        if self.index == self.jumps.len() {
+            self.push(current);
+            self.index = self.jumps.len() - 1;
+        }
+        if self.index == 0 {
+            return None;
+        }
+        self.index -= 1;
+        Some(self.jumps[self.index].index)
+    }
+    /// return the next position, after going back
+    fn forward(&mut self) -> Option<usize> {
+        if self.index + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.jumps[self.index].index)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 struct Selection {
     start: usize,
@@ -98,13 +233,6 @@ impl Selection {
     fn new(start: usize, end: usize) -> Self {
         Selection { start, end }
     }
-    fn contains(&self, index: usize) -> bool {
-        if self.start <= self.end {
-            self.start <= index && self.end > index
-        } else {
-            self.end <= index && self.start > index
-        }
-    }
     fn expand(&mut self, index: usize) {
         self.end = index;
     }
@@ -130,6 +258,50 @@ impl Into<Range<usize>> for Selection {
     }
 }
 
+/// A rectangular selection between two corners, given as (line, visual column).
+/// The anchor is the corner where the selection started, the head follows the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlockSelection {
+    anchor: (usize, usize),
+    head: (usize, usize),
+}
+
+impl BlockSelection {
+    fn new(anchor: (usize, usize), head: (usize, usize)) -> Self {
+        BlockSelection { anchor, head }
+    }
+    /// the first and the last line of the block
+    fn lines(&self) -> (usize, usize) {
+        use std::cmp::{max, min};
+        (min(self.anchor.0, self.head.0), max(self.anchor.0, self.head.0))
+    }
+    /// the visual columns covered by the block
+    fn columns(&self) -> Range<usize> {
+        use std::cmp::{max, min};
+        min(self.anchor.1, self.head.1)..max(self.anchor.1, self.head.1)
+    }
+    /// return the selected chars of the given line. A line ending before the left column
+    /// is not part of the block, a line ending before the right column is selected up to its end
+    fn line_range(&self, line: usize, buffer: &Rc<RefCell<Buffer>>) -> Option<Range<usize>> {
+        let cols = self.columns();
+        let to_index = |col| {
+            let idx: Index = Point {
+                line,
+                col,
+                buffer: buffer.clone(),
+            }
+            .into();
+            idx
+        };
+        let start = to_index(cols.start);
+        let reached: Point = start.clone().into();
+        if reached.col < cols.start {
+            return None;
+        }
+        Some(start.index..to_index(cols.end).index)
+    }
+}
+
 // #[derive(Debug, Clone, Copy, Default)]
 // struct Cursor {
 //     index: usize,
@@ -192,11 +364,122 @@ pub struct View<'a> {
     buffer: Rc<RefCell<Buffer>>,
     cursor: Cursor,
     selection: Option<Selection>,
+    // a rectangular selection, used in place of the selection
+    block_selection: Option<BlockSelection>,
     undo_stack: UndoStack,
+    jump_list: JumpList,
     linefeed: LineFeed,
     geometry: Geometry,
     viewport: Viewport,
     styling: Option<StylingCache<'a>>,
+    search_query: Option<String>,
+    // the query compiled once, when it is a valid regular expression
+    search_regex: Option<Regex>,
+    search_matches: Vec<Range<usize>>,
+    show_line_numbers: bool,
+    // the spaces, tabs and line endings are marked with a faint glyph
+    show_whitespace: bool,
+    // a thin line is drawn at each indentation level of the leading whitespace
+    show_indent_guides: bool,
+    tab_width: usize,
+    use_spaces: bool,
+    trim_on_save: bool,
+    final_newline_on_save: bool,
+    comment_prefix: String,
+    // typed chars replace the char under the cursor
+    overtype: bool,
+    // lines kept visible above and below the cursor
+    scroll_margin: usize,
+    // blank lines that can be shown after the last line
+    scroll_past_end: usize,
+    // a selected line end is highlighted up to the right side of the view
+    selection_to_line_end: bool,
+    // the background of the cursor line is highlighted
+    highlight_current_line: bool,
+    // a vertical line is drawn before this column
+    ruler_column: Option<usize>,
+    // chars typed in a block selection are also inserted on the lines too short to reach it
+    pad_block_lines: bool,
+    // false while the blinking caret is off
+    caret_visible: bool,
+    cursor_style: CursorStyle,
+}
+
+/// return the regular expression of a search query starting with a '/', or None for a plain text query
+fn regex_pattern(query: &str) -> Option<&str> {
+    if query.len() > 1 && query.starts_with('/') {
+        Some(&query[1..])
+    } else {
+        None
+    }
+}
+
+/// return the range of the word containing index, or ending at index
+fn word_range_at(b: &Buffer, index: usize) -> Option<Range<usize>> {
+    let is_word_at = |i: usize| i < b.len_chars() && is_word_char(b.char(i));
+    let mut start = if is_word_at(index) {
+        index
+    } else if index > 0 && is_word_at(index - 1) {
+        index - 1
+    } else {
+        return None;
+    };
+    while start > 0 && is_word_at(start - 1) {
+        start -= 1;
+    }
+    let mut end = start;
+    while is_word_at(end) {
+        end += 1;
+    }
+    Some(start..end)
+}
+
+/// return the range of the line containing index, including its line feed
+fn line_range_at(b: &Buffer, index: usize) -> Range<usize> {
+    let line = b.char_to_line(index);
+    let start = b.line_to_char(line);
+    start..start + b.line_len(line)
+}
+
+/// return the index of the bracket matching the one at index, skipping nested pairs
+fn matching_bracket(b: &Buffer, index: usize) -> Option<usize> {
+    if index >= b.len_chars() {
+        return None;
+    }
+    let c = b.char(index);
+    let (open, close, forward) = match c {
+        '(' => ('(', ')', true),
+        '[' => ('[', ']', true),
+        '{' => ('{', '}', true),
+        ')' => ('(', ')', false),
+        ']' => ('[', ']', false),
+        '}' => ('{', '}', false),
+        _ => return None,
+    };
+    let mut depth = 0;
+    let mut i = index;
+    loop {
+        let c = b.char(i);
+        if c == open {
+            depth += if forward { 1 } else { -1 };
+        } else if c == close {
+            depth += if forward { -1 } else { 1 };
+        }
+        if depth == 0 {
+            return Some(i);
+        }
+        if forward {
+            i += 1;
+            if i == b.len_chars() {
+                return None;
+            }
+        } else {
+            if i == 0 {
+                return None;
+            }
+            i -= 1;
+        }
+    }
 }
 
 impl<'a> View<'a> {
@@ -206,11 +489,36 @@ impl<'a> View<'a> {
             buffer: buffer.clone(),
             cursor: Cursor::new(buffer.clone()),
             selection: None,
+            block_selection: None,
             undo_stack: UndoStack::new(),
+            jump_list: JumpList::default(),
             linefeed: LineFeed::LF,
             geometry,
             viewport: Viewport::default(),
             styling: None,
+            search_query: None,
+            search_regex: None,
+            search_matches: Vec::new(),
+            show_line_numbers: false,
+            show_whitespace: false,
+            show_indent_guides: false,
+            tab_width: SETTINGS.read().unwrap().get("tabSize").unwrap(),
+            use_spaces: SETTINGS.read().unwrap().get("indentWithSpace").unwrap(),
+            trim_on_save: SETTINGS.read().unwrap().get("trimTrailingWhitespace").unwrap(),
+            final_newline_on_save: SETTINGS.read().unwrap().get("ensureFinalNewline").unwrap(),
+            comment_prefix: SETTINGS.read().unwrap().get("commentPrefix").unwrap(),
+            overtype: false,
+            scroll_margin: SETTINGS.read().unwrap().get("scrollMargin").unwrap(),
+            scroll_past_end: SETTINGS.read().unwrap().get("scrollPastEnd").unwrap(),
+            selection_to_line_end: SETTINGS.read().unwrap().get("selectionToLineEnd").unwrap(),
+            highlight_current_line: false,
+            ruler_column: match SETTINGS.read().unwrap().get("rulerColumn").unwrap() {
+                0 => None,
+                col => Some(col),
+            },
+            pad_block_lines: SETTINGS.read().unwrap().get("padBlockSelection").unwrap(),
+            caret_visible: true,
+            cursor_style: CursorStyle::Bar,
         };
         v.relayout(geometry);
         v.detect_linefeed();
@@ -219,6 +527,12 @@ impl<'a> View<'a> {
 
     /// save the underlying buffer to disk
     pub fn save(&mut self) -> io::Result<()> {
+        if self.trim_on_save {
+            self.trim_trailing_whitespace();
+        }
+        if self.final_newline_on_save {
+            self.ensure_trailing_newline();
+        }
         {
             let mut b = self.buffer.borrow_mut();
             if b.get_filename().is_some() {
@@ -234,6 +548,11 @@ impl<'a> View<'a> {
         Ok(())
     }
 
+    /// return the underlying buffer
+    pub fn get_buffer(&self) -> Rc<RefCell<Buffer>> {
+        self.buffer.clone()
+    }
+
     /// return the number of line visible on screen
     pub fn page_length(&self) -> usize {
         self.viewport.heigth
@@ -242,12 +561,99 @@ impl<'a> View<'a> {
     /// resize the view and update the viewport accordingly
     pub fn relayout(&mut self, geometry: Geometry) {
         self.geometry = geometry;
-        self.viewport.heigth = (self.geometry.h / self.geometry.font_height) as usize - 1;
-        self.viewport.width = (self.geometry.w / self.geometry.font_advance) as usize - 1;
+        // a view smaller than a line still shows one line
+        self.viewport.heigth = ((self.geometry.h / self.geometry.font_height) as usize)
+            .saturating_sub(1)
+            .max(1);
+        self.update_viewport_width();
         let end = self.viewport.line_end();
         self.expand_styling_cache(end);
     }
 
+    fn update_viewport_width(&mut self) {
+        let columns = (self.geometry.w / self.geometry.font_advance) as usize;
+        self.viewport.width = columns.saturating_sub(self.gutter_width() + 1).max(1);
+    }
+
+    /// set the number of lines kept visible above and below the cursor,
+    /// it is limited to half the page
+    pub fn set_scroll_margin(&mut self, margin: usize) {
+        self.scroll_margin = margin;
+        self.focus_on_cursor();
+    }
+
+    /// return the x position of the ruler, or None if there is no ruler or if it is scrolled out of the view
+    fn ruler_x(&self) -> Option<f32> {
+        let col = self.ruler_column?;
+        if col < self.viewport.col_start || col > self.viewport.col_end() {
+            return None;
+        }
+        let gutter = self.geometry.x + self.gutter_width() as f32 * self.geometry.font_advance;
+        Some(gutter + (col - self.viewport.col_start) as f32 * self.geometry.font_advance)
+    }
+
+    /// return the x positions of the indentation guides of a line, the visible multiples of tabsize
+    /// in its leading whitespace. Tabs and spaces can be mixed, a tab goes to the next tab stop.
+    /// tabsize must be the one the text is drawn with for the guides to line up with it
+    fn indent_guides(&self, line: usize, tabsize: usize) -> Vec<f32> {
+        let b = self.buffer.borrow();
+        let mut indent = 0;
+        for c in b.line(line).chars() {
+            match c {
+                ' ' => indent += 1,
+                '\t' => indent += spaces_to_next_tab_stop(indent, tabsize),
+                _ => break,
+            }
+        }
+        let gutter = self.geometry.x + self.gutter_width() as f32 * self.geometry.font_advance;
+        (0..indent)
+            .step_by(tabsize)
+            .filter(|col| *col >= self.viewport.col_start && *col <= self.viewport.col_end())
+            .map(|col| gutter + (col - self.viewport.col_start) as f32 * self.geometry.font_advance)
+            .collect()
+    }
+
+    /// highlight the background of the line containing the cursor
+    pub fn set_highlight_current_line(&mut self, highlight: bool) {
+        self.highlight_current_line = highlight;
+    }
+
+    /// change how the caret is drawn, it is always a block in overtype mode
+    pub fn set_cursor_style(&mut self, style: CursorStyle) {
+        self.cursor_style = style;
+    }
+
+    /// show or hide the caret, to make it blink
+    pub fn set_caret_visible(&mut self, visible: bool) {
+        self.caret_visible = visible;
+    }
+
+    /// show or hide the whitespace markers
+    pub fn set_show_whitespace(&mut self, show: bool) {
+        self.show_whitespace = show;
+    }
+
+    /// show or hide the indentation guides
+    pub fn set_show_indent_guides(&mut self, show: bool) {
+        self.show_indent_guides = show;
+    }
+
+    /// show or hide the line numbers gutter
+    pub fn set_show_line_numbers(&mut self, show: bool) {
+        self.show_line_numbers = show;
+        self.update_viewport_width();
+        self.focus_on_cursor();
+    }
+
+    /// return the width of the line numbers gutter, in columns
+    pub fn gutter_width(&self) -> usize {
+        if self.show_line_numbers {
+            self.buffer.borrow().len_lines().to_string().len() + 1
+        } else {
+            0
+        }
+    }
+
     fn get_state(&self) -> State {
         State {
             buffer: self.buffer.borrow().clone(),
@@ -260,6 +666,24 @@ impl<'a> View<'a> {
         self.undo_stack.push(&state);
     }
 
+    /// save the state before an edit of the given kind, unless the edit continues the current undo group.
+    /// A group is a run of edits of the same kind, without cursor move in between
+    fn push_grouped_state(&mut self, kind: EditKind) {
+        if self.selection.is_some() || !self.undo_stack.continues_group(kind, self.cursor.get_index()) {
+            self.push_state();
+        }
+    }
+
+    /// extend the current undo group up to the cursor, after an edit of the given kind
+    fn extend_undo_group(&mut self, kind: EditKind) {
+        self.undo_stack.group = Some((kind, self.cursor.get_index()));
+    }
+
+    /// end the current undo group, the next edit will be undone separately
+    pub fn break_undo_group(&mut self) {
+        self.undo_stack.group = None;
+    }
+
     /// return the file extension or None if there is no file attached to the buffer
     pub fn get_extension(&self) -> Option<String> {
         self.buffer
@@ -295,10 +719,27 @@ impl<'a> View<'a> {
         self.buffer.borrow().get_encoding().name()
     }
 
+    /// reload the buffer from its file, discarding the unsaved changes.
+    /// The cursor stays on the same line and column, clamped to the new content
+    pub fn reload(&mut self) -> io::Result<()> {
+        let (line, col) = (self.line_idx(), self.col_idx());
+        let state = self.get_state();
+        self.buffer.borrow_mut().reload()?;
+        self.undo_stack.push(&state);
+        self.clear_selection();
+        self.set_cursor_point(line, col);
+        self.focus_on_cursor();
+
+        let end = self.buffer.borrow().len_lines();
+        self.update_styling_cache(0..end);
+        Ok(())
+    }
+
     fn update_styling_cache(&mut self, r: Range<usize>) {
         if let Some(ref mut style) = self.styling {
             style.update(r, &self.buffer.borrow());
         }
+        self.update_search_matches();
     }
     fn expand_styling_cache(&mut self, end: usize) {
         if let Some(ref mut style) = self.styling {
@@ -330,707 +771,3918 @@ impl<'a> View<'a> {
     //     self.update_styling_cache(start..end);
     // }
 
-    /// insert the given char at the cursor position
+    /// insert the given char at the cursor position, or on every line of the block selection
     pub fn insert_char(&mut self, ch: char) {
+        if self.block_selection.is_some() && ch != '\r' && ch != '\n' {
+            self.edit_block(Some(ch), false);
+            return;
+        }
         let start = self.line_idx();
-        self.push_state();
+        if ch == '\r' || ch == '\n' {
+            self.push_state();
+        } else {
+            self.push_grouped_state(EditKind::Insert);
+        }
 
         if let Some(r) = self.selection {
             self.cursor.set_index(r.lower());
             self.buffer.borrow_mut().remove(r);
+        } else if self.overtype && ch != '\r' && ch != '\n' {
+            // replace the char under the cursor, unless at the end of the line
+            let index = self.cursor.get_index();
+            let line_end = self.buffer.borrow().line_to_last_char(self.line_idx());
+            if index < line_end {
+                self.buffer.borrow_mut().remove(index..index + 1);
+            }
         }
         self.buffer.borrow_mut().insert_char(self.cursor.get_index(), ch);
 
         self.cursor_right();
         self.clear_selection();
+        if ch != '\r' && ch != '\n' {
+            self.extend_undo_group(EditKind::Insert);
+        }
         self.focus_on_cursor();
 
         let end = self.viewport.line_end();
         self.update_styling_cache(start..end);
     }
 
-    pub fn insert_linefeed(&mut self) {
-        match self.linefeed {
-            LineFeed::CRLF => self.insert("\r\n"),
-            LineFeed::CR => self.insert_char('\r'),
-            LineFeed::LF => self.insert_char('\n'),
-        }
+    /// switch between the insert and the overtype mode
+    pub fn toggle_overtype(&mut self) {
+        self.overtype = !self.overtype;
     }
 
-    /// insert the given string at the cursor position
-    pub fn insert(&mut self, text: &str) {
-        let start = self.line_idx();
-        self.push_state();
+    /// return true if the typed chars replace the char under the cursor
+    pub fn is_overtype(&self) -> bool {
+        self.overtype
+    }
 
-        if let Some(r) = self.selection {
-            self.cursor.set_index(r.lower());
-            self.buffer.borrow_mut().remove(r);
-        }
-        self.buffer.borrow_mut().insert(self.cursor.get_index(), &text);
-        self.cursor.set_index(self.cursor.get_index() + text.chars().count());
+    /// run f on the buffer in a single transaction, it is undone in one step.
+    /// The selection is cleared and the cursor is kept in the buffer
+    pub fn edit<F: FnOnce(&mut Buffer)>(&mut self, f: F) {
+        self.push_state();
+        self.buffer.borrow_mut().edit(f);
+        let len = self.buffer.borrow().len_chars();
+        let index = std::cmp::min(self.cursor.get_index(), len);
+        self.cursor.set_index(index);
         self.clear_selection();
         self.focus_on_cursor();
+        let end = self.buffer.borrow().len_lines();
+        self.update_styling_cache(0..end);
+    }
 
+    /// insert the open and close chars, the cursor is put between them
+    pub fn insert_pair(&mut self, open: char, close: char) {
+        self.insert_char(open);
+        self.buffer.borrow_mut().insert_char(self.cursor.get_index(), close);
+        let start = self.line_idx();
         let end = self.viewport.line_end();
         self.update_styling_cache(start..end);
     }
 
-    /// delete the charater directly to the left of cursor
-    pub fn backspace(&mut self) {
-        let start = self.line_idx();
+    /// put the open and close chars around the selection, the selection still covers the same text
+    pub fn wrap_selection(&mut self, open: char, close: char) {
+        let r: Range<usize> = match self.selection {
+            Some(selection) => selection.into(),
+            None => return,
+        };
         self.push_state();
-        if let Some(r) = self.selection {
-            let mut b = self.buffer.borrow_mut();
-            self.cursor.set_index(r.lower());
-            b.remove(r);
-        } else if self.cursor.get_index() > 0 {
-            self.cursor_left();
-            let mut b = self.buffer.borrow_mut();
-            b.remove(self.cursor.get_index()..self.cursor.get_previous_index());
-        }
-        self.clear_selection();
+        self.buffer.borrow_mut().edit(|b| {
+            b.insert_char(r.end, close);
+            b.insert_char(r.start, open);
+        });
+        self.shift_selection(|i, _| i + 1);
         self.focus_on_cursor();
-
+        let start = self.buffer.borrow().char_to_line(r.start);
         let end = self.viewport.line_end();
         self.update_styling_cache(start..end);
     }
 
-    /// delete the charater under the cursor
-    pub fn delete_at_cursor(&mut self) {
-        let start = self.line_idx();
-        self.push_state();
-        if let Some(r) = self.selection {
-            self.cursor.set_index(r.lower());
-            self.buffer.borrow_mut().remove(r);
-        } else if self.cursor.get_index() < self.buffer.borrow().len_chars() {
-            let curs = self.cursor.get_index();
-            self.cursor_right();
-            self.buffer
-                .borrow_mut()
-                .remove(self.cursor.get_previous_index()..self.cursor.get_index());
-            self.cursor.set_index(curs);
+    /// return the char under the cursor, None at the end of the buffer
+    pub fn char_at_cursor(&self) -> Option<char> {
+        let b = self.buffer.borrow();
+        let index = self.cursor.get_index();
+        if index < b.len_chars() {
+            Some(b.char(index))
+        } else {
+            None
         }
-        self.clear_selection();
-        self.focus_on_cursor();
-        let end = self.viewport.line_end();
-        self.update_styling_cache(start..end);
     }
 
-    /// return a newly allocated string of the buffer
-    pub fn to_string(&self) -> String {
-        self.buffer.borrow().to_string()
+    /// return the char before the cursor, None at the start of the buffer
+    pub fn char_before_cursor(&self) -> Option<char> {
+        let index = self.cursor.get_index();
+        if index > 0 {
+            Some(self.buffer.borrow().char(index - 1))
+        } else {
+            None
+        }
     }
 
-    /// undo the last action
-    pub fn undo(&mut self) {
-        if self.undo_stack.is_on_top() && !self.undo_stack.stack.is_empty() {
-            // push the current state in case we redo
-            let st = self.get_state();
-            self.undo_stack.push_only(&st);
+    pub fn insert_linefeed(&mut self) {
+        match self.linefeed {
+            LineFeed::CRLF => self.insert("\r\n"),
+            LineFeed::CR => self.insert_char('\r'),
+            LineFeed::LF => self.insert_char('\n'),
         }
-        if let Some(state) = self.undo_stack.undo() {
-            self.buffer.replace(state.buffer);
-            self.cursor = state.cursor;
+    }
+
+    /// insert a tabulation, or spaces up to the next tab stop when use_spaces is set
+    pub fn insert_tab(&mut self) {
+        if self.use_spaces {
+            let n = spaces_to_next_tab_stop(self.col_idx(), self.tab_width);
+            self.insert(&" ".repeat(n));
+        } else {
+            self.insert_char('\t');
         }
+    }
+
+    /// add one level of indentation to every line intersecting the selection
+    pub fn indent_selection(&mut self) {
+        let lines = self.selected_lines();
+        let unit = if self.use_spaces {
+            " ".repeat(self.tab_width)
+        } else {
+            "\t".to_owned()
+        };
+        let n = unit.chars().count();
+        let starts: Vec<usize> = lines.clone().map(|l| self.buffer.borrow().line_to_char(l)).collect();
+
+        self.push_state();
+        self.buffer.borrow_mut().edit(|b| {
+            for start in starts.iter().rev() {
+                b.insert(*start, &unit);
+            }
+        });
+        self.shift_selection(|i, upper| i + n * starts.iter().filter(|s| **s < i || (upper && **s == i)).count());
         self.focus_on_cursor();
-        let start = self.line_idx();
         let end = self.viewport.line_end();
-        self.update_styling_cache(start..end);
+        self.update_styling_cache(lines.start..end);
     }
 
-    /// redo the last undo action
-    pub fn redo(&mut self) {
-        if let Some(state) = self.undo_stack.redo() {
-            self.buffer.replace(state.buffer);
-            self.cursor = state.cursor;
+    /// remove one level of indentation from every line intersecting the selection
+    pub fn dedent_selection(&mut self) {
+        let lines = self.selected_lines();
+        // start and length of the indentation removed on each line
+        let removed: Vec<(usize, usize)> = {
+            let b = self.buffer.borrow();
+            lines
+                .clone()
+                .map(|l| {
+                    let mut chars = b.chars_on_line(l).peekable();
+                    let n = if chars.peek() == Some(&'\t') {
+                        1
+                    } else {
+                        chars.take(self.tab_width).take_while(|c| *c == ' ').count()
+                    };
+                    (b.line_to_char(l), n)
+                })
+                .filter(|(_, n)| *n > 0)
+                .collect()
+        };
+        if removed.is_empty() {
+            return;
         }
+
+        self.push_state();
+        self.buffer.borrow_mut().edit(|b| {
+            for (start, n) in removed.iter().rev() {
+                b.remove(*start..*start + *n);
+            }
+        });
+        self.shift_selection(|i, _| {
+            i - removed
+                .iter()
+                .map(|(start, n)| std::cmp::min(*n, i.saturating_sub(*start)))
+                .sum::<usize>()
+        });
         self.focus_on_cursor();
-        let start = self.line_idx();
         let end = self.viewport.line_end();
-        self.update_styling_cache(start..end);
+        self.update_styling_cache(lines.start..end);
     }
 
-    /// return the currently selection
-    pub fn get_selection(&self) -> Option<String> {
-        match self.selection {
-            None => None,
-            Some(s) => Some(self.buffer.borrow().slice(s).to_string()),
+    /// comment every line intersecting the selection, or uncomment them if they are all commented.
+    /// The comment prefix is put after the indentation, blank lines are left untouched
+    pub fn toggle_comment(&mut self) {
+        let lines = self.selected_lines();
+        let prefix = self.comment_prefix.clone();
+        let marker = prefix.trim_end();
+        if marker.is_empty() {
+            return;
+        }
+        // position after the indentation of each non blank line, and the text following it
+        let starts: Vec<(usize, String)> = {
+            let b = self.buffer.borrow();
+            lines
+                .clone()
+                .filter_map(|l| {
+                    let indent = b.chars_on_line(l).take_while(|c| *c == ' ' || *c == '\t').count();
+                    if indent == b.line_len_no_eol(l) {
+                        return None;
+                    }
+                    let text = b.line_chars(l).skip(indent).take(prefix.chars().count()).collect();
+                    Some((b.line_to_char(l) + indent, text))
+                })
+                .collect()
+        };
+        if starts.is_empty() {
+            return;
         }
-    }
 
-    /// return the cursor position in line
-    pub fn line_idx(&self) -> usize {
-        self.cursor.get_line()
+        self.push_state();
+        if starts.iter().all(|(_, text)| text.starts_with(marker)) {
+            // start and length of the prefix removed on each line, its trailing space may be missing
+            let removed: Vec<(usize, usize)> = starts
+                .iter()
+                .map(|(start, text)| {
+                    let p = if text.starts_with(&prefix) { prefix.as_str() } else { marker };
+                    (*start, p.chars().count())
+                })
+                .collect();
+            self.buffer.borrow_mut().edit(|b| {
+                for (start, n) in removed.iter().rev() {
+                    b.remove(*start..*start + *n);
+                }
+            });
+            self.shift_selection(|i, _| {
+                i - removed
+                    .iter()
+                    .map(|(start, n)| std::cmp::min(*n, i.saturating_sub(*start)))
+                    .sum::<usize>()
+            });
+        } else {
+            let n = prefix.chars().count();
+            self.buffer.borrow_mut().edit(|b| {
+                for (start, _) in starts.iter().rev() {
+                    b.insert(*start, &prefix);
+                }
+            });
+            self.shift_selection(|i, upper| {
+                i + n * starts.iter().filter(|(s, _)| *s < i || (upper && *s == i)).count()
+            });
+        }
+        self.focus_on_cursor();
+        let end = self.viewport.line_end();
+        self.update_styling_cache(lines.start..end);
     }
 
-    /// return the cursor position in column
-    pub fn col_idx(&self) -> usize {
-        self.cursor.get_col()
+    /// set the text put before the lines commented by toggle_comment
+    pub fn set_comment_prefix(&mut self, prefix: &str) {
+        self.comment_prefix = prefix.to_owned();
     }
 
-    // /// return the cursor position in line,col corrdinate
-    // pub fn cursor_as_point(&self) -> (usize, usize) {
-    //     //self.buffer.borrow().index_to_point(self.cursor.get_index())
-    //     (self.cursor.get_line(), self.cursor.get_col())
-    // }
-
-    fn cursor_up(&mut self) {
-        // let b = self.buffer.borrow();
-        // let (mut l, c) = b.index_to_point(self.cursor.get_index());
-        // if l > 0 {
-        //     l -= 1
-        // };
-        // self.cursor.set_index(b.point_to_index(l, c));
-        self.cursor.up(1);
-    }
-    fn cursor_down(&mut self) {
-        // let b = self.buffer.borrow();
-        // let (mut l, c) = b.index_to_point(self.cursor.get_index());
-        // if l < b.len_lines() - 1 {
-        //     l += 1
-        // };
-        // self.cursor.set_index(b.point_to_index(l, c));
-        self.cursor.down(1);
+    /// return the lines intersecting the selection, or the cursor line
+    fn selected_lines(&self) -> Range<usize> {
+        let b = self.buffer.borrow();
+        match self.selection {
+            Some(selection) => {
+                let r: Range<usize> = selection.into();
+                let first = b.char_to_line(r.start);
+                let mut last = b.char_to_line(r.end);
+                // a selection ending at the beginning of a line does not include it
+                if last > first && b.line_to_char(last) == r.end {
+                    last -= 1;
+                }
+                first..last + 1
+            }
+            None => {
+                let line = b.char_to_line(self.cursor.get_index());
+                line..line + 1
+            }
+        }
     }
-    fn cursor_left(&mut self) {
-        // let b = self.buffer.borrow();
-        // if self.cursor.index > 0 {
-        //     let line_idx = b.char_to_line(self.cursor.index);
-        //     let line_idx_char = b.line_to_char(line_idx);
-        //     // handle crlf and lf
-        //     if self.cursor.index == line_idx_char {
-        //         self.cursor.set(b.line_to_last_char(line_idx - 1));
-        //     } else {
-        //         self.cursor -= 1;
-        //     }
-        // }
-        self.cursor.left();
+
+    /// move the selection and the cursor after an edit. f map an old index to the new one,
+    /// its second parameter is true for the upper bound of the selection
+    fn shift_selection<F: Fn(usize, bool) -> usize>(&mut self, f: F) {
+        match self.selection {
+            Some(selection) => {
+                let forward = selection.start <= selection.end;
+                let r: Range<usize> = selection.into();
+                let (lower, upper) = (f(r.start, false), f(r.end, true));
+                if forward {
+                    self.selection = Some(Selection::new(lower, upper));
+                    self.cursor.set_index(upper);
+                } else {
+                    self.selection = Some(Selection::new(upper, lower));
+                    self.cursor.set_index(lower);
+                }
+            }
+            None => {
+                let i = f(self.cursor.get_index(), true);
+                self.cursor.set_index(i);
+            }
+        }
     }
-    fn cursor_right(&mut self) {
-        // let b = self.buffer.borrow();
-        // if self.cursor.index < b.len_chars() {
-        //     let line_idx = b.char_to_line(self.cursor.index);
-        //     let line_idx_char = b.line_to_last_char(line_idx);
-        //     // handle crlf and lf
-        //     if self.cursor.index == line_idx_char {
-        //         self.cursor.set(b.line_to_char(line_idx + 1));
-        //     } else {
-        //         self.cursor += 1;
-        //     }
-        // }
-        self.cursor.right();
+
+    /// select the whole buffer and move the cursor at its end
+    pub fn select_all(&mut self) {
+        let len = self.buffer.borrow().len_chars();
+        self.selection = Some(Selection::new(0, len));
+        self.cursor.set_index(len);
+        self.focus_on_cursor();
     }
 
-    /// move the cursor in the given direction
-    pub fn move_cursor(&mut self, dir: Direction, expand_selection: bool) {
-        match dir {
-            Direction::Up => self.cursor_up(),
-            Direction::Down => self.cursor_down(),
-            Direction::Right => self.cursor_right(),
-            Direction::Left => self.cursor_left(),
-        }
-        if expand_selection {
-            self.expand_selection();
+    /// extend both ends of the selection to the boundaries of the words they are in, the selection keeps its direction.
+    /// Without selection, or with an empty one, the word under the cursor is selected
+    pub fn expand_selection_to_words(&mut self) {
+        let selection = match self.selection {
+            Some(s) if s.start != s.end => s,
+            _ => {
+                self.select_word_under_cursor();
+                self.focus_on_cursor();
+                return;
+            }
+        };
+        let r: Range<usize> = selection.into();
+        let (start, end) = {
+            let b = self.buffer.borrow();
+            let is_word_at = |i: usize| i < b.len_chars() && is_word_char(b.char(i));
+            let mut start = r.start;
+            while start > 0 && is_word_at(start - 1) && is_word_at(start) {
+                start -= 1;
+            }
+            let mut end = r.end;
+            while end > 0 && is_word_at(end - 1) && is_word_at(end) {
+                end += 1;
+            }
+            (start, end)
+        };
+        if selection.start <= selection.end {
+            self.selection = Some(Selection::new(start, end));
+            self.cursor.set_index(end);
         } else {
-            self.clear_selection();
+            self.selection = Some(Selection::new(end, start));
+            self.cursor.set_index(start);
         }
         self.focus_on_cursor();
     }
 
-    /// move one page in the given direction
-    pub fn move_page(&mut self, dir: Direction, expand_selection: bool) {
-        for _ in 0..self.page_length() {
-            self.move_cursor(dir, expand_selection);
-        }
-        self.focus_on_cursor();
+    /// return true if some text is selected
+    pub fn has_selection(&self) -> bool {
+        self.selection.is_some()
     }
 
-    /// put the cursor at the begining of the line
-    pub fn home(&mut self, expand_selection: bool) {
-        // let l = self.line_idx();
-        // self.cursor.set(self.buffer.borrow().line_to_char(l));
-        self.cursor.goto_line_start();
-        if expand_selection {
-            self.expand_selection();
-        } else {
-            self.clear_selection();
+    /// remove the spaces and tabs at the end of every line.
+    /// The cursor stays on its line, clamped to the trimmed line length
+    pub fn trim_trailing_whitespace(&mut self) {
+        let (line, col) = (self.line_idx(), self.col_idx());
+        let state = self.get_state();
+        if self.buffer.borrow_mut().trim_trailing_whitespace() == 0 {
+            return;
         }
+        self.undo_stack.push(&state);
+        self.clear_selection();
+        self.set_cursor_point(line, col);
         self.focus_on_cursor();
+
+        let end = self.buffer.borrow().len_lines();
+        self.update_styling_cache(0..end);
     }
 
-    /// put the cursor at the end of the line
-    pub fn end(&mut self, expand_selection: bool) {
-        // let l = self.line_idx();
-        // self.cursor.set(self.buffer.borrow().line_to_last_char(l));
-        self.cursor.goto_line_end();
-        if expand_selection {
-            self.expand_selection();
-        } else {
-            self.clear_selection();
+    /// set if the trailing whitespace is removed when the buffer is saved
+    pub fn set_trim_on_save(&mut self, trim: bool) {
+        self.trim_on_save = trim;
+    }
+
+    /// append a line ending at the end of the buffer if it doesn't end with one
+    pub fn ensure_trailing_newline(&mut self) {
+        let state = self.get_state();
+        let len = self.buffer.borrow().len_chars();
+        self.buffer.borrow_mut().ensure_trailing_newline();
+        if self.buffer.borrow().len_chars() == len {
+            return;
         }
-        self.focus_on_cursor();
+        self.undo_stack.push(&state);
+        let end = self.buffer.borrow().len_lines();
+        self.update_styling_cache(end - 2..end);
     }
 
-    // /// return the cursor position in number of chars from the begining of the buffer
-    // pub fn index(&self) -> usize {
-    //     self.cursor.get_index()
-    // }
+    /// set if a line ending is appended to the buffer when it is saved
+    pub fn set_final_newline_on_save(&mut self, final_newline: bool) {
+        self.final_newline_on_save = final_newline;
+    }
 
-    // /// put the cursor at the given position
-    // pub fn set_index(&mut self, idx: usize) {
-    //     assert!(idx <= self.buffer.borrow().len_chars());
-    //     self.cursor.set(idx);
-    // }
+    /// set the number of columns between two tab stops
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = std::cmp::max(1, tab_width);
+    }
 
-    /// Set the cursor to the given pixel position
-    pub fn click(&mut self, x: i32, y: i32, expand_selection: bool) {
-        let col = x / self.geometry.font_advance as i32 + self.viewport.col_start as i32;
-        let line = y / self.geometry.font_height as i32 + self.viewport.line_start as i32;
+    /// set if the tab key insert spaces instead of a tabulation
+    pub fn set_use_spaces(&mut self, use_spaces: bool) {
+        self.use_spaces = use_spaces;
+    }
 
-        let p = crate::cursor::Point {
-            line: line as usize,
-            col: col as usize,
-            buffer: self.buffer.clone(),
+    /// insert a line feed followed by the indentation of the current line
+    pub fn insert_newline(&mut self) {
+        let line = self.line_idx();
+        let line_start = self.buffer.borrow().line_to_char(line);
+        let col = self.cursor.get_index() - line_start;
+        let (indent, blank) = {
+            let b = self.buffer.borrow();
+            let indent: String = b
+                .chars_on_line(line)
+                .take(col)
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect();
+            let blank = b.chars_on_line(line).all(char::is_whitespace);
+            (indent, blank)
         };
-        let idx: crate::cursor::Index = p.into();
-        self.cursor.set_index(idx.index);
-        if expand_selection {
-            self.expand_selection();
-        } else {
-            self.clear_selection();
-        }
-    }
 
-    /// select the word when double clicked
-    pub fn double_click(&mut self, x: i32, y: i32) {
-        self.select_word_under_cursor();
+        // a blank line is emptied, so its whitespace is not duplicated on each new line
+        if blank && self.selection.is_none() {
+            self.selection = Some(Selection::new(line_start, self.cursor.get_index()));
+        }
+        let mut text = self.linefeed_str().to_owned();
+        text.push_str(&indent);
+        self.insert(&text);
     }
 
-    /// Select the word under the cursor
-    pub fn select_word_under_cursor(&mut self) {
-        let line = self.buffer.borrow().char_to_line(self.cursor.get_index());
-        let mut start = self.buffer.borrow().line_to_char(line);
-        let mut end = start;
-        for c in self.buffer.borrow().chars_on_line(line) {
-            match c {
-                ' ' | '`' | '~' | '!' | '@' | '#' | '$' | '%' | '^' | '&' | '*' | '(' | ')' | '-' | '=' | '+' | '['
-                | '{' | ']' | '}' | '\\' | '|' | ';' | ':' | '\'' | '"' | ',' | '.' | '<' | '>' | '/' | '?' => {
-                    if start <= self.cursor.get_index() && self.cursor.get_index() < end {
-                        self.selection = Some(Selection { start, end });
-                        return;
-                    }
-                    start = end + 1;
-                }
-                _ => {}
-            }
-            end += 1;
+    fn linefeed_str(&self) -> &'static str {
+        match self.linefeed {
+            LineFeed::CRLF => "\r\n",
+            LineFeed::CR => "\r",
+            LineFeed::LF => "\n",
         }
-        self.selection = None;
     }
 
-    /// scroll the view in the given direction
-    pub fn scroll(&mut self, dir: Direction, amount: i32) {
-        for _ in 0..amount {
-            match dir {
-                Direction::Up => {
-                    if self.viewport.line_start > 0 {
-                        self.viewport.line_start -= 1;
-                    }
-                }
-                Direction::Down => {
-                    if self.viewport.line_start < self.buffer.borrow().len_lines() {
-                        self.viewport.line_start += 1;
-                    }
-                }
-                _ => (),
+    /// duplicate the selection, or the current line if nothing is selected.
+    /// The cursor is moved on the copy
+    pub fn duplicate(&mut self) {
+        let start = self.line_idx();
+        self.push_state();
+        if let Some(selection) = self.selection {
+            let r: Range<usize> = selection.into();
+            let text = self.buffer.borrow().slice(r.clone());
+            self.buffer.borrow_mut().insert(r.end, &text);
+            let end = r.end + r.len();
+            self.cursor.set_index(end);
+            self.selection = Some(Selection::new(r.end, end));
+        } else {
+            let index = self.cursor.get_index();
+            let mut b = self.buffer.borrow_mut();
+            let line_start = b.line_to_char(start);
+            let len = b.line_len(start);
+            let line = b.slice(line_start..line_start + len);
+            if start + 1 < b.len_lines() {
+                b.insert(line_start + len, &line);
+                drop(b);
+                self.cursor.set_index(index + len);
+            } else {
+                // the last line has no line feed
+                let text = format!("{}{}", self.linefeed_str(), line);
+                b.insert(line_start + len, &text);
+                drop(b);
+                self.cursor.set_index(index + text.chars().count());
             }
         }
+        self.focus_on_cursor();
+        let end = self.viewport.line_end();
+        self.update_styling_cache(start..end);
     }
 
-    /// Detect the carriage return type of the buffer
-    pub fn detect_linefeed(&mut self) {
-        #[cfg(target_os = "windows")]
-        let linefeed = LineFeed::CRLF;
-        #[cfg(not(target_os = "windows"))]
-        let linefeed = LineFeed::LF;
+    /// insert the given string at the cursor position
+    pub fn insert(&mut self, text: &str) {
+        let start = self.line_idx();
+        self.push_state();
 
-        let b = self.buffer.borrow();
-        if b.len_chars() == 0 {
-            self.linefeed = linefeed;
-            return;
+        if let Some(r) = self.selection {
+            self.cursor.set_index(r.lower());
+            self.buffer.borrow_mut().remove(r);
         }
+        self.buffer.borrow_mut().insert(self.cursor.get_index(), &text);
+        self.cursor.set_index(self.cursor.get_index() + text.chars().count());
+        self.clear_selection();
+        self.focus_on_cursor();
 
-        let mut cr = 0;
-        let mut lf = 0;
-        let mut crlf = 0;
+        let end = self.viewport.line_end();
+        self.update_styling_cache(start..end);
+    }
 
-        let mut chars = b.chars().take(1000);
-        while let Some(c) = chars.next() {
-            if c == '\r' {
-                if let Some(c2) = chars.next() {
-                    if c2 == '\n' {
-                        crlf += 1;
-                    } else {
-                        cr += 1;
-                    }
-                }
-            } else if c == '\n' {
-                lf += 1;
+    /// delete the line under the cursor with its line feed
+    pub fn delete_line(&mut self) {
+        let line = self.line_idx();
+        let (r, cursor) = {
+            let b = self.buffer.borrow();
+            let start = b.line_to_char(line);
+            if line + 1 < b.len_lines() {
+                (start..start + b.line_len(line), start)
+            } else if line > 0 {
+                // the last line has no line feed, remove the one of the previous line
+                let prev = b.line_to_char(line - 1);
+                (prev + b.line_len_no_eol(line - 1)..b.len_chars(), prev)
+            } else {
+                (0..b.len_chars(), 0)
             }
+        };
+        if r.start == r.end {
+            return;
         }
+        self.push_state();
+        self.buffer.borrow_mut().remove(r);
+        self.cursor.set_index(cursor);
+        self.clear_selection();
+        self.focus_on_cursor();
 
-        self.linefeed = if cr > crlf && cr > lf {
-            LineFeed::CR
-        } else if lf > crlf && lf > cr {
-            LineFeed::LF
-        } else {
-            LineFeed::CRLF
-        }
+        let start = self.line_idx();
+        let end = self.viewport.line_end();
+        self.update_styling_cache(start..end);
     }
 
-    pub fn detect_indentation(&self) -> Indentation {
-        // detect Tabs first. If the first char of a line is more often a Tab
-        // then we consider the indentation as tabulation.
-        let b = self.buffer.borrow();
-        let mut tab = 0;
-        let mut space = 0;
-        for line in b.lines() {
-            match line.chars().next() {
-                Some(' ') => space += 1,
-                Some('\t') => tab += 1,
-                _ => (),
-            }
+    /// replace the selected text with its transformation by f, the selection covers the new text
+    fn transform_selection<F: Fn(&str) -> String>(&mut self, f: F) {
+        let selection = match self.selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        let r: Range<usize> = selection.into();
+        let text = self.buffer.borrow().slice(r.clone());
+        let transformed = f(&text);
+        if transformed == text {
+            return;
         }
-        if tab > space {
-            let tabsize: u32 = SETTINGS.read().unwrap().get("tabSize").unwrap();
-            return Indentation::Tab(tabsize);
+        self.push_state();
+        self.buffer.borrow_mut().edit(|b| {
+            b.remove(r.clone());
+            b.insert(r.start, &transformed);
+        });
+        let end = r.start + transformed.chars().count();
+        if selection.start <= selection.end {
+            self.selection = Some(Selection::new(r.start, end));
+            self.cursor.set_index(end);
+        } else {
+            self.selection = Some(Selection::new(end, r.start));
+            self.cursor.set_index(r.start);
         }
+        self.focus_on_cursor();
+        let start = self.buffer.borrow().char_to_line(r.start);
+        let end = self.viewport.line_end();
+        self.update_styling_cache(start..end);
+    }
 
-        // Algorythm from
-        // https://medium.com/firefox-developer-tools/detecting-code-indentation-eff3ed0fb56b
-        use std::collections::HashMap;
-        let mut indents = HashMap::new();
-        let mut last = 0;
+    /// convert the selected text to upper case
+    pub fn uppercase_selection(&mut self) {
+        self.transform_selection(str::to_uppercase);
+    }
 
-        for line in b.lines() {
-            let width = line.chars().take_while(|c| *c == ' ').count();
-            let indent = (width as isize - last as isize).abs();
-            if indent > 1 {
-                let i = indents.entry(indent).or_insert(0);
-                *i += 1;
+    /// convert the selected text to lower case
+    pub fn lowercase_selection(&mut self) {
+        self.transform_selection(str::to_lowercase);
+    }
+
+    /// convert the first letter of each selected word to upper case, and the others to lower case
+    pub fn titlecase_selection(&mut self) {
+        self.transform_selection(|text| {
+            let mut result = String::with_capacity(text.len());
+            let mut word_start = true;
+            for c in text.chars() {
+                if word_start {
+                    result.extend(c.to_uppercase());
+                } else {
+                    result.extend(c.to_lowercase());
+                }
+                word_start = !c.is_alphanumeric();
             }
-            last = width;
-        }
-        if let Some(i) = indents.iter().max_by(|x,y| x.1.cmp(y.1)) {
-            println!("largest {}", i.0);
-            Indentation::Space(*i.0 as u32)
-        } else {
-            let tabsize: u32 = SETTINGS.read().unwrap().get("tabSize").unwrap();
-            Indentation::Space(tabsize)
-        }
+            result
+        });
     }
 
-    /// move the view so that the cursor is visible
-    pub fn focus_on_cursor(&mut self) {
-        use std::cmp::min;
-        let pagelen = self.viewport.heigth;
-        let l = self.line_idx();
-        if l < self.viewport.line_start {
-            self.viewport.line_start = l;
+    /// swap the char before the cursor with the char at the cursor, then move the cursor forward.
+    /// At the end of a line the two chars before the cursor are swapped, nothing is done at the start of a line
+    pub fn transpose_chars(&mut self) {
+        let line = self.line_idx();
+        let index = self.cursor.get_index();
+        let (i, swapped) = {
+            let b = self.buffer.borrow();
+            let start = b.line_to_char(line);
+            let len = b.line_len_no_eol(line);
+            let i = match index - start {
+                0 => return,
+                col if col == len && len < 2 => return,
+                col if col == len => index - 1,
+                _ => index,
+            };
+            let swapped: String = [b.char(i), b.char(i - 1)].iter().collect();
+            (i, swapped)
+        };
+        self.push_state();
+        self.buffer.borrow_mut().edit(|b| {
+            b.remove(i - 1..i + 1);
+            b.insert(i - 1, &swapped);
+        });
+        self.cursor.set_index(i + 1);
+        self.clear_selection();
+        self.focus_on_cursor();
+        self.update_styling_cache(line..line + 1);
+    }
+
+    /// swap the line under the cursor with the previous one
+    pub fn move_line_up(&mut self) {
+        let line = self.line_idx();
+        if line > 0 {
+            self.swap_lines(line - 1, line);
         }
-        if l > self.viewport.line_end() {
-            self.viewport.line_start = l - pagelen;
+    }
+
+    /// swap the line under the cursor with the next one
+    pub fn move_line_down(&mut self) {
+        let line = self.line_idx();
+        if line + 1 < self.buffer.borrow().len_lines() {
+            self.swap_lines(line, line + 1);
         }
-        {
+    }
+
+    /// swap the content of two consecutive lines, the line feeds are kept in place.
+    /// The cursor follows the line it was on
+    fn swap_lines(&mut self, first: usize, second: usize) {
+        let cursor_line = self.line_idx();
+        self.push_state();
+        let col = {
+            let mut b = self.buffer.borrow_mut();
+            let col = self.cursor.get_index() - b.line_to_char(cursor_line);
+            let start = b.line_to_char(first);
+            let first_end = start + b.line_len_no_eol(first);
+            let second_start = b.line_to_char(second);
+            let end = second_start + b.line_len_no_eol(second);
+
+            let mut text = String::with_capacity(end - start);
+            text.extend(b.slice_chars(second_start..end));
+            text.extend(b.slice_chars(first_end..second_start));
+            text.extend(b.slice_chars(start..first_end));
+            b.edit(|b| {
+                b.remove(start..end);
+                b.insert(start, &text);
+            });
+            col
+        };
+        let moved_to = if cursor_line == first { second } else { first };
+        let index = self.buffer.borrow().line_to_char(moved_to) + col;
+        self.cursor.set_index(index);
+        self.clear_selection();
+        self.focus_on_cursor();
+
+        let end = self.viewport.line_end();
+        self.update_styling_cache(first..end);
+    }
+
+    /// delete the charater directly to the left of cursor
+    pub fn backspace(&mut self) {
+        if self.block_selection.is_some() {
+            self.edit_block(None, true);
+            return;
+        }
+        let start = self.line_idx();
+        self.push_grouped_state(EditKind::Delete);
+        if let Some(r) = self.selection {
+            let mut b = self.buffer.borrow_mut();
+            self.cursor.set_index(r.lower());
+            b.remove(r);
+        } else if self.cursor.get_index() > 0 {
+            self.cursor_left();
+            let mut b = self.buffer.borrow_mut();
+            b.remove(self.cursor.get_index()..self.cursor.get_previous_index());
+        }
+        self.clear_selection();
+        self.extend_undo_group(EditKind::Delete);
+        self.focus_on_cursor();
+
+        let end = self.viewport.line_end();
+        self.update_styling_cache(start..end);
+    }
+
+    /// delete the charater under the cursor
+    pub fn delete_at_cursor(&mut self) {
+        if self.block_selection.is_some() {
+            self.edit_block(None, false);
+            return;
+        }
+        let start = self.line_idx();
+        self.push_grouped_state(EditKind::Delete);
+        if let Some(r) = self.selection {
+            self.cursor.set_index(r.lower());
+            self.buffer.borrow_mut().remove(r);
+        } else if self.cursor.get_index() < self.buffer.borrow().len_chars() {
+            let curs = self.cursor.get_index();
+            self.cursor_right();
+            self.buffer
+                .borrow_mut()
+                .remove(self.cursor.get_previous_index()..self.cursor.get_index());
+            self.cursor.set_index(curs);
+        }
+        self.clear_selection();
+        self.extend_undo_group(EditKind::Delete);
+        self.focus_on_cursor();
+        let end = self.viewport.line_end();
+        self.update_styling_cache(start..end);
+    }
+
+    /// delete from the cursor to the beginning of the word, like word_left would move it.
+    /// At the start of a line only the line feed before it is deleted. The selection is deleted if there is one
+    pub fn delete_word_backward(&mut self) {
+        if let Some(r) = self.selection {
+            self.delete_range(r.into());
+            return;
+        }
+        let mut target = self.cursor.clone();
+        target.word_left();
+        self.delete_range(target.get_index()..self.cursor.get_index());
+    }
+
+    /// delete from the cursor to the beginning of the next word, like word_right would move it.
+    /// At the end of a line only its line feed is deleted. The selection is deleted if there is one
+    pub fn delete_word_forward(&mut self) {
+        if let Some(r) = self.selection {
+            self.delete_range(r.into());
+            return;
+        }
+        let mut target = self.cursor.clone();
+        target.word_right();
+        self.delete_range(self.cursor.get_index()..target.get_index());
+    }
+
+    /// delete from the cursor to the end of the line. At the end of a line its line feed is deleted,
+    /// joining the next line
+    pub fn delete_to_line_end(&mut self) {
+        let index = self.cursor.get_index();
+        let end = {
             let b = self.buffer.borrow();
-            self.viewport.line_start = min(self.viewport.line_start, b.len_lines());
+            let line = self.line_idx();
+            let end = b.line_to_last_char(line);
+            if index < end {
+                end
+            } else {
+                b.line_to_char(line) + b.line_len(line)
+            }
+        };
+        self.delete_range(index..end);
+    }
+
+    /// delete from the start of the line to the cursor
+    pub fn delete_to_line_start(&mut self) {
+        let start = self.buffer.borrow().line_to_char(self.line_idx());
+        self.delete_range(start..self.cursor.get_index());
+    }
+
+    /// join the line under the cursor with the next one, or all the selected lines. The whitespace
+    /// around each joint is collapsed to a single space, or removed if one of the lines is blank.
+    /// Nothing is done on the last line
+    pub fn join_lines(&mut self) {
+        let lines = self.selected_lines();
+        let first = lines.start;
+        let joins = std::cmp::max(lines.len() - 1, 1);
+        if first + 1 >= self.buffer.borrow().len_lines() {
+            return;
         }
+        self.push_state();
+        let mut joint = 0;
+        self.buffer.borrow_mut().edit(|b| {
+            for _ in 0..joins {
+                if first + 1 >= b.len_lines() {
+                    break;
+                }
+                let start = b.line_to_char(first);
+                let mut end = b.line_to_last_char(first);
+                while end > start && (b.char(end - 1) == ' ' || b.char(end - 1) == '\t') {
+                    end -= 1;
+                }
+                let next_start = b.line_to_char(first + 1);
+                let indent = b.chars_on_line(first + 1).take_while(|c| *c == ' ' || *c == '\t').count();
+                let next_blank = indent == b.line_len_no_eol(first + 1);
+                b.remove(end..next_start + indent);
+                if end > start && !next_blank {
+                    b.insert_char(end, ' ');
+                }
+                joint = end;
+            }
+        });
+        self.cursor.set_index(joint);
+        self.clear_selection();
+        self.focus_on_cursor();
 
-        let pagewidth = self.viewport.width;
-        let c = self.col_idx();
-        if c < self.viewport.col_start {
-            self.viewport.col_start = c;
+        let end = self.viewport.line_end();
+        self.update_styling_cache(first..end);
+    }
+
+    /// reverse the order of the lines intersecting the selection. The line endings stay in place,
+    /// so the last line keeps having no line ending. The selection covers the same lines
+    pub fn reverse_lines(&mut self) {
+        let lines = self.selected_lines();
+        if lines.len() < 2 {
+            return;
+        }
+        let (start, end, text) = {
+            let b = self.buffer.borrow();
+            let start = b.line_to_char(lines.start);
+            let end = b.line_to_last_char(lines.end - 1);
+            let mut contents: Vec<String> = lines.clone().map(|l| b.line_str(l)).collect();
+            contents.reverse();
+            let mut text = String::new();
+            for (line, content) in lines.clone().zip(contents) {
+                text.push_str(&content);
+                if line + 1 < lines.end {
+                    text.push_str(&b.slice(b.line_to_last_char(line)..b.line_to_char(line + 1)));
+                }
+            }
+            (start, end, text)
+        };
+        self.push_state();
+        self.buffer.borrow_mut().edit(|b| {
+            b.remove(start..end);
+            b.insert(start, &text);
+        });
+        // the offsets of the old lines point in the middle of the reversed ones
+        let (start, end) = {
+            let b = self.buffer.borrow();
+            (b.line_to_char(lines.start), b.line_to_last_char(lines.end - 1))
+        };
+        self.selection = Some(Selection::new(start, end));
+        self.cursor.set_index(end);
+        self.focus_on_cursor();
+
+        let end = self.viewport.line_end();
+        self.update_styling_cache(lines.start..end);
+    }
+
+    /// remove the lines equal to the line just before them, in the selected lines or in the whole buffer,
+    /// like uniq. The line endings are not compared
+    pub fn dedupe_lines(&mut self) {
+        self.remove_duplicate_lines(false);
+    }
+
+    /// remove the lines equal to any line before them, in the selected lines or in the whole buffer
+    pub fn dedupe_all_lines(&mut self) {
+        self.remove_duplicate_lines(true);
+    }
+
+    fn remove_duplicate_lines(&mut self, global: bool) {
+        use std::collections::HashSet;
+        let lines = if self.selection.is_some() {
+            self.selected_lines()
+        } else {
+            0..self.buffer.borrow().len_lines()
+        };
+        let first = lines.start;
+        let duplicates: Vec<usize> = {
+            let b = self.buffer.borrow();
+            // the empty line after a final line feed is not a line of the text
+            let last = b.len_lines() - 1;
+            let lines = if last > 0 && b.line_len(last) == 0 {
+                lines.start..std::cmp::min(lines.end, last)
+            } else {
+                lines
+            };
+            let mut seen = HashSet::new();
+            let mut previous = None;
+            let mut duplicates = Vec::new();
+            for line in lines {
+                let text = b.line_str(line);
+                let duplicate = if global {
+                    !seen.insert(text.clone())
+                } else {
+                    previous.as_ref() == Some(&text)
+                };
+                if duplicate {
+                    duplicates.push(line);
+                }
+                previous = Some(text);
+            }
+            duplicates
+        };
+        if duplicates.is_empty() {
+            return;
+        }
+        self.push_state();
+        self.buffer.borrow_mut().edit(|b| {
+            // from the bottom, so the lines above keep their index
+            for line in duplicates.iter().rev() {
+                let start = b.line_to_char(*line);
+                let end = start + b.line_len(*line);
+                if end == b.len_chars() && b.line_len(*line) == b.line_len_no_eol(*line) && *line > 0 {
+                    // the last line has no line ending, the one before it is removed instead
+                    let previous_end = b.line_to_last_char(line - 1);
+                    b.remove(previous_end..end);
+                } else {
+                    b.remove(start..end);
+                }
+            }
+        });
+        let index = self.buffer.borrow().line_to_char(first);
+        self.cursor.set_index(index);
+        self.clear_selection();
+        self.focus_on_cursor();
+
+        let end = self.buffer.borrow().len_lines();
+        self.update_styling_cache(first..end);
+    }
+
+    /// delete the given range in a single undo step and put the cursor at its start
+    fn delete_range(&mut self, r: Range<usize>) {
+        if r.start == r.end {
+            return;
+        }
+        self.push_state();
+        self.buffer.borrow_mut().remove(r.clone());
+        self.cursor.set_index(r.start);
+        self.clear_selection();
+        self.focus_on_cursor();
+
+        let start = self.line_idx();
+        let end = self.viewport.line_end();
+        self.update_styling_cache(start..end);
+    }
+
+    /// return a newly allocated string of the buffer
+    pub fn to_string(&self) -> String {
+        self.buffer.borrow().to_string()
+    }
+
+    /// return true if there is an action to undo
+    pub fn can_undo(&self) -> bool {
+        self.undo_depth() > 0
+    }
+
+    /// return true if there is an undone action to redo
+    pub fn can_redo(&self) -> bool {
+        self.redo_depth() > 0
+    }
+
+    /// return the number of actions that can be undone
+    pub fn undo_depth(&self) -> usize {
+        self.undo_stack.undo_depth()
+    }
+
+    /// return the number of actions that can be redone
+    pub fn redo_depth(&self) -> usize {
+        self.undo_stack.redo_depth()
+    }
+
+    /// undo the last action
+    pub fn undo(&mut self) {
+        if !self.can_undo() {
+            return;
+        }
+        if self.undo_stack.is_on_top() {
+            // push the current state in case we redo
+            let st = self.get_state();
+            self.undo_stack.push_only(&st);
         }
-        if c > self.viewport.col_end() {
-            self.viewport.col_start = c - pagewidth;
+        if let Some(state) = self.undo_stack.undo() {
+            self.restore_buffer(state.buffer);
+            self.cursor = state.cursor;
         }
+        self.focus_on_cursor();
+        let start = self.line_idx();
+        let end = self.viewport.line_end();
+        self.update_styling_cache(start..end);
+    }
 
+    /// redo the last undo action
+    pub fn redo(&mut self) {
+        if !self.can_redo() {
+            return;
+        }
+        if let Some(state) = self.undo_stack.redo() {
+            self.restore_buffer(state.buffer);
+            self.cursor = state.cursor;
+        }
+        self.focus_on_cursor();
+        let start = self.line_idx();
         let end = self.viewport.line_end();
-        self.expand_styling_cache(end);
+        self.update_styling_cache(start..end);
     }
 
-    /// Draw the vew on the given screen
-    pub fn draw(&self, canvas: &mut Canvas) {
-        let adv = self.geometry.font_advance;
-        let line_spacing = self.geometry.font_height;
-        let mut y = line_spacing;
+    /// replace the buffer by a state of the undo stack. The bookmarks are not part of the history,
+    /// the current ones are kept
+    fn restore_buffer(&mut self, buffer: Buffer) {
+        let bookmarks: Vec<usize> = self.buffer.borrow().bookmarks().collect();
+        self.buffer.replace(buffer);
+        self.buffer.borrow_mut().set_bookmarks(bookmarks);
+    }
+
+    /// return the currently selection
+    pub fn get_selection(&self) -> Option<String> {
+        match self.selection {
+            None => None,
+            Some(s) => Some(self.buffer.borrow().slice(s)),
+        }
+    }
 
-        let tabsize: i32 = SETTINGS.read().unwrap().get("tabSize").unwrap();
+    /// return the cursor position in line
+    pub fn line_idx(&self) -> usize {
+        self.cursor.get_line()
+    }
 
-        let first_visible_line = self.viewport.line_start;
-        let first_visible_col = self.viewport.col_start;
-        let page_len = self.viewport.heigth;
+    /// return the cursor position in column
+    pub fn col_idx(&self) -> usize {
+        self.cursor.get_col()
+    }
 
-        let mut current_col = 0;
+    /// return the cursor position as (line, column), the column is counted in chars.
+    /// Unlike col_idx, a tab counts as a single column
+    pub fn cursor_point(&self) -> (usize, usize) {
+        self.buffer.borrow().index_to_point(self.cursor.get_index())
+    }
+
+    fn cursor_up(&mut self) {
+        // let b = self.buffer.borrow();
+        // let (mut l, c) = b.index_to_point(self.cursor.get_index());
+        // if l > 0 {
+        //     l -= 1
+        // };
+        // self.cursor.set_index(b.point_to_index(l, c));
+        self.cursor.up(1);
+    }
+    fn cursor_down(&mut self) {
+        // let b = self.buffer.borrow();
+        // let (mut l, c) = b.index_to_point(self.cursor.get_index());
+        // if l < b.len_lines() - 1 {
+        //     l += 1
+        // };
+        // self.cursor.set_index(b.point_to_index(l, c));
+        self.cursor.down(1);
+    }
+    fn cursor_left(&mut self) {
+        // let b = self.buffer.borrow();
+        // if self.cursor.index > 0 {
+        //     let line_idx = b.char_to_line(self.cursor.index);
+        //     let line_idx_char = b.line_to_char(line_idx);
+        //     // handle crlf and lf
+        //     if self.cursor.index == line_idx_char {
+        //         self.cursor.set(b.line_to_last_char(line_idx - 1));
+        //     } else {
+        //         self.cursor -= 1;
+        //     }
+        // }
+        self.cursor.left();
+    }
+    fn cursor_right(&mut self) {
+        // let b = self.buffer.borrow();
+        // if self.cursor.index < b.len_chars() {
+        //     let line_idx = b.char_to_line(self.cursor.index);
+        //     let line_idx_char = b.line_to_last_char(line_idx);
+        //     // handle crlf and lf
+        //     if self.cursor.index == line_idx_char {
+        //         self.cursor.set(b.line_to_char(line_idx + 1));
+        //     } else {
+        //         self.cursor += 1;
+        //     }
+        // }
+        self.cursor.right();
+    }
+
+    /// move the cursor in the given direction
+    pub fn move_cursor(&mut self, dir: Direction, expand_selection: bool) {
+        match dir {
+            Direction::Up => self.cursor_up(),
+            Direction::Down => self.cursor_down(),
+            Direction::Right => self.cursor_right(),
+            Direction::Left => self.cursor_left(),
+        }
+        if expand_selection {
+            self.expand_selection();
+        } else {
+            self.clear_selection();
+        }
+        self.focus_on_cursor();
+    }
+
+    /// move the cursor to the beginning of the previous word
+    pub fn move_word_left(&mut self, expand_selection: bool) {
+        self.cursor.word_left();
+        if expand_selection {
+            self.expand_selection();
+        } else {
+            self.clear_selection();
+        }
+        self.focus_on_cursor();
+    }
+
+    /// move the cursor to the beginning of the next word
+    pub fn move_word_right(&mut self, expand_selection: bool) {
+        self.cursor.word_right();
+        if expand_selection {
+            self.expand_selection();
+        } else {
+            self.clear_selection();
+        }
+        self.focus_on_cursor();
+    }
+
+    /// move one page in the given direction
+    pub fn move_page(&mut self, dir: Direction, expand_selection: bool) {
+        for _ in 0..self.page_length() {
+            self.move_cursor(dir, expand_selection);
+        }
+        self.focus_on_cursor();
+    }
+
+    /// return the index of the first non whitespace char of the line, or the index of the
+    /// line start if the cursor is already there or if the line is blank
+    fn smart_home_index(&self) -> usize {
+        let line = self.line_idx();
+        let b = self.buffer.borrow();
+        let start = b.line_to_char(line);
+        let indent = b.chars_on_line(line).take_while(|c| *c == ' ' || *c == '\t').count();
+        if indent == b.line_len_no_eol(line) || self.cursor.get_index() == start + indent {
+            start
+        } else {
+            start + indent
+        }
+    }
+
+    /// put the cursor on the first non whitespace char of the line,
+    /// or at the beginning of the line if it is already there
+    pub fn home(&mut self, expand_selection: bool) {
+        let index = self.smart_home_index();
+        self.cursor.set_index(index);
+        if expand_selection {
+            self.expand_selection();
+        } else {
+            self.clear_selection();
+        }
+        self.focus_on_cursor();
+    }
+
+    /// put the cursor at the end of the line
+    pub fn end(&mut self, expand_selection: bool) {
+        // let l = self.line_idx();
+        // self.cursor.set(self.buffer.borrow().line_to_last_char(l));
+        self.cursor.goto_line_end();
+        if expand_selection {
+            self.expand_selection();
+        } else {
+            self.clear_selection();
+        }
+        self.focus_on_cursor();
+    }
+
+    /// move the cursor to the given index, extending the selection from its anchor,
+    /// or from the cursor if nothing is selected
+    fn select_to(&mut self, index: usize) {
+        let anchor = self.selection.map_or(self.cursor.get_index(), |s| s.start);
+        self.cursor.set_index(index);
+        self.selection = Some(Selection::new(anchor, index));
+        self.focus_on_cursor();
+    }
+
+    /// extend the selection to the first non whitespace char of the line,
+    /// or to the beginning of the line if the cursor is already there
+    pub fn select_home(&mut self) {
+        let index = self.smart_home_index();
+        self.select_to(index);
+    }
+
+    /// extend the selection to the end of the line
+    pub fn select_end(&mut self) {
+        let index = self.buffer.borrow().line_to_last_char(self.line_idx());
+        self.select_to(index);
+    }
+
+    /// extend the selection to the beginning of the buffer
+    pub fn select_document_start(&mut self) {
+        self.select_to(0);
+    }
+
+    /// extend the selection to the end of the buffer
+    pub fn select_document_end(&mut self) {
+        let index = self.buffer.borrow().len_chars();
+        self.select_to(index);
+    }
+
+    /// return the cursor position in number of chars from the begining of the buffer
+    pub fn index(&self) -> usize {
+        self.cursor.get_index()
+    }
+
+    /// put the cursor at the given position, clamped to the buffer content
+    pub fn set_index(&mut self, idx: usize) {
+        self.cursor.set_index(idx);
+    }
+
+    /// Set the cursor to the given line and visual column, clamped to the buffer content
+    pub fn set_cursor_point(&mut self, line: usize, col: usize) {
+        let line = std::cmp::min(line, self.buffer.borrow().len_lines() - 1);
+        let p = crate::cursor::Point {
+            line,
+            col,
+            buffer: self.buffer.clone(),
+        };
+        let idx: crate::cursor::Index = p.into();
+        self.cursor.set_index(idx.index);
+    }
+
+    /// return the line and the visual column at the given pixel position, they can be past the end of the text
+    fn point_at(&self, x: i32, y: i32) -> (usize, usize) {
+        let x = std::cmp::max(0, x - (self.gutter_width() as f32 * self.geometry.font_advance) as i32);
+        let y = std::cmp::max(0, y);
+        let col = x / self.geometry.font_advance as i32 + self.viewport.col_start as i32;
+        let line = y / self.geometry.font_height as i32 + self.viewport.line_start as i32;
+        (line as usize, col as usize)
+    }
+
+    /// Set the cursor to the given pixel position
+    pub fn click(&mut self, x: i32, y: i32, expand_selection: bool) {
+        let (line, col) = self.point_at(x, y);
+        self.set_cursor_point(line, col);
+        if expand_selection {
+            self.expand_selection();
+        } else {
+            self.clear_selection();
+        }
+    }
+
+    /// Expand the selection to the given pixel position, scrolling when dragged outside of the view
+    pub fn drag(&mut self, x: i32, y: i32) {
+        let height = self.geometry.h as i32;
+        if y < 0 {
+            self.scroll(Direction::Up, 1);
+        } else if y >= height {
+            self.scroll(Direction::Down, 1);
+        }
+        self.click(x, std::cmp::min(y, height - 1), true);
+    }
+
+    /// Expand the block selection to the given pixel position, scrolling when dragged outside of the view
+    pub fn drag_block(&mut self, x: i32, y: i32) {
+        let height = self.geometry.h as i32;
+        if y < 0 {
+            self.scroll(Direction::Up, 1);
+        } else if y >= height {
+            self.scroll(Direction::Down, 1);
+        }
+        let (line, col) = self.point_at(x, std::cmp::min(y, height - 1));
+        self.select_block_to(line, col);
+    }
+
+    /// move the corner of the block selection following the cursor, starting a block at the cursor if there is none.
+    /// The corner can go past the end of the lines
+    pub fn move_block_selection(&mut self, dir: Direction) {
+        let (line, col) = match self.block_selection {
+            Some(block) => block.head,
+            None => (self.line_idx(), self.col_idx()),
+        };
+        let (line, col) = match dir {
+            Direction::Up => (line.saturating_sub(1), col),
+            Direction::Down => (line + 1, col),
+            Direction::Left => (line, col.saturating_sub(1)),
+            Direction::Right => (line, col + 1),
+        };
+        self.select_block_to(line, col);
+    }
+
+    /// return true if a block is selected
+    pub fn has_block_selection(&self) -> bool {
+        self.block_selection.is_some()
+    }
+
+    fn select_block_to(&mut self, line: usize, col: usize) {
+        let anchor = match self.block_selection {
+            Some(block) => block.anchor,
+            None => (self.line_idx(), self.col_idx()),
+        };
+        let line = std::cmp::min(line, self.buffer.borrow().len_lines() - 1);
+        self.set_cursor_point(line, col);
+        self.clear_selection();
+        self.block_selection = Some(BlockSelection::new(anchor, (line, col)));
+        self.focus_on_cursor();
+    }
+
+    /// replace the block selection with ch on every line, or delete it. When the block is empty, the char
+    /// before (backward) or after its column is deleted instead. The block becomes empty, after the edit.
+    /// The lines ending before the block are padded with spaces to insert ch if pad_block_lines is set
+    fn edit_block(&mut self, ch: Option<char>, backward: bool) {
+        let block = match self.block_selection {
+            Some(block) => block,
+            None => return,
+        };
+        let (first, last) = block.lines();
+        let last = std::cmp::min(last, self.buffer.borrow().len_lines() - 1);
+        let left = block.columns().start;
+        let empty = block.columns().is_empty();
+        let pad = ch.is_some() && self.pad_block_lines;
+        // the range replaced on each line, with the number of spaces inserted before ch
+        let edits: Vec<(Range<usize>, usize)> = (first..=last)
+            .filter_map(|line| match block.line_range(line, &self.buffer) {
+                Some(r) => Some((r, 0)),
+                None if pad => {
+                    let index = self.buffer.borrow().line_to_last_char(line);
+                    let end: Point = Index {
+                        index,
+                        buffer: self.buffer.clone(),
+                    }
+                    .into();
+                    Some((index..index, left - end.col))
+                }
+                None => None,
+            })
+            .collect();
+        if edits.is_empty() {
+            return;
+        }
+        self.push_state();
+        let col = {
+            let mut b = self.buffer.borrow_mut();
+            let mut top_start = 0;
+            b.edit(|b| {
+                // from the bottom, so the ranges above are not moved by the edits
+                for (r, padding) in edits.iter().rev() {
+                    let mut r = r.clone();
+                    if empty && ch.is_none() {
+                        let line = b.char_to_line(r.start);
+                        if backward && r.start > b.line_to_char(line) {
+                            r.start -= 1;
+                        } else if !backward && r.end < b.line_to_last_char(line) {
+                            r.end += 1;
+                        }
+                    }
+                    b.remove(r.clone());
+                    if let Some(ch) = ch {
+                        b.insert(r.start, &" ".repeat(*padding));
+                        b.insert_char(r.start + padding, ch);
+                    }
+                    top_start = r.start + padding;
+                }
+            });
+            let idx = Index {
+                index: top_start + ch.map_or(0, |_| 1),
+                buffer: self.buffer.clone(),
+            };
+            drop(b);
+            let p: Point = idx.into();
+            p.col
+        };
+        self.set_cursor_point(block.head.0, col);
+        self.block_selection = Some(BlockSelection::new((block.anchor.0, col), (block.head.0, col)));
+        self.focus_on_cursor();
+        let (first, _) = block.lines();
+        let end = self.viewport.line_end();
+        self.update_styling_cache(first..end);
+    }
+
+    /// select the word when double clicked
+    pub fn double_click(&mut self, x: i32, y: i32) {
+        self.select_word_under_cursor();
+    }
+
+    /// select the line when triple clicked
+    pub fn triple_click(&mut self) {
+        self.select_line_at(self.cursor.get_index());
+    }
+
+    /// Select the word under the cursor
+    pub fn select_word_under_cursor(&mut self) {
+        self.select_word_at(self.cursor.get_index());
+    }
+
+    /// Select the word containing index, or ending at index, and move the cursor at its end
+    pub fn select_word_at(&mut self, index: usize) {
+        let r = word_range_at(&self.buffer.borrow(), index);
+        match r {
+            Some(r) => {
+                self.selection = Some(Selection::new(r.start, r.end));
+                self.cursor.set_index(r.end);
+            }
+            None => self.selection = None,
+        }
+    }
+
+    /// Select the line containing index with its line feed, and move the cursor at its end
+    pub fn select_line_at(&mut self, index: usize) {
+        let r = line_range_at(&self.buffer.borrow(), index);
+        self.selection = Some(Selection::new(r.start, r.end));
+        self.cursor.set_index(r.end);
+    }
+
+    /// return the bracket under or just before the cursor, and its match
+    fn bracket_pair(&self) -> Option<(usize, usize)> {
+        let b = self.buffer.borrow();
+        let index = self.cursor.get_index();
+        matching_bracket(&b, index).map(|m| (index, m)).or_else(|| {
+            if index > 0 {
+                matching_bracket(&b, index - 1).map(|m| (index - 1, m))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// move the cursor to the bracket matching the one under the cursor
+    pub fn goto_matching_bracket(&mut self) {
+        let index = matching_bracket(&self.buffer.borrow(), self.cursor.get_index());
+        if let Some(index) = index {
+            self.cursor.set_index(index);
+            self.clear_selection();
+            self.focus_on_cursor();
+        }
+    }
+
+    /// move the cursor to the beginning of the given line, and center the view on it
+    pub fn goto_line(&mut self, line: usize) {
+        use std::cmp::min;
+        self.push_jump();
+        let line = min(line, self.buffer.borrow().len_lines() - 1);
+        let idx = self.buffer.borrow().line_to_char(line);
+        self.goto_index(idx);
+        self.viewport.line_start = line.saturating_sub(self.viewport.heigth / 2);
+        let end = self.viewport.line_end();
+        self.expand_styling_cache(end);
+    }
+
+    /// return true if the line is empty or contains only whitespace
+    fn is_blank_line(&self, line: usize) -> bool {
+        self.buffer.borrow().chars_on_line(line).all(char::is_whitespace)
+    }
+
+    /// move the cursor to the start of the next blank line following a non blank one,
+    /// or to the end of the buffer if there is none
+    pub fn move_paragraph_down(&mut self) {
+        self.push_jump();
+        let len_lines = self.buffer.borrow().len_lines();
+        let mut line = self.line_idx() + 1;
+        while line < len_lines && self.is_blank_line(line) {
+            line += 1;
+        }
+        while line < len_lines && !self.is_blank_line(line) {
+            line += 1;
+        }
+        let index = if line < len_lines {
+            self.buffer.borrow().line_to_char(line)
+        } else {
+            self.buffer.borrow().len_chars()
+        };
+        self.goto_index(index);
+    }
+
+    /// move the cursor to the start of the previous blank line preceding a non blank one,
+    /// or to the start of the buffer if there is none
+    pub fn move_paragraph_up(&mut self) {
+        self.push_jump();
+        let mut line = self.line_idx();
+        while line > 0 && self.is_blank_line(line - 1) {
+            line -= 1;
+        }
+        while line > 0 && !self.is_blank_line(line - 1) {
+            line -= 1;
+        }
+        let index = if line > 0 {
+            self.buffer.borrow().line_to_char(line - 1)
+        } else {
+            0
+        };
+        self.goto_index(index);
+    }
+
+    /// search the given text, and move the cursor to the next match.
+    /// A text starting with a '/' is a regular expression
+    pub fn search(&mut self, needle: &str) {
+        if !needle.is_empty() {
+            self.push_jump();
+        }
+        self.search_without_jump(needle);
+    }
+
+    /// same as search, without recording the cursor position in the jump list.
+    /// Used while the text is typed, the jump is recorded once for the whole search
+    pub fn search_without_jump(&mut self, needle: &str) {
+        if needle.is_empty() {
+            self.clear_search();
+            return;
+        }
+        self.search_query = Some(needle.to_owned());
+        self.search_regex = regex_pattern(needle).and_then(|pattern| Regex::new(pattern).ok());
+        self.update_search_matches();
+        let idx = self.cursor.get_index();
+        let next = self
+            .search_matches
+            .iter()
+            .find(|m| m.start >= idx)
+            .or_else(|| self.search_matches.first())
+            .map(|m| m.start);
+        if let Some(next) = next {
+            self.goto_index(next);
+        }
+    }
+
+    /// move the cursor to the next match of the current search, wrapping at the end of the buffer
+    pub fn search_next(&mut self) {
+        self.push_jump();
+        let idx = self.cursor.get_index();
+        let next = self
+            .search_matches
+            .iter()
+            .find(|m| m.start > idx)
+            .or_else(|| self.search_matches.first())
+            .map(|m| m.start);
+        if let Some(next) = next {
+            self.goto_index(next);
+        }
+    }
+
+    /// move the cursor to the previous match of the current search, wrapping at the beginning of the buffer
+    pub fn search_prev(&mut self) {
+        self.push_jump();
+        let idx = self.cursor.get_index();
+        let prev = self
+            .search_matches
+            .iter()
+            .rev()
+            .find(|m| m.start < idx)
+            .or_else(|| self.search_matches.last())
+            .map(|m| m.start);
+        if let Some(prev) = prev {
+            self.goto_index(prev);
+        }
+    }
+
+    /// return true if the matches of a search are highlighted
+    pub fn has_search(&self) -> bool {
+        self.search_query.is_some()
+    }
+
+    /// forget the current search and its highlights
+    pub fn clear_search(&mut self) {
+        self.search_query = None;
+        self.search_regex = None;
+        self.search_matches.clear();
+    }
+
+    fn update_search_matches(&mut self) {
+        self.search_matches.clear();
+        if let Some(needle) = &self.search_query {
+            let b = self.buffer.borrow();
+            // an invalid regular expression matches nothing
+            if regex_pattern(needle).is_some() {
+                if let Some(re) = &self.search_regex {
+                    self.search_matches = b.find_regex(re, 0);
+                }
+                return;
+            }
+            let len = needle.chars().count();
+            let mut from = 0;
+            while let Some(start) = b.find(needle, from) {
+                self.search_matches.push(start..start + len);
+                from = start + len;
+            }
+        }
+    }
+
+    fn goto_index(&mut self, index: usize) {
+        self.cursor.set_index(index);
+        self.clear_selection();
+        self.focus_on_cursor();
+    }
+
+    fn current_jump(&self) -> Jump {
+        Jump {
+            index: self.cursor.get_index(),
+            line: self.cursor.get_line(),
+        }
+    }
+
+    /// record the cursor position in the jump list, before a large move
+    pub fn push_jump(&mut self) {
+        let jump = self.current_jump();
+        self.jump_list.push(jump);
+    }
+
+    /// add or remove a bookmark on the cursor line
+    pub fn toggle_bookmark(&mut self) {
+        let line = self.line_idx();
+        self.buffer.borrow_mut().toggle_bookmark(line);
+    }
+
+    /// move the cursor to the start of the next bookmarked line, wrapping at the end of the buffer
+    pub fn goto_next_bookmark(&mut self) {
+        let line = self.buffer.borrow().next_bookmark(self.line_idx());
+        if let Some(line) = line {
+            let index = self.buffer.borrow().line_to_char(line);
+            self.goto_index(index);
+        }
+    }
+
+    /// move the cursor to the start of the previous bookmarked line, wrapping at the beginning of the buffer
+    pub fn goto_prev_bookmark(&mut self) {
+        let line = self.buffer.borrow().prev_bookmark(self.line_idx());
+        if let Some(line) = line {
+            let index = self.buffer.borrow().line_to_char(line);
+            self.goto_index(index);
+        }
+    }
+
+    /// move the cursor back to the position it had before the last jump
+    pub fn jump_back(&mut self) {
+        let current = self.current_jump();
+        if let Some(index) = self.jump_list.back(current) {
+            // the buffer may have shrunk since
+            let index = std::cmp::min(index, self.buffer.borrow().len_chars());
+            self.goto_index(index);
+        }
+    }
+
+    /// move the cursor forward in the jump list, after going back
+    pub fn jump_forward(&mut self) {
+        if let Some(index) = self.jump_list.forward() {
+            let index = std::cmp::min(index, self.buffer.borrow().len_chars());
+            self.goto_index(index);
+        }
+    }
+
+    /// scroll the view in the given direction
+    pub fn scroll(&mut self, dir: Direction, amount: i32) {
+        match dir {
+            Direction::Up => self.scroll_lines(-amount),
+            Direction::Down => self.scroll_lines(amount),
+            _ => (),
+        }
+    }
+
+    /// scroll the view by delta lines without moving the cursor, up to the last line
+    pub fn scroll_lines(&mut self, delta: i32) {
+        let last_line = self.buffer.borrow().len_lines() as i32 - 1;
+        let line_start = self.viewport.line_start as i32 + delta;
+        self.viewport.line_start = std::cmp::max(0, std::cmp::min(line_start, last_line)) as usize;
+    }
+
+    /// return the last line that can be displayed first
+    fn max_line_start(&self) -> usize {
+        let last_line = self.buffer.borrow().len_lines() - 1;
+        let max_line_start = last_line.saturating_sub(self.viewport.heigth).saturating_add(self.scroll_past_end);
+        std::cmp::min(max_line_start, last_line)
+    }
+
+    /// Detect the carriage return type of the buffer
+    pub fn detect_linefeed(&mut self) {
+        #[cfg(target_os = "windows")]
+        let linefeed = LineFeed::CRLF;
+        #[cfg(not(target_os = "windows"))]
+        let linefeed = LineFeed::LF;
+
+        let b = self.buffer.borrow();
+        if b.len_chars() == 0 {
+            self.linefeed = linefeed;
+            return;
+        }
+
+        let mut cr = 0;
+        let mut lf = 0;
+        let mut crlf = 0;
+
+        let mut chars = b.chars().take(1000);
+        while let Some(c) = chars.next() {
+            if c == '\r' {
+                if let Some(c2) = chars.next() {
+                    if c2 == '\n' {
+                        crlf += 1;
+                    } else {
+                        cr += 1;
+                    }
+                }
+            } else if c == '\n' {
+                lf += 1;
+            }
+        }
+
+        self.linefeed = if cr > crlf && cr > lf {
+            LineFeed::CR
+        } else if lf > crlf && lf > cr {
+            LineFeed::LF
+        } else if crlf == 0 {
+            // no line break yet
+            linefeed
+        } else {
+            LineFeed::CRLF
+        }
+    }
+
+    pub fn detect_indentation(&self) -> Indentation {
+        // detect Tabs first. If the first char of a line is more often a Tab
+        // then we consider the indentation as tabulation.
+        let b = self.buffer.borrow();
+        let mut tab = 0;
+        let mut space = 0;
+        for line in b.lines() {
+            match line.chars().next() {
+                Some(' ') => space += 1,
+                Some('\t') => tab += 1,
+                _ => (),
+            }
+        }
+        if tab > space {
+            let tabsize: u32 = SETTINGS.read().unwrap().get("tabSize").unwrap();
+            return Indentation::Tab(tabsize);
+        }
+
+        // Algorythm from
+        // https://medium.com/firefox-developer-tools/detecting-code-indentation-eff3ed0fb56b
+        use std::collections::HashMap;
+        let mut indents = HashMap::new();
+        let mut last = 0;
+
+        for line in b.lines() {
+            let width = line.chars().take_while(|c| *c == ' ').count();
+            let indent = (width as isize - last as isize).abs();
+            if indent > 1 {
+                let i = indents.entry(indent).or_insert(0);
+                *i += 1;
+            }
+            last = width;
+        }
+        if let Some(i) = indents.iter().max_by(|x,y| x.1.cmp(y.1)) {
+            println!("largest {}", i.0);
+            Indentation::Space(*i.0 as u32)
+        } else {
+            let tabsize: u32 = SETTINGS.read().unwrap().get("tabSize").unwrap();
+            Indentation::Space(tabsize)
+        }
+    }
+
+    /// move the view so that the cursor is visible
+    pub fn focus_on_cursor(&mut self) {
+        use std::cmp::min;
+        let pagelen = self.viewport.heigth;
+        let l = self.line_idx();
+        // keep a few lines visible around the cursor, the bottom margin stops at the last line
+        // unless the view can scroll past it
+        let margin = min(self.scroll_margin, pagelen / 2);
+        if l < self.viewport.line_start + margin {
+            self.viewport.line_start = l.saturating_sub(margin);
+        }
+        let bottom = min(l + margin, self.max_line_start() + pagelen);
+        if bottom > self.viewport.line_end() {
+            self.viewport.line_start = bottom - pagelen;
+        }
+        {
+            let b = self.buffer.borrow();
+            self.viewport.line_start = min(self.viewport.line_start, b.len_lines());
+        }
+
+        self.update_viewport_width();
+        let pagewidth = self.viewport.width;
+        // keep a few columns visible around the cursor
+        let margin = min(HSCROLL_MARGIN, pagewidth / 2);
+        let c = self.col_idx();
+        if c < self.viewport.col_start + margin {
+            self.viewport.col_start = c.saturating_sub(margin);
+        }
+        if c + margin > self.viewport.col_end() {
+            self.viewport.col_start = c + margin - pagewidth;
+        }
+
+        let end = self.viewport.line_end();
+        self.expand_styling_cache(end);
+    }
+
+    /// scroll so the cursor line is in the middle of the page, without scrolling past the last line
+    /// more than allowed
+    pub fn center_cursor(&mut self) {
+        let pagelen = self.viewport.heigth;
+        let line_start = self.line_idx().saturating_sub(pagelen / 2);
+        let max_line_start = self.max_line_start();
+        self.set_line_start(std::cmp::min(line_start, max_line_start));
+    }
+
+    /// scroll so the cursor line is the first of the page
+    pub fn cursor_to_top(&mut self) {
+        let line = self.line_idx();
+        self.set_line_start(line);
+    }
+
+    /// scroll so the cursor line is the last of the page
+    pub fn cursor_to_bottom(&mut self) {
+        let line_start = self.line_idx().saturating_sub(self.viewport.heigth);
+        self.set_line_start(line_start);
+    }
+
+    /// return the first line displayed
+    pub fn first_visible_line(&self) -> usize {
+        self.viewport.line_start
+    }
+
+    /// scroll to display the given line first, clamped to the last line that can be displayed first
+    pub fn set_first_visible_line(&mut self, line: usize) {
+        let line_start = std::cmp::min(line, self.max_line_start());
+        self.set_line_start(line_start);
+    }
+
+    fn set_line_start(&mut self, line_start: usize) {
+        self.viewport.line_start = line_start;
+        let end = self.viewport.line_end();
+        self.expand_styling_cache(end);
+    }
+
+    /// return what would be drawn by the view
+    pub fn draw_state(&self) -> ViewDrawState {
+        let b = self.buffer.borrow();
+        ViewDrawState {
+            revision: b.revision(),
+            is_dirty: b.is_dirty(),
+            changed_on_disk: b.is_changed_on_disk(),
+            cursor: self.cursor.get_index(),
+            selection: self.selection.map(Into::into),
+            line_start: self.viewport.line_start,
+            col_start: self.viewport.col_start,
+            search_query: self.search_query.clone(),
+            show_line_numbers: self.show_line_numbers,
+            overtype: self.overtype,
+            caret_visible: self.caret_visible,
+            bookmarks: b.bookmarks().collect(),
+            block_selection: self.block_selection,
+            show_whitespace: self.show_whitespace,
+            show_indent_guides: self.show_indent_guides,
+            highlight_current_line: self.highlight_current_line,
+        }
+    }
+
+    /// Draw the vew on the given screen
+    pub fn draw(&self, canvas: &mut Canvas, theme: &Theme) {
+        let adv = self.geometry.font_advance;
+        let line_spacing = self.geometry.font_height;
+        let mut y = self.geometry.y + line_spacing;
+        // the cursor columns are computed with the same tab size
+        let tabsize: usize = SETTINGS.read().unwrap().get("tabSize").unwrap();
+
+        let first_visible_line = self.viewport.line_start;
+        let first_visible_col = self.viewport.col_start;
+        let last_visible_col = self.viewport.col_end();
+        let page_len = self.viewport.heigth;
+        let gutter_width = self.gutter_width();
+        let gutter = self.geometry.x + gutter_width as f32 * adv;
+
+        let mut current_col = 0;
+
+        let first_visible_char = self.buffer.borrow().line_to_char(first_visible_line);
+        let mut search_matches = self
+            .search_matches
+            .iter()
+            .skip_while(|m| m.end <= first_visible_char)
+            .peekable();
+        let brackets = self.bracket_pair();
+
+        // read once, the loop below only uses locals
+        let selection: Option<Range<usize>> = self.selection.map(Into::into);
+        let descender = canvas.font_metrics.descender;
+        let rgb = |c: syntect::highlighting::Color| Color::from_rgb(c.r, c.g, c.b);
+        let default_fg = rgb(theme.foreground);
+        let find_color = rgb(theme.find_highlight);
+        let bracket_color = rgb(theme.bracket_highlight);
+        let selection_color = rgb(theme.selection);
+        let whitespace_color = {
+            let c = theme.gutter_foreground;
+            Color::from_rgba(c.r, c.g, c.b, 0x80)
+        };
+        let guide_color = {
+            let c = theme.indent_guide;
+            Color::from_rgba(c.r, c.g, c.b, 0x80)
+        };
+        let right = self.geometry.x + self.geometry.w;
+
+        // only the visible lines are iterated
+        let b = self.buffer.borrow();
+        let last_visible_line = std::cmp::min(first_visible_line + page_len + 1, b.len_lines());
+        for line_index in first_visible_line..last_visible_line {
+            let line = b.line(line_index);
+            let mut style = self
+                .styling
+                .as_ref()
+                .and_then(|s| s.result.get(line_index))
+                .map(|s| s.iter());
+            let mut idx = b.line_to_char(line_index);
+
+            // the cursor line is highlighted on the whole width of the text area
+            if self.highlight_current_line && line_index == self.cursor.get_line() {
+                canvas.set_color(rgb(theme.line_highlight));
+                canvas.move_to(gutter, y - descender - line_spacing);
+                canvas.draw_rect(right - gutter, line_spacing as _);
+            }
+
+            // the block selection is a rectangle, even over the lines too short to be part of it
+            if let Some(block) = self.block_selection {
+                let (first, last) = block.lines();
+                let cols = block.columns();
+                let start = std::cmp::max(cols.start, first_visible_col);
+                if line_index >= first && line_index <= last && cols.end > start {
+                    canvas.set_color(selection_color);
+                    canvas.move_to(gutter + (start - first_visible_col) as f32 * adv, y - descender - line_spacing);
+                    canvas.draw_rect((cols.end - start) as f32 * adv, line_spacing as _);
+                }
+            }
+
+            if self.show_indent_guides {
+                canvas.set_color(guide_color);
+                for x in self.indent_guides(line_index, tabsize) {
+                    canvas.move_to(x, y - descender - line_spacing);
+                    canvas.draw_rect(1.0, line_spacing as _);
+                }
+            }
+
+            if self.show_line_numbers {
+                canvas.set_color(rgb(theme.gutter_foreground));
+                canvas.move_to(self.geometry.x, y);
+                canvas.draw_str(&format!("{:>1$}", line_index + 1, gutter_width - 1));
+            }
+
+            // a bookmark is marked in the space between the line number and the text,
+            // or by a bar on the left of the text without line numbers
+            if b.is_bookmarked(line_index) {
+                canvas.set_color(rgb(theme.gutter_foreground));
+                if self.show_line_numbers {
+                    canvas.move_to(gutter - adv * 0.75, y - descender - line_spacing * 0.75);
+                    canvas.draw_rect(adv / 2.0, line_spacing / 2.0);
+                } else {
+                    canvas.move_to(gutter, y - descender - line_spacing);
+                    canvas.draw_rect(adv / 5.0, line_spacing as _);
+                }
+            }
+
+            for c in line.chars() {
+                if current_col > last_visible_col as i32 {
+                    break;
+                }
+                let visible = current_col >= first_visible_col as i32;
+                let x = gutter + (current_col - first_visible_col as i32) as f32 * adv;
+                let width = match c {
+                    '\t' => spaces_to_next_tab_stop(current_col as usize, tabsize),
+                    '\r' | '\n' | '\0' | '\u{feff}' | '\u{fffe}' => 1,
+                    c => char_width(c),
+                };
+
+                let (fg, glyph_style) = match style.as_mut().and_then(|s| s.next()) {
+                    None => (default_fg, GlyphStyle::default()),
+                    Some(s) => (rgb(s.foreground), GlyphStyle::from(s.font_style)),
+                };
+                while search_matches.peek().map_or(false, |m| m.end <= idx) {
+                    search_matches.next();
+                }
+                if visible {
+                    let rect_width = width as f32 * adv;
+                    let selection_width = if c == '\n' && self.selection_to_line_end {
+                        f32::max(right - x, rect_width)
+                    } else {
+                        rect_width
+                    };
+                    let highlights = [
+                        (search_matches.peek().map_or(false, |m| m.contains(&idx)), find_color, rect_width),
+                        (brackets.map_or(false, |(a, b)| idx == a || idx == b), bracket_color, rect_width),
+                        (selection.as_ref().map_or(false, |s| s.contains(&idx)), selection_color, selection_width),
+                    ];
+                    for (_, color, w) in highlights.iter().filter(|(on, _, _)| *on) {
+                        canvas.set_color(*color);
+                        canvas.move_to(x as _, y - descender - line_spacing);
+                        canvas.draw_rect(*w, line_spacing as _);
+                    }
+                    let marker = match c {
+                        ' ' => Some(SPACE_MARKER),
+                        '\t' => Some(TAB_MARKER),
+                        '\n' => Some(EOL_MARKER),
+                        _ => None,
+                    };
+                    if let (true, Some(marker)) = (self.show_whitespace, marker) {
+                        canvas.move_to(x as _, y as _);
+                        canvas.set_color(whitespace_color);
+                        canvas.draw_char(marker);
+                    }
+                }
+                match c {
+                    '\t' => current_col += width as i32,
+                    '\0' => (),
+                    '\r' => (), //idx -= 1,
+                    '\n' => (),
+                    // Bom hiding. TODO: rework
+                    '\u{feff}' | '\u{fffe}' => (),
+                    _ => {
+                        if visible {
+                            canvas.move_to(x as _, y as _);
+                            canvas.set_color(fg);
+                            canvas.draw_styled_char(c, glyph_style);
+                        }
+                        current_col += width as i32;
+                    }
+                }
+                idx += 1;
+            }
+            y += line_spacing;
+            current_col = 0;
+        }
+
+        if let Some(x) = self.ruler_x() {
+            canvas.set_color(rgb(theme.ruler));
+            canvas.move_to(x, self.geometry.y);
+            canvas.draw_rect(1.0, self.geometry.h);
+        }
+
+        // Cursor
+        let fg = theme.caret;
+        let (mut line, mut col) = (self.cursor.get_line(), self.cursor.get_col());
+
+        if self.caret_visible && self.viewport.contain(line, col) {
+            line -= first_visible_line;
+            col -= first_visible_col;
+            let style = if self.overtype { CursorStyle::Block } else { self.cursor_style };
+            let width = self.char_at_cursor().map_or(1, char_width) as f32 * adv;
+            let (y_offset, w, h) = style.rect(width, line_spacing);
+            canvas.move_to(
+                gutter + col as f32 * adv,
+                self.geometry.y + line as f32 * line_spacing - canvas.font_metrics.descender + y_offset,
+            );
+            // the block is translucent, the char stays readable under it
+            if style == CursorStyle::Block {
+                canvas.set_color(Color::from_rgba(fg.r, fg.g, fg.b, 0x80));
+            } else {
+                canvas.set_color(Color::from_rgb(fg.r, fg.g, fg.b));
+            }
+            canvas.draw_rect(w, h);
+        }
+    }
+
+    /// clear the current selection
+    pub fn clear_selection(&mut self) {
+        self.selection = None;
+        self.block_selection = None;
+    }
+    fn expand_selection(&mut self) {
+        self.selection = if let Some(mut selection) = self.selection {
+            selection.expand(self.cursor.get_index());
+            Some(selection)
+        } else {
+            Some(Selection::new(
+                self.cursor.get_previous_index(),
+                self.cursor.get_index(),
+            ))
+        }
+    }
+}
+
+pub trait ViewCmd {
+    fn name(&self) -> &'static str;
+    fn desc(&self) -> &'static str;
+    fn keybinding(&self) -> Vec<KeySequence>;
+    /// return true if the command waits for an input typed in the palette, a prompt or a dialog.
+    /// It is never recorded in a macro: the input is not part of it
+    fn is_interactive(&self) -> bool {
+        false
+    }
+    fn run(&mut self, _: &mut View<'_>);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::Buffer;
+    use crate::nanovg::tests::{drawn_chars, drawn_rects, test_canvas};
+    use crate::theme::Theme;
+    use crate::view::{BlockSelection, CursorStyle, Direction, Selection, View};
+    use nanovg::Color;
+    use crate::window::Geometry;
+    use regex::Regex;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const GEO: Geometry = Geometry {
+        x: 0.0,
+        y: 0.0,
+        w: 100.0,
+        h: 100.0,
+        font_advance: 10.0,
+        font_height: 10.0,
+    };
+
+    /// a theme color as drawn
+    fn rgb(c: syntect::highlighting::Color) -> Color {
+        Color::from_rgb(c.r, c.g, c.b)
+    }
+
+    #[test]
+    fn block_selection_ranges() {
+        // the selected chars of each line of the block
+        fn ranges(block: BlockSelection, b: &Rc<RefCell<Buffer>>) -> Vec<Option<std::ops::Range<usize>>> {
+            let (first, last) = block.lines();
+            (first..=last).map(|line| block.line_range(line, b)).collect()
+        }
+
+        let b = Rc::new(RefCell::new(Buffer::from_str("abcdef\nab\n\nabcdefgh\nx")));
+        // the corners can be given in any order, the short lines are cut or skipped
+        let block = BlockSelection::new((3, 4), (0, 1));
+        assert_eq!(block.lines(), (0, 3));
+        assert_eq!(block.columns(), 1..4);
+        assert_eq!(ranges(block, &b), vec![Some(1..4), Some(8..9), None, Some(12..15)]);
+        let block = BlockSelection::new((0, 5), (1, 2));
+        assert_eq!(ranges(block, &b), vec![Some(2..5), Some(9..9)]);
+        // an empty block
+        let block = BlockSelection::new((0, 2), (4, 2));
+        assert_eq!(ranges(block, &b), vec![Some(2..2), Some(9..9), None, Some(13..13), None]);
+        // a tab is selected when the right column is inside it
+        let b = Rc::new(RefCell::new(Buffer::from_str("ab\tc\nabcdefg")));
+        let block = BlockSelection::new((0, 1), (1, 3));
+        assert_eq!(ranges(block, &b), vec![Some(1..3), Some(6..8)]);
+    }
+    #[test]
+    fn block_selection_edit() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("abcdef\nab\n\nabcdefgh")));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_cursor_point(0, 1);
+        for _ in 0..3 {
+            v.move_block_selection(Direction::Down);
+            v.move_block_selection(Direction::Right);
+        }
+        assert!(v.has_block_selection());
+        assert_eq!(v.cursor_point(), (3, 4));
+
+        v.insert_char('X');
+        assert_eq!(b.borrow().to_string(), "aXef\naX\n\naXefgh");
+        assert_eq!(v.cursor_point(), (3, 2));
+        v.insert_char('Y');
+        assert_eq!(b.borrow().to_string(), "aXYef\naXY\n\naXYefgh");
+        v.backspace();
+        assert_eq!(b.borrow().to_string(), "aXef\naX\n\naXefgh");
+        // nothing is deleted after the end of a line
+        v.delete_at_cursor();
+        assert_eq!(b.borrow().to_string(), "aXf\naX\n\naXfgh");
+        assert!(v.has_block_selection());
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "aXef\naX\n\naXefgh");
+
+        // a cursor move ends the block
+        v.move_cursor(Direction::Left, false);
+        assert!(!v.has_block_selection());
+        v.insert_char('Z');
+        assert_eq!(b.borrow().to_string(), "aXef\naX\n\naZXefgh");
+    }
+    #[test]
+    fn block_selection_prefix() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("one\ntwo\nthree\nfour")));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_cursor_point(0, 0);
+        v.move_block_selection(Direction::Down);
+        v.move_block_selection(Direction::Down);
+        for c in "// ".chars() {
+            v.insert_char(c);
+        }
+        assert_eq!(b.borrow().to_string(), "// one\n// two\n// three\nfour");
+        assert_eq!(v.cursor_point(), (2, 3));
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "//one\n//two\n//three\nfour");
+    }
+    #[test]
+    fn block_selection_padding() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("abcd\na\n\tb\nabcd")));
+        let mut v = View::new(b.clone(), GEO);
+        v.pad_block_lines = false;
+        v.set_cursor_point(0, 3);
+        v.move_block_selection(Direction::Down);
+        v.move_block_selection(Direction::Down);
+        v.move_block_selection(Direction::Down);
+        v.insert_char('X');
+        // the tab covers the column
+        assert_eq!(b.borrow().to_string(), "abcXd\na\n\tXb\nabcXd");
+
+        let b = Rc::new(RefCell::new(Buffer::from_str("abcd\na\n\nabcd")));
+        let mut v = View::new(b.clone(), GEO);
+        v.pad_block_lines = true;
+        v.set_cursor_point(0, 3);
+        v.move_block_selection(Direction::Down);
+        v.move_block_selection(Direction::Down);
+        v.move_block_selection(Direction::Down);
+        v.insert_char('X');
+        v.insert_char('Y');
+        assert_eq!(b.borrow().to_string(), "abcXYd\na  XY\n   XY\nabcXYd");
+        v.backspace();
+        assert_eq!(b.borrow().to_string(), "abcXd\na  X\n   X\nabcXd");
+        // the deletions never pad
+        v.move_block_selection(Direction::Right);
+        v.move_block_selection(Direction::Right);
+        v.backspace();
+        assert_eq!(b.borrow().to_string(), "abcX\na  X\n   X\nabcX");
+    }
+    #[test]
+    fn cursor_style_rect() {
+        assert_eq!(CursorStyle::Bar.rect(10.0, 12.0), (0.0, 2.0, 12.0));
+        assert_eq!(CursorStyle::Block.rect(10.0, 12.0), (0.0, 10.0, 12.0));
+        assert_eq!(CursorStyle::Block.rect(20.0, 12.0), (0.0, 20.0, 12.0));
+        assert_eq!(CursorStyle::Underline.rect(10.0, 12.0), (10.0, 10.0, 2.0));
+    }
+    #[test]
+    fn draw_cursor_style() {
+        fn caret(v: &View<'_>) -> Option<(f32, f32, f32, f32)> {
+            let mut canvas = test_canvas();
+            v.draw(&mut canvas, &Theme::default());
+            // the caret is the last rect drawn
+            drawn_rects(&canvas, None).pop()
+        }
+
+        let b = Rc::new(RefCell::new(Buffer::from_str("ab\ncd")));
+        let mut v = View::new(b, GEO);
+        v.set_show_line_numbers(false);
+        v.set_cursor_point(1, 1);
+        v.set_cursor_style(CursorStyle::Bar);
+        assert_eq!(caret(&v), Some((10.0, 12.0, 2.0, 10.0)));
+        v.set_cursor_style(CursorStyle::Block);
+        assert_eq!(caret(&v), Some((10.0, 12.0, 10.0, 10.0)));
+        v.set_cursor_style(CursorStyle::Underline);
+        assert_eq!(caret(&v), Some((10.0, 20.0, 10.0, 2.0)));
+        v.toggle_overtype();
+        assert_eq!(caret(&v), Some((10.0, 12.0, 10.0, 10.0)));
+    }
+    #[test]
+    fn ruler_x() {
+        let b = Rc::new(RefCell::new(Buffer::from_str(&"a".repeat(50))));
+        let mut v = View::new(b, GEO);
+        v.set_show_line_numbers(false);
+        v.ruler_column = None;
+        assert_eq!(v.ruler_x(), None);
+        v.ruler_column = Some(8);
+        assert_eq!(v.ruler_x(), Some(80.0));
+        // after the gutter
+        v.set_show_line_numbers(true);
+        v.ruler_column = Some(3);
+        assert_eq!(v.ruler_x(), Some(50.0));
+        // with the horizontal scroll
+        v.set_show_line_numbers(false);
+        v.set_cursor_point(0, 40);
+        v.focus_on_cursor();
+        let col_start = v.viewport.col_start;
+        assert!(col_start > 3);
+        assert_eq!(v.ruler_x(), None);
+        v.ruler_column = Some(col_start + 2);
+        assert_eq!(v.ruler_x(), Some(20.0));
+
+        let theme = Theme::default();
+        let mut canvas = test_canvas();
+        v.draw(&mut canvas, &theme);
+        assert_eq!(drawn_rects(&canvas, Some(rgb(theme.ruler))), vec![(20.0, 0.0, 1.0, 100.0)]);
+    }
+    #[test]
+    fn indent_guides() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("a\n    b\n\t  c\n  \t\td\n  e\n        f")));
+        let mut v = View::new(b, GEO);
+        v.set_show_line_numbers(false);
+        assert!(v.indent_guides(0, 4).is_empty());
+        assert_eq!(v.indent_guides(1, 4), vec![0.0]);
+        // a tab and two spaces
+        assert_eq!(v.indent_guides(2, 4), vec![0.0, 40.0]);
+        // the first tab only goes to the next tab stop
+        assert_eq!(v.indent_guides(3, 4), vec![0.0, 40.0]);
+        assert_eq!(v.indent_guides(4, 4), vec![0.0]);
+        assert_eq!(v.indent_guides(5, 2), vec![0.0, 20.0, 40.0, 60.0]);
+        // after the gutter
+        v.set_show_line_numbers(true);
+        assert_eq!(v.indent_guides(1, 2), vec![20.0, 40.0]);
+
+        // the guides follow the tab size of the drawn text, not the one of the view
+        let theme = Theme::default();
+        let mut canvas = test_canvas();
+        v.set_show_line_numbers(false);
+        v.set_tab_width(2);
+        v.set_show_indent_guides(true);
+        v.draw(&mut canvas, &theme);
+        let guides: Vec<_> = drawn_rects(&canvas, Some(rgb(theme.indent_guide)))
+            .into_iter()
+            .map(|(x, y, _, _)| (x, y))
+            .collect();
+        assert_eq!(
+            guides,
+            vec![
+                (0.0, 12.0),
+                (0.0, 22.0),
+                (40.0, 22.0),
+                (0.0, 32.0),
+                (40.0, 32.0),
+                (0.0, 42.0),
+                (0.0, 52.0),
+                (40.0, 52.0)
+            ]
+        );
+        // the tab of the third line ends on the second guide
+        let c = drawn_chars(&canvas).into_iter().find(|(_, _, c)| *c == 'c');
+        assert_eq!(c.map(|(x, _, _)| x), Some(60.0));
+    }
+    #[test]
+    fn draw_current_line() {
+        fn line_rects(v: &View<'_>, theme: &Theme) -> Vec<(f32, f32, f32)> {
+            let mut canvas = test_canvas();
+            v.draw(&mut canvas, theme);
+            drawn_rects(&canvas, Some(rgb(theme.line_highlight)))
+                .into_iter()
+                .map(|(x, y, w, _)| (x, y, w))
+                .collect()
+        }
+
+        let theme = Theme::default();
+        let b = Rc::new(RefCell::new(Buffer::from_str("abc\nd\nefgh")));
+        let mut v = View::new(b, GEO);
+        v.set_show_line_numbers(false);
+        v.set_cursor_point(1, 1);
+        v.set_highlight_current_line(false);
+        assert!(line_rects(&v, &theme).is_empty());
+        // the whole width, even on a short line
+        v.set_highlight_current_line(true);
+        assert_eq!(line_rects(&v, &theme), vec![(0.0, 12.0, 100.0)]);
+        // the gutter is not highlighted
+        v.set_show_line_numbers(true);
+        v.set_cursor_point(2, 0);
+        assert_eq!(line_rects(&v, &theme), vec![(20.0, 22.0, 80.0)]);
+    }
+    #[test]
+    fn draw_whitespace() {
+        fn markers(v: &View<'_>) -> Vec<(f32, f32, char)> {
+            let mut canvas = test_canvas();
+            v.draw(&mut canvas, &Theme::default());
+            drawn_chars(&canvas)
+                .into_iter()
+                .filter(|(_, _, c)| ['\u{b7}', '\u{ac}', '\u{2192}'].contains(c))
+                .collect()
+        }
+
+        let b = Rc::new(RefCell::new(Buffer::from_str("a b\n\tc\r\nd")));
+        let mut v = View::new(b, GEO);
+        v.set_show_line_numbers(false);
+        assert!(markers(&v).is_empty());
+        v.set_show_whitespace(true);
+        assert_eq!(
+            markers(&v),
+            vec![(10.0, 10.0, '\u{b7}'), (30.0, 10.0, '\u{ac}'), (0.0, 20.0, '\u{2192}'), (50.0, 20.0, '\u{ac}')]
+        );
+    }
+    #[test]
+    fn draw_block_selection() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("abcdef\nab\nabcd")));
+        let mut v = View::new(b, GEO);
+        v.set_cursor_point(0, 1);
+        v.move_block_selection(Direction::Down);
+        v.move_block_selection(Direction::Down);
+        v.move_block_selection(Direction::Right);
+        v.move_block_selection(Direction::Right);
+        v.move_block_selection(Direction::Right);
+        v.set_show_line_numbers(false);
+        let mut canvas = test_canvas();
+        let theme = Theme::default();
+        v.draw(&mut canvas, &theme);
+        // a rectangle, also over the short line
+        assert_eq!(
+            drawn_rects(&canvas, Some(rgb(theme.selection))),
+            vec![(10.0, 2.0, 30.0, 10.0), (10.0, 12.0, 30.0, 10.0), (10.0, 22.0, 30.0, 10.0)]
+        );
+    }
+    #[test]
+    fn new_view() {
+        let b = Rc::new(RefCell::new(Buffer::new()));
+        let _v = View::new(b, GEO);
+    }
+    #[test]
+    fn insert() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("text")));
+        let mut v = View::new(b, GEO);
+        v.insert_char('r');
+        assert_eq!(v.to_string(), "rtext");
+        v.insert_char('e');
+        assert_eq!(v.to_string(), "retext");
+        v.cursor.set_index(6);
+        v.insert_char('e');
+        assert_eq!(v.to_string(), "retexte");
+        v.insert_char('f');
+        assert_eq!(v.to_string(), "retextef");
+    }
+    #[test]
+    fn multiple_view() {
+        let buf = Rc::new(RefCell::new(Buffer::from_str("text")));
+        let mut v1 = View::new(buf.clone(), GEO);
+        let mut v2 = View::new(buf.clone(), GEO);
+        v1.insert_char('r');
+        assert_eq!(v2.to_string(), "rtext");
+        v2.insert_char('e');
+        assert_eq!(v1.to_string(), "ertext");
+    }
+
+    #[test]
+    fn set_index_oob() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("text")));
+        let mut v = View::new(b, GEO);
+        v.cursor.set_index(5);
+        assert_eq!(v.cursor.get_index(), 4);
+    }
+
+    #[test]
+    fn set_index() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("text\nplop")));
+        let mut v = View::new(b, GEO);
+        v.cursor.set_index(4);
+        assert_eq!(v.cursor.get_index(), 4);
+        v.cursor.set_index(5);
+        assert_eq!(v.cursor.get_index(), 5);
+    }
+
+    #[test]
+    fn cursor_up() {
+        let b = Rc::new(RefCell::new(Buffer::from_str(
+            "text\nhello\nme!\nan other very long line",
+        )));
+        let mut v = View::new(b, GEO);
+        v.cursor.set_index(11);
+        v.cursor_up();
+        assert_eq!(v.cursor.get_index(), 5);
+        v.cursor_up();
+        assert_eq!(v.cursor.get_index(), 0);
+        v.cursor_up();
+        assert_eq!(v.cursor.get_index(), 0);
+
+        v.cursor.set_index(25);
+        v.cursor_up();
+        assert_eq!(v.cursor.get_index(), 14);
+        v.cursor_up();
+        assert_eq!(v.cursor.get_index(), 10);
+        v.cursor_up();
+        assert_eq!(v.cursor.get_index(), 4);
+    }
+
+    #[test]
+    fn goal_column() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("a long text line\nhi\n\n\tindented line\nanother long line")));
+        let mut v = View::new(b, GEO);
+        v.set_cursor_point(0, 9);
+        // through a short line and an empty one, then back to long lines
+        v.move_cursor(Direction::Down, false);
+        assert_eq!(v.cursor_point(), (1, 2));
+        v.move_cursor(Direction::Down, false);
+        assert_eq!(v.cursor_point(), (2, 0));
+        v.move_cursor(Direction::Down, false);
+        assert_eq!((v.line_idx(), v.col_idx()), (3, 9));
+        assert_eq!(v.cursor_point(), (3, 6));
+        v.move_cursor(Direction::Down, false);
+        assert_eq!(v.cursor_point(), (4, 9));
+        v.move_cursor(Direction::Up, false);
+        v.move_cursor(Direction::Up, false);
+        v.move_cursor(Direction::Up, false);
+        v.move_cursor(Direction::Up, false);
+        assert_eq!(v.cursor_point(), (0, 9));
+
+        // a horizontal move sets a new goal
+        v.move_cursor(Direction::Down, false);
+        v.move_cursor(Direction::Left, false);
+        v.move_cursor(Direction::Up, false);
+        assert_eq!(v.cursor_point(), (0, 1));
+
+        // after End, the cursor stays at the end of the lines
+        v.end(false);
+        v.move_cursor(Direction::Down, false);
+        assert_eq!(v.cursor_point(), (1, 2));
+        v.move_cursor(Direction::Down, false);
+        v.move_cursor(Direction::Down, false);
+        assert_eq!(v.cursor_point(), (3, 14));
+        v.move_cursor(Direction::Down, false);
+        assert_eq!(v.cursor_point(), (4, 17));
+    }
+    #[test]
+    fn cursor_down() {
+        let b = Rc::new(RefCell::new(Buffer::from_str(
+            "a long text line\nhello\nme!\nan other very long line",
+        )));
+        let mut v = View::new(b, GEO);
+        v.cursor_down();
+        assert_eq!(v.cursor.get_index(), 17);
+        v.cursor_down();
+        assert_eq!(v.cursor.get_index(), 23);
+        v.cursor_down();
+        assert_eq!(v.cursor.get_index(), 27);
+        v.cursor_down();
+        assert_eq!(v.cursor.get_index(), 27);
+
+        v.cursor.set_index(10); // on the second t of text
+        v.cursor_down();
+        assert_eq!(v.cursor.get_index(), 22);
+        v.cursor_down();
+        assert_eq!(v.cursor.get_index(), 26);
+        v.cursor_down();
+        assert_eq!(v.cursor.get_index(), 37);
+        v.cursor_down();
+        assert_eq!(v.cursor.get_index(), 37);
+    }
+    #[test]
+    fn cursor_left() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("text\nhello\n")));
+        let mut v = View::new(b, GEO);
+        v.cursor_left();
+        assert_eq!(v.cursor.get_index(), 0);
+
+        v.cursor.set_index(5);
+        v.cursor_left();
+        assert_eq!(v.cursor.get_index(), 4);
+
+        v.cursor.set_index(2);
+        v.cursor_left();
+        assert_eq!(v.cursor.get_index(), 1);
+
+        v.cursor.set_index(6);
+        v.cursor_left();
+        assert_eq!(v.cursor.get_index(), 5);
+    }
+    #[test]
+    fn cursor_right() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("tt\nh\n")));
+        let mut v = View::new(b, GEO);
+        v.cursor_right();
+        assert_eq!(v.cursor.get_index(), 1);
+        v.cursor_right();
+        assert_eq!(v.cursor.get_index(), 2);
+        v.cursor_right();
+        assert_eq!(v.cursor.get_index(), 3);
+        v.cursor_right();
+        assert_eq!(v.cursor.get_index(), 4);
+        v.cursor_right();
+        assert_eq!(v.cursor.get_index(), 5);
+        v.cursor_right();
+        assert_eq!(v.cursor.get_index(), 5);
+    }
+    #[test]
+    fn backspace() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("hello")));
+        let mut v = View::new(b, GEO);
+        v.backspace();
+        assert_eq!(v.to_string(), "hello");
+        v.cursor.set_index(2);
+        v.backspace();
+        assert_eq!(v.to_string(), "hllo");
+    }
+    #[test]
+    fn delete_at_cursor() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("hello")));
+        let mut v = View::new(b, GEO);
+        v.delete_at_cursor();
+        assert_eq!(v.to_string(), "ello");
+        v.cursor.set_index(3);
+        v.delete_at_cursor();
+        assert_eq!(v.to_string(), "ell");
+        v.delete_at_cursor();
+        assert_eq!(v.to_string(), "ell");
+    }
+    #[test]
+    fn move_word_right() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("let foo_bar = 42;\nnext")));
+        let mut v = View::new(b, GEO);
+        v.cursor.set_index(5); // in the middle of foo_bar
+        v.move_word_right(false);
+        assert_eq!(v.cursor.get_index(), 12);
+        v.move_word_right(false);
+        assert_eq!(v.cursor.get_index(), 14);
+        v.move_word_right(false);
+        assert_eq!(v.cursor.get_index(), 16);
+        v.move_word_right(false);
+        assert_eq!(v.cursor.get_index(), 17); // stop on the newline
+        v.move_word_right(false);
+        assert_eq!(v.cursor.get_index(), 18);
+        v.move_word_right(false);
+        assert_eq!(v.cursor.get_index(), 22);
+        v.move_word_right(false);
+        assert_eq!(v.cursor.get_index(), 22);
+    }
+    #[test]
+    fn move_word_left() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("let foo_bar = 42;\nnext")));
+        let mut v = View::new(b, GEO);
+        v.cursor.set_index(20); // in the middle of next
+        v.move_word_left(false);
+        assert_eq!(v.cursor.get_index(), 18);
+        v.move_word_left(false);
+        assert_eq!(v.cursor.get_index(), 17); // stop on the newline
+        v.move_word_left(false);
+        assert_eq!(v.cursor.get_index(), 16);
+        v.move_word_left(false);
+        assert_eq!(v.cursor.get_index(), 14);
+        v.move_word_left(false);
+        assert_eq!(v.cursor.get_index(), 12);
+        v.move_word_left(false);
+        assert_eq!(v.cursor.get_index(), 4);
+        v.move_word_left(false);
+        assert_eq!(v.cursor.get_index(), 0);
+        v.move_word_left(false);
+        assert_eq!(v.cursor.get_index(), 0);
+    }
+    #[test]
+    fn search_cycle() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("foo bar foo\nbaz foo")));
+        let mut v = View::new(b, GEO);
+        v.cursor.set_index(2);
+        v.search("foo");
+        assert_eq!(v.cursor.get_index(), 8);
+        v.search_next();
+        assert_eq!(v.cursor.get_index(), 16);
+        v.search_next();
+        assert_eq!(v.cursor.get_index(), 0);
+        v.search_prev();
+        assert_eq!(v.cursor.get_index(), 16);
+        v.search_prev();
+        assert_eq!(v.cursor.get_index(), 8);
+        v.clear_search();
+        v.search_next();
+        assert_eq!(v.cursor.get_index(), 8);
+    }
+    #[test]
+    fn search_regex() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("abc 12 de/f 345\n6")));
+        let mut v = View::new(b, GEO);
+        v.search(r"/\d+");
+        assert_eq!(v.search_matches, vec![4..6, 12..15, 16..17]);
+        assert_eq!(v.index(), 4);
+        v.search_next();
+        assert_eq!(v.index(), 12);
+        v.search_prev();
+        assert_eq!(v.index(), 4);
+        // a lone slash, or a slash inside the text, is searched as is
+        v.search("/");
+        assert_eq!(v.search_matches, vec![9..10]);
+        v.search("e/f");
+        assert_eq!(v.search_matches, vec![8..11]);
+        // an invalid pattern matches nothing
+        v.search("/(");
+        assert!(v.search_matches.is_empty());
+        assert!(v.search_regex.is_none());
+
+        // the compiled regular expression is kept while editing
+        v.search(r"/\d+");
+        assert_eq!(v.search_regex.as_ref().map(Regex::as_str), Some(r"\d+"));
+        v.set_cursor_point(0, 0);
+        v.insert_char('9');
+        assert_eq!(v.search_matches, vec![0..1, 5..7, 13..16, 17..18]);
+        v.clear_search();
+        assert!(v.search_regex.is_none());
+    }
+    #[test]
+    fn search_matches_follow_edits() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("foo foo")));
+        let mut v = View::new(b, GEO);
+        v.search("foo");
+        assert_eq!(v.search_matches, vec![0..3, 4..7]);
+        v.insert_char('x');
+        assert_eq!(v.search_matches, vec![1..4, 5..8]);
+    }
+    #[test]
+    fn goto_line() {
+        let text: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&text)));
+        let mut v = View::new(b, GEO);
+        v.goto_line(50);
+        assert_eq!(v.cursor.get_line(), 50);
+        assert_eq!(v.cursor.get_index(), 50 * 7 + 40);
+        assert_eq!(v.viewport.line_start, 50 - v.viewport.heigth / 2);
+        v.goto_line(1000);
+        assert_eq!(v.cursor.get_line(), 100);
+        v.goto_line(0);
+        assert_eq!(v.cursor.get_index(), 0);
+        assert_eq!(v.viewport.line_start, 0);
+    }
+    #[test]
+    fn set_cursor_point() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("ab\n\tcd\r\nlast")));
+        let mut v = View::new(b, GEO);
+        v.set_cursor_point(0, 10);
+        assert_eq!(v.cursor.get_index(), 2);
+        v.set_cursor_point(1, 2);
+        assert_eq!(v.cursor.get_index(), 4);
+        v.set_cursor_point(1, 5);
+        assert_eq!(v.cursor.get_index(), 5);
+        v.set_cursor_point(1, 10);
+        assert_eq!(v.cursor.get_index(), 6);
+        v.set_cursor_point(9, 1);
+        assert_eq!(v.cursor.get_index(), 9);
+        v.click(25, 5, false);
+        assert_eq!(v.cursor.get_index(), 2);
+        v.click(15, 500, false);
+        assert_eq!(v.cursor.get_index(), 9);
+    }
+    #[test]
+    fn drag() {
+        let text: String = (0..100).map(|i| format!("{:02}\n", i)).collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&text)));
+        let mut v = View::new(b, GEO);
+        v.click(0, 15, false);
+        v.drag(25, 35);
+        assert_eq!(v.get_selection(), Some("01\n02\n03".to_owned()));
+        v.drag(0, -5);
+        assert_eq!(v.viewport.line_start, 0);
+        assert_eq!(v.get_selection(), Some("00\n".to_owned()));
+
+        v.viewport.line_start = 10;
+        v.click(0, 0, false);
+        v.drag(0, -5);
+        assert_eq!(v.viewport.line_start, 9);
+        assert_eq!(v.cursor.get_line(), 9);
+        v.drag(0, 200);
+        assert_eq!(v.viewport.line_start, 10);
+        assert_eq!(v.cursor.get_line(), 19);
+        assert_eq!(v.get_selection().unwrap().len(), 27);
+    }
+    #[test]
+    fn scroll_lines() {
+        let text: String = (0..20).map(|i| format!("{}\n", i)).collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&text)));
+        let mut v = View::new(b, GEO);
+        v.scroll_lines(3);
+        assert_eq!(v.viewport.line_start, 3);
+        assert_eq!(v.cursor.get_index(), 0);
+        v.scroll_lines(-5);
+        assert_eq!(v.viewport.line_start, 0);
+        v.scroll_lines(100);
+        assert_eq!(v.viewport.line_start, 20);
+        v.scroll(Direction::Up, 2);
+        assert_eq!(v.viewport.line_start, 18);
+    }
+    #[test]
+    fn delete_word() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("let foo_bar = baz;\nqux")));
+        let mut v = View::new(b, GEO);
+        v.set_cursor_point(0, 11);
+        v.delete_word_backward();
+        assert_eq!(v.to_string(), "let  = baz;\nqux");
+        assert_eq!(v.col_idx(), 4);
+        // the spaces up to the next word
+        v.delete_word_forward();
+        assert_eq!(v.to_string(), "let = baz;\nqux");
+        // a punctuation and the following spaces
+        v.delete_word_forward();
+        assert_eq!(v.to_string(), "let baz;\nqux");
+        v.delete_word_forward();
+        assert_eq!(v.to_string(), "let ;\nqux");
+        v.undo();
+        assert_eq!(v.to_string(), "let baz;\nqux");
+
+        // at a line boundary only the line feed is deleted
+        v.set_cursor_point(1, 0);
+        v.delete_word_backward();
+        assert_eq!(v.to_string(), "let baz;qux");
+        assert_eq!(v.cursor.get_index(), 8);
+        v.insert("\n");
+        v.set_cursor_point(0, 8);
+        v.delete_word_forward();
+        assert_eq!(v.to_string(), "let baz;qux");
+
+        // nothing to delete at the start and at the end of the buffer
+        v.set_cursor_point(0, 0);
+        let depth = v.undo_depth();
+        v.delete_word_backward();
+        v.end(false);
+        v.delete_word_forward();
+        assert_eq!(v.to_string(), "let baz;qux");
+        assert_eq!(v.undo_depth(), depth);
+
+        // the selection is deleted
+        v.set_cursor_point(0, 4);
+        v.select_to(7);
+        v.delete_word_backward();
+        assert_eq!(v.to_string(), "let ;qux");
+    }
+    #[test]
+    fn delete_to_line_end() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("hello world\r\n\nlast")));
+        let mut v = View::new(b, GEO);
+        v.set_cursor_point(0, 5);
+        v.delete_to_line_end();
+        assert_eq!(v.to_string(), "hello\r\n\nlast");
+        assert_eq!(v.col_idx(), 5);
+        // at the end of the line, the line feed is deleted
+        v.delete_to_line_end();
+        assert_eq!(v.to_string(), "hello\nlast");
+        v.delete_to_line_end();
+        assert_eq!(v.to_string(), "hellolast");
+        v.end(false);
+        v.delete_to_line_end();
+        assert_eq!(v.to_string(), "hellolast");
+        v.undo();
+        assert_eq!(v.to_string(), "hello\nlast");
+
+        v.set_cursor_point(1, 2);
+        v.delete_to_line_start();
+        assert_eq!(v.to_string(), "hello\nst");
+        assert_eq!(v.cursor.get_index(), 6);
+        v.delete_to_line_start();
+        assert_eq!(v.to_string(), "hello\nst");
+    }
+    #[test]
+    fn join_lines() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("fn a() {  \n    foo();\n\n}\n")));
+        let mut v = View::new(b, GEO);
+        v.join_lines();
+        assert_eq!(v.to_string(), "fn a() { foo();\n\n}\n");
+        assert_eq!(v.cursor.get_index(), 8);
+        // with a blank line, no space is added
+        v.join_lines();
+        assert_eq!(v.to_string(), "fn a() { foo();\n}\n");
+        v.undo();
+        assert_eq!(v.to_string(), "fn a() { foo();\n\n}\n");
+        v.undo();
+        assert_eq!(v.to_string(), "fn a() {  \n    foo();\n\n}\n");
+
+        // nothing on the last line
+        v.set_cursor_point(4, 0);
+        let depth = v.undo_depth();
+        v.join_lines();
+        assert_eq!(v.undo_depth(), depth);
+
+        // every selected line, in a single undo step
+        let b = Rc::new(RefCell::new(Buffer::from_str("a\n  b\n\tc\nd\n")));
+        let mut v = View::new(b, GEO);
+        v.select_to(7);
+        v.join_lines();
+        assert_eq!(v.to_string(), "a b c\nd\n");
+        assert_eq!(v.cursor.get_index(), 3);
+        v.undo();
+        assert_eq!(v.to_string(), "a\n  b\n\tc\nd\n");
+    }
+    #[test]
+    fn cursor_point() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("ab\r\n\tcd\né")));
+        let mut v = View::new(b, GEO);
+        assert_eq!(v.cursor_point(), (0, 0));
+        v.set_index(2);
+        assert_eq!(v.cursor_point(), (0, 2));
+        v.set_cursor_point(1, 5);
+        assert_eq!(v.index(), 6);
+        assert_eq!(v.cursor_point(), (1, 2));
+        assert_eq!(v.col_idx(), 5);
+        v.end(false);
+        v.move_cursor(Direction::Down, false);
+        assert_eq!(v.cursor_point(), (2, 1));
+        assert_eq!(v.index(), v.buffer.borrow().len_chars());
+    }
+    #[test]
+    fn bookmarks() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("a\nb\nc\nd\ne\n")));
+        let mut v = View::new(b.clone(), GEO);
+        v.goto_next_bookmark();
+        assert_eq!(v.line_idx(), 0);
+        v.set_cursor_point(1, 1);
+        v.toggle_bookmark();
+        v.set_cursor_point(3, 0);
+        v.toggle_bookmark();
+        v.goto_next_bookmark();
+        assert_eq!(v.cursor.get_index(), 2);
+        v.goto_next_bookmark();
+        assert_eq!(v.line_idx(), 3);
+        v.goto_prev_bookmark();
+        assert_eq!(v.line_idx(), 1);
+        v.goto_prev_bookmark();
+        assert_eq!(v.line_idx(), 3);
+
+        // the bookmark follows its line
+        v.set_cursor_point(0, 0);
+        v.insert("\n\n");
+        v.goto_next_bookmark();
+        assert_eq!(v.line_idx(), 3);
+        assert_eq!(b.borrow().bookmarks().collect::<Vec<_>>(), vec![3, 5]);
+        v.toggle_bookmark();
+        assert_eq!(b.borrow().bookmarks().collect::<Vec<_>>(), vec![5]);
+
+        // the bookmarks are not part of the undo history
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "a\nb\nc\nd\ne\n");
+        assert_eq!(b.borrow().bookmarks().collect::<Vec<_>>(), vec![5]);
+        v.redo();
+        assert_eq!(b.borrow().bookmarks().collect::<Vec<_>>(), vec![5]);
+        // the ones past the end of the restored buffer move to its last line
+        v.set_cursor_point(7, 0);
+        v.toggle_bookmark();
+        v.undo();
+        assert_eq!(b.borrow().bookmarks().collect::<Vec<_>>(), vec![5]);
+
+        // drawn in the gutter, or on the left of the text without line numbers
+        let marks = |v: &View<'_>, width: f32| {
+            let theme = Theme::default();
+            let mut canvas = test_canvas();
+            v.draw(&mut canvas, &theme);
+            drawn_rects(&canvas, Some(rgb(theme.gutter_foreground)))
+                .into_iter()
+                .filter(|(_, _, w, _)| *w == width)
+                .collect::<Vec<_>>()
+        };
+        v.set_show_line_numbers(true);
+        assert_eq!(marks(&v, 5.0).len(), 1);
+        v.set_show_line_numbers(false);
+        assert_eq!(marks(&v, 2.0), vec![(0.0, 52.0, 2.0, 10.0)]);
+    }
+    #[test]
+    fn jump_list() {
+        let text: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&text)));
+        let mut v = View::new(b.clone(), GEO);
+        v.jump_back();
+        assert_eq!(v.line_idx(), 0);
+
+        v.goto_line(10);
+        v.goto_line(50);
+        v.search("line 80");
+        assert_eq!(v.line_idx(), 80);
+        v.jump_back();
+        assert_eq!(v.line_idx(), 50);
+        v.jump_back();
+        assert_eq!(v.line_idx(), 10);
+        v.jump_back();
+        assert_eq!(v.line_idx(), 0);
+        v.jump_back();
+        assert_eq!(v.line_idx(), 0);
+        v.jump_forward();
+        assert_eq!(v.line_idx(), 10);
+        v.jump_forward();
+        v.jump_forward();
+        assert_eq!(v.line_idx(), 80);
+        v.jump_forward();
+        assert_eq!(v.line_idx(), 80);
+
+        // a jump from the middle of the list drops the positions ahead
+        v.jump_back();
+        v.jump_back();
+        assert_eq!(v.line_idx(), 10);
+        v.goto_line(30);
+        v.jump_forward();
+        assert_eq!(v.line_idx(), 30);
+        v.jump_back();
+        assert_eq!(v.line_idx(), 10);
+
+        // the positions are clamped to the buffer
+        v.goto_line(99);
+        v.goto_line(0);
+        let len = b.borrow().len_chars();
+        b.borrow_mut().remove(10..len);
+        v.jump_back();
+        assert_eq!(v.cursor.get_index(), 10);
+    }
+    #[test]
+    fn jump_list_collapse_and_cap() {
+        use super::{Jump, JumpList, MAX_JUMPS};
+        let jump = |line| Jump { index: line * 10, line };
+        let mut jumps = JumpList::default();
+        jumps.push(jump(1));
+        jumps.push(jump(2));
+        jumps.push(Jump { index: 25, line: 2 });
+        assert_eq!(jumps.jumps, vec![jump(1), Jump { index: 25, line: 2 }]);
+
+        for line in 0..MAX_JUMPS + 10 {
+            jumps.push(jump(line));
+        }
+        assert_eq!(jumps.jumps.len(), MAX_JUMPS);
+        assert_eq!(jumps.jumps[0], jump(10));
+        assert_eq!(jumps.back(jump(500)), Some(jump(MAX_JUMPS + 9).index));
+    }
+    #[test]
+    fn scroll_past_end() {
+        let text: String = (0..20).map(|i| format!("{}\n", i)).collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&text)));
+        let mut v = View::new(b, GEO);
+        assert_eq!(v.page_length(), 9);
+
+        // the last line stays at the bottom of the page
+        v.scroll_past_end = 0;
+        v.set_first_visible_line(100);
+        assert_eq!(v.viewport.line_start, 11);
+        // the cursor line is always brought to the top
+        v.set_cursor_point(18, 0);
+        v.cursor_to_top();
+        assert_eq!(v.viewport.line_start, 18);
+
+        // a few blank lines after the last line
+        v.scroll_past_end = 3;
+        v.set_first_visible_line(100);
+        assert_eq!(v.viewport.line_start, 14);
+        v.center_cursor();
+        assert_eq!(v.viewport.line_start, 14);
+
+        // the last line up to the top
+        v.scroll_past_end = 100;
+        v.set_first_visible_line(100);
+        assert_eq!(v.viewport.line_start, 20);
+        v.cursor_to_top();
+        assert_eq!(v.viewport.line_start, 18);
+
+        // the bottom margin is kept after the last line when scrolling past it
+        v.set_scroll_margin(3);
+        v.set_first_visible_line(0);
+        v.set_cursor_point(20, 0);
+        v.focus_on_cursor();
+        assert_eq!(v.viewport.line_start, 14);
+        v.scroll_past_end = 0;
+        v.set_first_visible_line(0);
+        v.focus_on_cursor();
+        assert_eq!(v.viewport.line_start, 11);
+
+        // the mouse wheel still scrolls the last line up to the top
+        v.scroll_lines(100);
+        assert_eq!(v.viewport.line_start, 20);
+    }
+    #[test]
+    fn insert_newline() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("\tfoo")));
+        let mut v = View::new(b.clone(), GEO);
+        v.end(false);
+        v.insert_newline();
+        assert_eq!(b.borrow().to_string(), "\tfoo\n\t");
+        assert_eq!(v.cursor.get_index(), 6);
+
+        let b = Rc::new(RefCell::new(Buffer::from_str("    foo")));
+        let mut v = View::new(b.clone(), GEO);
+        v.end(false);
+        v.insert_newline();
+        assert_eq!(b.borrow().to_string(), "    foo\n    ");
+        assert_eq!(v.cursor.get_index(), 12);
+        v.insert_newline();
+        v.insert_newline();
+        assert_eq!(b.borrow().to_string(), "    foo\n\n\n    ");
+        assert_eq!(v.cursor.get_index(), 14);
+
+        let b = Rc::new(RefCell::new(Buffer::from_str("    foo")));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_cursor_point(0, 2);
+        v.insert_newline();
+        assert_eq!(b.borrow().to_string(), "  \n    foo");
+    }
+    #[test]
+    fn spaces_to_next_tab_stop() {
+        use super::spaces_to_next_tab_stop;
+        assert_eq!(spaces_to_next_tab_stop(0, 4), 4);
+        assert_eq!(spaces_to_next_tab_stop(1, 4), 3);
+        assert_eq!(spaces_to_next_tab_stop(3, 4), 1);
+        assert_eq!(spaces_to_next_tab_stop(4, 4), 4);
+        assert_eq!(spaces_to_next_tab_stop(6, 4), 2);
+        assert_eq!(spaces_to_next_tab_stop(5, 8), 3);
+        assert_eq!(spaces_to_next_tab_stop(7, 1), 1);
+    }
+    #[test]
+    fn insert_tab() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("\tab")));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_tab_width(4);
+        v.set_use_spaces(true);
+        v.end(false);
+        v.insert_tab();
+        assert_eq!(b.borrow().to_string(), "\tab  ");
+        v.insert_tab();
+        assert_eq!(b.borrow().to_string(), "\tab      ");
+        v.set_use_spaces(false);
+        v.insert_tab();
+        assert_eq!(b.borrow().to_string(), "\tab      \t");
+    }
+    #[test]
+    fn indent_selection() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("a\n  b\n\tc\nd")));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_tab_width(2);
+        v.set_use_spaces(true);
+        v.selection = Some(Selection::new(1, 7));
+        v.cursor.set_index(7);
+        v.indent_selection();
+        assert_eq!(b.borrow().to_string(), "  a\n    b\n  \tc\nd");
+        assert_eq!(v.get_selection(), Some("\n    b\n  \t".to_owned()));
+        assert_eq!(v.cursor.get_index(), 13);
+
+        v.dedent_selection();
+        assert_eq!(b.borrow().to_string(), "a\n  b\n\tc\nd");
+        assert_eq!(v.get_selection(), Some("\n  b\n\t".to_owned()));
+        v.dedent_selection();
+        assert_eq!(b.borrow().to_string(), "a\nb\nc\nd");
+        assert_eq!(v.get_selection(), Some("\nb\n".to_owned()));
+        assert_eq!(v.cursor.get_index(), 4);
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "a\n  b\n\tc\nd");
+
+        // the line where the selection ends is not indented
+        v.selection = Some(Selection::new(6, 0));
+        v.cursor.set_index(0);
+        v.set_use_spaces(false);
+        v.indent_selection();
+        assert_eq!(b.borrow().to_string(), "\ta\n\t  b\n\tc\nd");
+        assert_eq!(v.get_selection(), Some("\ta\n\t  b\n".to_owned()));
+        assert_eq!(v.cursor.get_index(), 0);
+    }
+    #[test]
+    fn transpose_chars() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("abcd\r\nx\n\u{e9}t")));
+        let mut v = View::new(b.clone(), GEO);
+        v.transpose_chars();
+        assert_eq!(b.borrow().to_string(), "abcd\r\nx\n\u{e9}t");
+        assert!(!v.can_undo());
+
+        v.cursor.set_index(1);
+        v.transpose_chars();
+        assert_eq!(b.borrow().to_string(), "bacd\r\nx\n\u{e9}t");
+        assert_eq!(v.cursor.get_index(), 2);
+        v.transpose_chars();
+        assert_eq!(b.borrow().to_string(), "bcad\r\nx\n\u{e9}t");
+        assert_eq!(v.cursor.get_index(), 3);
+
+        // at the end of a line, the line ending is not moved
+        v.cursor.set_index(4);
+        v.transpose_chars();
+        assert_eq!(b.borrow().to_string(), "bcda\r\nx\n\u{e9}t");
+        assert_eq!(v.cursor.get_index(), 4);
+        v.cursor.set_index(7);
+        v.transpose_chars();
+        assert_eq!(b.borrow().to_string(), "bcda\r\nx\n\u{e9}t");
+
+        // at the end of the buffer
+        v.cursor.set_index(10);
+        v.transpose_chars();
+        assert_eq!(b.borrow().to_string(), "bcda\r\nx\nt\u{e9}");
+        assert_eq!(v.cursor.get_index(), 10);
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "bcda\r\nx\n\u{e9}t");
+    }
+    #[test]
+    fn change_case() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("un caf\u{e9} stra\u{df}e")));
+        let mut v = View::new(b.clone(), GEO);
+        v.uppercase_selection();
+        assert!(!v.can_undo());
+
+        v.selection = Some(Selection::new(3, 7));
+        v.cursor.set_index(7);
+        v.uppercase_selection();
+        assert_eq!(b.borrow().to_string(), "un CAF\u{c9} stra\u{df}e");
+        assert_eq!(v.get_selection(), Some("CAF\u{c9}".to_owned()));
+
+        v.select_all();
+        v.uppercase_selection();
+        assert_eq!(b.borrow().to_string(), "UN CAF\u{c9} STRASSE");
+        assert_eq!(v.cursor.get_index(), 15);
+        v.lowercase_selection();
+        assert_eq!(b.borrow().to_string(), "un caf\u{e9} strasse");
+
+        v.selection = Some(Selection::new(15, 0));
+        v.cursor.set_index(0);
+        v.titlecase_selection();
+        assert_eq!(b.borrow().to_string(), "Un Caf\u{e9} Strasse");
+        let s = v.selection.unwrap();
+        assert_eq!((s.start, s.end), (15, 0));
+        assert_eq!(v.cursor.get_index(), 0);
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "un caf\u{e9} strasse");
+    }
+    #[test]
+    fn delete_line() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("one\ntwo\r\nthree\nfour")));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_cursor_point(1, 2);
+        v.delete_line();
+        assert_eq!(b.borrow().to_string(), "one\nthree\nfour");
+        assert_eq!(v.cursor.get_index(), 4);
+        v.set_cursor_point(0, 1);
+        v.delete_line();
+        assert_eq!(b.borrow().to_string(), "three\nfour");
+        assert_eq!(v.cursor.get_index(), 0);
+        v.set_cursor_point(1, 3);
+        v.delete_line();
+        assert_eq!(b.borrow().to_string(), "three");
+        assert_eq!(v.cursor.get_index(), 0);
+        v.delete_line();
+        assert_eq!(b.borrow().to_string(), "");
+        assert_eq!(v.cursor.get_index(), 0);
+        v.delete_line();
+        assert_eq!(b.borrow().to_string(), "");
+        v.undo();
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "three\nfour");
+    }
+    #[test]
+    fn duplicate() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("one\ntwo")));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_cursor_point(0, 1);
+        v.duplicate();
+        assert_eq!(b.borrow().to_string(), "one\none\ntwo");
+        assert_eq!(v.cursor.get_index(), 5);
+        v.set_cursor_point(2, 2);
+        v.duplicate();
+        assert_eq!(b.borrow().to_string(), "one\none\ntwo\ntwo");
+        assert_eq!(v.cursor.get_index(), 14);
+
+        v.selection = Some(Selection::new(1, 5));
+        v.cursor.set_index(5);
+        v.duplicate();
+        assert_eq!(b.borrow().to_string(), "one\none\none\ntwo\ntwo");
+        assert_eq!(v.get_selection(), Some("ne\no".to_owned()));
+        assert_eq!(v.cursor.get_index(), 9);
+    }
+    #[test]
+    fn move_line() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("one\r\ntwo\nthree")));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_cursor_point(1, 1);
+        v.move_line_up();
+        assert_eq!(b.borrow().to_string(), "two\r\none\nthree");
+        assert_eq!(v.cursor.get_index(), 1);
+        v.move_line_up();
+        assert_eq!(b.borrow().to_string(), "two\r\none\nthree");
+        assert_eq!(v.cursor.get_index(), 1);
+
+        v.move_line_down();
+        assert_eq!(b.borrow().to_string(), "one\r\ntwo\nthree");
+        assert_eq!(v.cursor.get_index(), 6);
+        v.move_line_down();
+        assert_eq!(b.borrow().to_string(), "one\r\nthree\ntwo");
+        assert_eq!(v.cursor.get_index(), 12);
+        v.move_line_down();
+        assert_eq!(b.borrow().to_string(), "one\r\nthree\ntwo");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one\r\ntwo\nthree");
+    }
+    #[test]
+    fn reload() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("nonedit_{}_view_reload.txt", std::process::id()));
+        std::fs::write(&path, "one\ntwo\nthree").unwrap();
+        let b = Rc::new(RefCell::new(Buffer::from_file(&path).unwrap()));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_cursor_point(1, 2);
+        v.insert("ee");
+
+        std::fs::write(&path, "1\n2222\n3").unwrap();
+        v.reload().unwrap();
+        assert_eq!(b.borrow().to_string(), "1\n2222\n3");
+        assert_eq!((v.line_idx(), v.col_idx()), (1, 4));
+
+        v.set_cursor_point(2, 1);
+        std::fs::write(&path, "1").unwrap();
+        v.reload().unwrap();
+        assert_eq!(v.cursor.get_index(), 1);
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "1\n2222\n3");
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn can_undo() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("")));
+        let mut v = View::new(b.clone(), GEO);
+        assert!(!v.can_undo());
+        assert!(!v.can_redo());
+        v.redo();
+        assert_eq!(b.borrow().to_string(), "");
+
+        v.insert("one");
+        v.insert_linefeed();
+        assert!(v.can_undo());
+        assert!(!v.can_redo());
+        assert_eq!(v.undo_depth(), 2);
+
+        v.undo();
+        assert_eq!((v.undo_depth(), v.redo_depth()), (1, 1));
+        v.undo();
+        assert!(!v.can_undo());
+        assert_eq!(v.redo_depth(), 2);
+        v.redo();
+        assert_eq!(b.borrow().to_string(), "one");
+        assert_eq!((v.undo_depth(), v.redo_depth()), (1, 1));
+        v.redo();
+        assert_eq!(b.borrow().to_string(), "one\n");
+        assert!(!v.can_redo());
+
+        v.undo();
+        v.insert("two");
+        assert_eq!(v.undo_depth(), 2);
+        assert!(!v.can_redo());
+    }
+    #[test]
+    fn undo_groups() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("")));
+        let mut v = View::new(b.clone(), GEO);
+        for c in "one two".chars() {
+            v.insert_char(c);
+        }
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "");
+        v.redo();
+        assert_eq!(b.borrow().to_string(), "one two");
+
+        // a cursor move starts a new group
+        v.insert_char('a');
+        v.cursor_left();
+        v.insert_char('b');
+        assert_eq!(b.borrow().to_string(), "one twoba");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one twoa");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one two");
+
+        // deletions are grouped apart from insertions
+        v.cursor.set_index(7);
+        v.insert_char('s');
+        v.backspace();
+        v.backspace();
+        assert_eq!(b.borrow().to_string(), "one tw");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one twos");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one two");
+
+        // a line feed or an explicit break ends the group
+        v.cursor.set_index(7);
+        v.insert_char('!');
+        v.insert_char('\n');
+        v.insert_char('x');
+        v.break_undo_group();
+        v.insert_char('y');
+        assert_eq!(b.borrow().to_string(), "one two!\nxy");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one two!\nx");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one two!\n");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one two!");
+    }
+    #[test]
+    fn expand_selection_to_words() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("one two_2, three four")));
+        let mut v = View::new(b, GEO);
+        // from the middle of two to the middle of three
+        v.selection = Some(Selection::new(5, 13));
+        v.expand_selection_to_words();
+        assert_eq!(v.get_selection(), Some("two_2, three".to_owned()));
+        assert_eq!(v.index(), 16);
+        // backward, the ends are already on boundaries
+        v.selection = Some(Selection::new(9, 4));
+        v.expand_selection_to_words();
+        assert_eq!(v.get_selection(), Some("two_2".to_owned()));
+        assert_eq!(v.index(), 4);
+        // the end is in a word, the start is on a space
+        v.selection = Some(Selection::new(3, 6));
+        v.expand_selection_to_words();
+        assert_eq!(v.get_selection(), Some(" two_2".to_owned()));
+        // an empty selection selects the word under the cursor
+        v.clear_selection();
+        v.set_index(19);
+        v.expand_selection_to_words();
+        assert_eq!(v.get_selection(), Some("four".to_owned()));
+        assert_eq!(v.index(), 21);
+    }
+    #[test]
+    fn reverse_lines() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("one\ntwo\r\nthree\nfour")));
+        let mut v = View::new(b.clone(), GEO);
+        v.selection = Some(Selection::new(1, 10));
+        v.reverse_lines();
+        assert_eq!(b.borrow().to_string(), "three\ntwo\r\none\nfour");
+        assert_eq!(v.get_selection(), Some("three\ntwo\r\none".to_owned()));
+        assert_eq!(v.index(), 14);
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one\ntwo\r\nthree\nfour");
 
-        let mut line_index = first_visible_line;
-        for line in self.buffer.borrow().lines().skip(first_visible_line).take(page_len + 1) {
-            let mut style = self
-                .styling
-                .as_ref()
-                .and_then(|s| s.result.get(line_index))
-                .map(|s| s.iter());
-            let mut idx = self.buffer.borrow().line_to_char(line_index);
+        // the last line has no line ending, no blank line is added
+        v.select_all();
+        v.reverse_lines();
+        assert_eq!(b.borrow().to_string(), "four\nthree\r\ntwo\none");
+        assert_eq!(b.borrow().len_lines(), 4);
 
-            for c in line.chars() {
-                let x = (current_col - first_visible_col as i32) as f32 * adv;
+        // a single line is left as is
+        v.clear_selection();
+        v.reverse_lines();
+        assert_eq!(v.undo_depth(), 1);
 
-                let fg = match style.as_mut().and_then(|s| s.next()) {
-                    None => Color::from_rgb(255, 255, 255),
-                    Some(s) => Color::from_rgb(s.foreground.r, s.foreground.g, s.foreground.b),
-                };
-                match self.selection {
-                    Some(sel) if sel.contains(idx) => {
-                        let color = STYLE.theme.settings.selection.unwrap_or(highlighting::Color::WHITE);
-                        canvas.set_color(Color::from_rgb(color.r, color.g, color.b));
-                        canvas.move_to(x as _, y - canvas.font_metrics.descender - line_spacing);
-                        canvas.draw_rect(adv as _, line_spacing as _);
-                    }
-                    _ => (),
-                }
-                match c {
-                    '\t' => {
-                        let nbspace = ((current_col + tabsize) / tabsize) * tabsize;
-                        current_col = nbspace;
-                    }
-                    '\0' => (),
-                    '\r' => (), //idx -= 1,
-                    '\n' => (),
-                    // Bom hiding. TODO: rework
-                    '\u{feff}' | '\u{fffe}' => (),
-                    _ => {
-                        canvas.move_to(x as _, y as _);
-                        canvas.set_color(fg);
-                        canvas.draw_char(c);
-                        current_col += 1;
-                    }
-                }
-                idx += 1;
-            }
-            line_index += 1;
-            y += line_spacing;
-            current_col = 0;
-        }
+        // the cursor stays before the line ending of the last line
+        let b = Rc::new(RefCell::new(Buffer::from_str("a\r\nbbb\r\ncc\r\n")));
+        let mut v = View::new(b.clone(), GEO);
+        v.move_cursor(Direction::Down, true);
+        v.move_cursor(Direction::Down, true);
+        v.reverse_lines();
+        assert_eq!(b.borrow().to_string(), "bbb\r\na\r\ncc\r\n");
+        assert_eq!(v.get_selection(), Some("bbb\r\na".to_owned()));
+        assert_eq!((v.line_idx(), v.col_idx()), (1, 1));
+        assert_eq!(v.index(), 6);
+    }
+    #[test]
+    fn dedupe_lines() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("a\na\r\nb\na\nc\nc\nc")));
+        let mut v = View::new(b.clone(), GEO);
+        v.dedupe_lines();
+        assert_eq!(b.borrow().to_string(), "a\nb\na\nc");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "a\na\r\nb\na\nc\nc\nc");
 
-        // Cursor
-        let fg = STYLE.theme.settings.caret.unwrap_or(highlighting::Color::WHITE);
-        let (mut line, mut col) = (self.cursor.get_line(), self.cursor.get_col());
+        // only in the selected lines
+        v.selection = Some(Selection::new(0, 5));
+        v.dedupe_lines();
+        assert_eq!(b.borrow().to_string(), "a\nb\na\nc\nc\nc");
 
-        if self.viewport.contain(line, col) {
-            line -= first_visible_line;
-            col -= first_visible_col;
-            canvas.move_to(
-                col as f32 * adv,
-                line as f32 * line_spacing - canvas.font_metrics.descender,
-            );
-            canvas.set_color(Color::from_rgb(fg.r, fg.g, fg.b));
-            canvas.draw_rect(2.0, line_spacing as _);
-        }
+        let b = Rc::new(RefCell::new(Buffer::from_str("x\ny\n")));
+        let mut v = View::new(b.clone(), GEO);
+        v.dedupe_lines();
+        assert_eq!(b.borrow().to_string(), "x\ny\n");
+        assert_eq!(v.undo_depth(), 0);
     }
+    #[test]
+    fn dedupe_all_lines() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("a\nb\na\nc\nb\n\nd\n")));
+        let mut v = View::new(b.clone(), GEO);
+        v.dedupe_all_lines();
+        assert_eq!(b.borrow().to_string(), "a\nb\nc\n\nd\n");
+        assert_eq!(v.index(), 0);
 
-    /// clear the current selection
-    pub fn clear_selection(&mut self) {
-        self.selection = None;
+        let b = Rc::new(RefCell::new(Buffer::from_str("a\nb\na")));
+        let mut v = View::new(b.clone(), GEO);
+        v.dedupe_all_lines();
+        assert_eq!(b.borrow().to_string(), "a\nb");
     }
-    fn expand_selection(&mut self) {
-        self.selection = if let Some(mut selection) = self.selection {
-            selection.expand(self.cursor.get_index());
-            Some(selection)
-        } else {
-            Some(Selection::new(
-                self.cursor.get_previous_index(),
-                self.cursor.get_index(),
-            ))
-        }
+    #[test]
+    fn select_all() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("one\ntwo\r\nthree\n")));
+        let mut v = View::new(b, GEO);
+        v.select_all();
+        let r: std::ops::Range<usize> = v.selection.unwrap().into();
+        assert_eq!(r, 0..15);
+        assert_eq!(v.cursor.get_index(), 15);
+        assert_eq!(v.get_selection(), Some("one\ntwo\r\nthree\n".to_owned()));
     }
-}
+    #[test]
+    fn trim_trailing_whitespace() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("nonedit_{}_view_trim.txt", std::process::id()));
+        std::fs::write(&path, "one  \ntwo\t\n").unwrap();
+        let b = Rc::new(RefCell::new(Buffer::from_file(&path).unwrap()));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_cursor_point(0, 5);
 
-pub trait ViewCmd {
-    fn name(&self) -> &'static str;
-    fn desc(&self) -> &'static str;
-    fn keybinding(&self) -> Vec<KeyBinding>;
-    fn run(&mut self, _: &mut View<'_>);
-}
+        v.set_trim_on_save(false);
+        v.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one  \ntwo\t\n");
 
-#[cfg(test)]
-mod tests {
-    use crate::buffer::Buffer;
-    use crate::view::View;
-    use crate::window::Geometry;
-    use std::cell::RefCell;
-    use std::rc::Rc;
+        v.set_trim_on_save(true);
+        v.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        assert_eq!(v.cursor.get_index(), 3);
+        assert!(!b.borrow().is_dirty());
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one  \ntwo\t\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn final_newline_on_save() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("nonedit_{}_view_final_newline.txt", std::process::id()));
+        std::fs::write(&path, "one\ntwo").unwrap();
+        let b = Rc::new(RefCell::new(Buffer::from_file(&path).unwrap()));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_cursor_point(1, 3);
 
-    const GEO: Geometry = Geometry {
-        x: 0.0,
-        y: 0.0,
-        w: 100.0,
-        h: 100.0,
-        font_advance: 10.0,
-        font_height: 10.0,
-    };
+        v.set_final_newline_on_save(false);
+        v.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo");
 
-    #[test]
-    fn new_view() {
-        let b = Rc::new(RefCell::new(Buffer::new()));
-        let _v = View::new(b, GEO);
+        v.set_final_newline_on_save(true);
+        v.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        assert_eq!(v.cursor.get_index(), 7);
+        assert!(v.can_undo());
+        v.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "one\ntwo");
+        std::fs::remove_file(&path).unwrap();
     }
     #[test]
-    fn insert() {
-        let b = Rc::new(RefCell::new(Buffer::from_str("text")));
+    fn paragraph_motion() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("a\nb\n\n  \nc\r\n\t\r\nd\ne")));
         let mut v = View::new(b, GEO);
-        v.insert_char('r');
-        assert_eq!(v.to_string(), "rtext");
-        v.insert_char('e');
-        assert_eq!(v.to_string(), "retext");
-        v.cursor.set_index(6);
-        v.insert_char('e');
-        assert_eq!(v.to_string(), "retexte");
-        v.insert_char('f');
-        assert_eq!(v.to_string(), "retextef");
+        v.move_paragraph_down();
+        assert_eq!(v.line_idx(), 2);
+        v.move_paragraph_down();
+        assert_eq!(v.line_idx(), 5);
+        v.move_paragraph_down();
+        assert_eq!(v.line_idx(), 7);
+        assert_eq!(v.cursor.get_index(), 17);
+        v.move_paragraph_down();
+        assert_eq!(v.cursor.get_index(), 17);
+
+        v.move_paragraph_up();
+        assert_eq!(v.line_idx(), 5);
+        v.move_paragraph_up();
+        assert_eq!(v.line_idx(), 3);
+        v.move_paragraph_up();
+        assert_eq!(v.cursor.get_index(), 0);
+        v.move_paragraph_up();
+        assert_eq!(v.cursor.get_index(), 0);
     }
     #[test]
-    fn multiple_view() {
-        let buf = Rc::new(RefCell::new(Buffer::from_str("text")));
-        let mut v1 = View::new(buf.clone(), GEO);
-        let mut v2 = View::new(buf.clone(), GEO);
-        v1.insert_char('r');
-        assert_eq!(v2.to_string(), "rtext");
-        v2.insert_char('e');
-        assert_eq!(v1.to_string(), "ertext");
+    fn toggle_comment() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("fn a() {\n    b();\n\n\tc();\n}")));
+        let mut v = View::new(b.clone(), GEO);
+        v.set_comment_prefix("// ");
+        v.selection = Some(Selection::new(9, 24));
+        v.cursor.set_index(24);
+        v.toggle_comment();
+        assert_eq!(b.borrow().to_string(), "fn a() {\n    // b();\n\n\t// c();\n}");
+        assert_eq!(v.get_selection(), Some("    // b();\n\n\t// c();".to_owned()));
+
+        v.toggle_comment();
+        assert_eq!(b.borrow().to_string(), "fn a() {\n    b();\n\n\tc();\n}");
+        assert_eq!(v.get_selection(), Some("    b();\n\n\tc();".to_owned()));
+
+        // a line without comment is commented again, a prefix without its space is removed
+        b.borrow_mut().insert(17, "//");
+        v.selection = None;
+        v.cursor.set_index(0);
+        v.select_all();
+        v.toggle_comment();
+        assert_eq!(b.borrow().to_string(), "// fn a() {\n    // b();//\n\n\t// c();\n// }");
+        v.toggle_comment();
+        v.selection = None;
+        v.set_cursor_point(1, 0);
+        b.borrow_mut().insert(13, "//");
+        v.toggle_comment();
+        assert_eq!(b.borrow().to_string(), "fn a() {\n    b();//\n\n\tc();\n}");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "fn a() {\n    //b();//\n\n\tc();\n}");
     }
+    #[test]
+    fn smart_home() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("    let x;\n\t \r\n\n ab")));
+        let mut v = View::new(b, GEO);
+        v.set_cursor_point(0, 7);
+        v.home(false);
+        assert_eq!(v.cursor.get_index(), 4);
+        v.home(false);
+        assert_eq!(v.cursor.get_index(), 0);
+        v.home(false);
+        assert_eq!(v.cursor.get_index(), 4);
+        // from the indentation, the first press goes to the first non whitespace char
+        v.set_cursor_point(0, 2);
+        v.home(false);
+        assert_eq!(v.cursor.get_index(), 4);
+
+        // blank and empty lines
+        v.set_cursor_point(1, 2);
+        v.home(false);
+        assert_eq!(v.cursor.get_index(), 11);
+        v.home(false);
+        assert_eq!(v.cursor.get_index(), 11);
+        v.set_cursor_point(2, 0);
+        v.home(false);
+        assert_eq!(v.cursor.get_index(), 15);
 
+        v.set_cursor_point(3, 3);
+        v.select_home();
+        assert_eq!(v.get_selection(), Some("ab".to_owned()));
+        v.select_home();
+        assert_eq!(v.get_selection(), Some(" ab".to_owned()));
+    }
     #[test]
-    fn set_index_oob() {
-        let b = Rc::new(RefCell::new(Buffer::from_str("text")));
+    fn select_home_end() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("one\ntwo three\r\nfour")));
         let mut v = View::new(b, GEO);
-        v.cursor.set_index(5);
+        let range = |v: &View<'_>| -> std::ops::Range<usize> { v.selection.unwrap().into() };
+
+        v.set_cursor_point(1, 3);
+        v.select_home();
+        assert_eq!(range(&v), 4..7);
         assert_eq!(v.cursor.get_index(), 4);
+        // the anchor stays where the selection started
+        v.select_end();
+        assert_eq!(range(&v), 7..13);
+        assert_eq!(v.get_selection(), Some(" three".to_owned()));
+        assert_eq!(v.cursor.get_index(), 13);
+
+        v.clear_selection();
+        v.set_cursor_point(1, 3);
+        v.select_document_start();
+        assert_eq!(range(&v), 0..7);
+        assert_eq!(v.cursor.get_index(), 0);
+        v.select_document_end();
+        assert_eq!(range(&v), 7..19);
+        assert_eq!(v.get_selection(), Some(" three\r\nfour".to_owned()));
+        assert_eq!(v.cursor.get_index(), 19);
     }
+    #[test]
+    fn word_and_line_range() {
+        use super::{line_range_at, word_range_at};
+        let b = Buffer::from_str("let foo_1 = bar;\nlast");
+        assert_eq!(word_range_at(&b, 5), Some(4..9));
+        assert_eq!(word_range_at(&b, 4), Some(4..9));
+        assert_eq!(word_range_at(&b, 9), Some(4..9));
+        assert_eq!(word_range_at(&b, 10), None);
+        assert_eq!(word_range_at(&b, 15), Some(12..15));
+        assert_eq!(word_range_at(&b, 19), Some(17..21));
+        assert_eq!(word_range_at(&b, 21), Some(17..21));
+        assert_eq!(line_range_at(&b, 5), 0..17);
+        assert_eq!(line_range_at(&b, 16), 0..17);
+        assert_eq!(line_range_at(&b, 18), 17..21);
+        assert_eq!(line_range_at(&b, 21), 17..21);
 
+        let b = Rc::new(RefCell::new(b));
+        let mut v = View::new(b, GEO);
+        v.select_word_at(6);
+        assert_eq!(v.get_selection(), Some("foo_1".to_owned()));
+        assert_eq!(v.cursor.get_index(), 9);
+        v.select_line_at(3);
+        assert_eq!(v.get_selection(), Some("let foo_1 = bar;\n".to_owned()));
+        assert_eq!(v.cursor.get_index(), 17);
+    }
     #[test]
-    fn set_index() {
-        let b = Rc::new(RefCell::new(Buffer::from_str("text\nplop")));
+    fn matching_bracket() {
+        use super::matching_bracket;
+        let b = Buffer::from_str("x(a[b]c{(d)})(");
+        assert_eq!(matching_bracket(&b, 1), Some(12));
+        assert_eq!(matching_bracket(&b, 12), Some(1));
+        assert_eq!(matching_bracket(&b, 3), Some(5));
+        assert_eq!(matching_bracket(&b, 5), Some(3));
+        assert_eq!(matching_bracket(&b, 7), Some(11));
+        assert_eq!(matching_bracket(&b, 10), Some(8));
+        assert_eq!(matching_bracket(&b, 0), None);
+        assert_eq!(matching_bracket(&b, 13), None);
+        assert_eq!(matching_bracket(&b, 14), None);
+
+        let b = Rc::new(RefCell::new(Buffer::from_str("(a[b]c)")));
         let mut v = View::new(b, GEO);
+        v.goto_matching_bracket();
+        assert_eq!(v.cursor.get_index(), 6);
+        v.goto_matching_bracket();
+        assert_eq!(v.cursor.get_index(), 0);
         v.cursor.set_index(4);
-        assert_eq!(v.cursor.get_index(), 4);
+        v.goto_matching_bracket();
+        assert_eq!(v.cursor.get_index(), 2);
+        v.cursor.set_index(1);
+        v.goto_matching_bracket();
+        assert_eq!(v.cursor.get_index(), 1);
+    }
+    #[test]
+    fn bracket_pair() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("f(a[b]) x")));
+        let mut v = View::new(b, GEO);
+        assert_eq!(v.bracket_pair(), None);
+        v.cursor.set_index(1);
+        assert_eq!(v.bracket_pair(), Some((1, 6)));
+        v.cursor.set_index(2);
+        assert_eq!(v.bracket_pair(), Some((1, 6)));
         v.cursor.set_index(5);
-        assert_eq!(v.cursor.get_index(), 5);
+        assert_eq!(v.bracket_pair(), Some((5, 3)));
+        v.cursor.set_index(7);
+        assert_eq!(v.bracket_pair(), Some((6, 1)));
+        v.cursor.set_index(8);
+        assert_eq!(v.bracket_pair(), None);
     }
-
     #[test]
-    fn cursor_up() {
-        let b = Rc::new(RefCell::new(Buffer::from_str(
-            "text\nhello\nme!\nan other very long line",
-        )));
+    fn horizontal_scroll() {
+        let text: String = (0..50).map(|i| std::char::from_digit(i % 10, 10).unwrap()).collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&format!("{}\n\t\t\t\tx", text))));
         let mut v = View::new(b, GEO);
-        v.cursor.set_index(11);
-        v.cursor_up();
-        assert_eq!(v.cursor.get_index(), 5);
-        v.cursor_up();
-        assert_eq!(v.cursor.get_index(), 0);
-        v.cursor_up();
-        assert_eq!(v.cursor.get_index(), 0);
+        assert_eq!(v.viewport.width, 9);
+        v.set_cursor_point(0, 4);
+        v.focus_on_cursor();
+        assert_eq!(v.viewport.col_start, 0);
+        v.set_cursor_point(0, 6);
+        v.focus_on_cursor();
+        assert_eq!(v.viewport.col_start, 1);
+        v.end(false);
+        assert_eq!(v.viewport.col_start, 45);
+        v.set_cursor_point(0, 49);
+        v.focus_on_cursor();
+        assert_eq!(v.viewport.col_start, 45);
+        v.set_cursor_point(0, 46);
+        v.focus_on_cursor();
+        assert_eq!(v.viewport.col_start, 42);
+        v.home(false);
+        assert_eq!(v.viewport.col_start, 0);
 
-        v.cursor.set_index(25);
-        v.cursor_up();
-        assert_eq!(v.cursor.get_index(), 14);
-        v.cursor_up();
-        assert_eq!(v.cursor.get_index(), 10);
-        v.cursor_up();
-        assert_eq!(v.cursor.get_index(), 4);
+        // tabs are expanded to the next tab stop
+        v.set_cursor_point(1, 17);
+        v.focus_on_cursor();
+        assert_eq!(v.col_idx(), 17);
+        assert_eq!(v.viewport.col_start, 12);
     }
+    #[test]
+    fn overtype() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("abc\r\nd")));
+        let mut v = View::new(b.clone(), GEO);
+        let caret_width = |v: &View<'_>| {
+            let mut canvas = test_canvas();
+            v.draw(&mut canvas, &Theme::default());
+            // the caret is the last rect drawn
+            drawn_rects(&canvas, None).pop().map(|(_, _, w, _)| w).unwrap()
+        };
+        assert_eq!(caret_width(&v), 2.0);
+        let state = v.draw_state();
+
+        v.toggle_overtype();
+        assert!(v.is_overtype());
+        assert_ne!(v.draw_state(), state);
+        assert_eq!(caret_width(&v), GEO.font_advance);
+        v.insert_char('x');
+        v.insert_char('y');
+        assert_eq!(b.borrow().to_string(), "xyc\r\nd");
+        v.insert_char('z');
+        v.insert_char('w');
+        assert_eq!(b.borrow().to_string(), "xyzw\r\nd");
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "abc\r\nd");
+
+        // the selection is replaced as in insert mode
+        v.select_all();
+        v.insert_char('x');
+        assert_eq!(b.borrow().to_string(), "x");
 
+        v.toggle_overtype();
+        v.cursor.set_index(0);
+        v.insert_char('y');
+        assert_eq!(b.borrow().to_string(), "yx");
+    }
     #[test]
-    fn cursor_down() {
-        let b = Rc::new(RefCell::new(Buffer::from_str(
-            "a long text line\nhello\nme!\nan other very long line",
-        )));
+    fn scroll_margin() {
+        let text: String = (0..100).map(|_| "x\n").collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&text)));
         let mut v = View::new(b, GEO);
-        v.cursor_down();
-        assert_eq!(v.cursor.get_index(), 17);
-        v.cursor_down();
-        assert_eq!(v.cursor.get_index(), 23);
-        v.cursor_down();
-        assert_eq!(v.cursor.get_index(), 27);
-        v.cursor_down();
-        assert_eq!(v.cursor.get_index(), 27);
+        assert_eq!(v.page_length(), 9);
+        v.set_scroll_margin(3);
+        for _ in 0..6 {
+            v.move_cursor(Direction::Down, false);
+        }
+        assert_eq!(v.viewport.line_start, 0);
+        v.move_cursor(Direction::Down, false);
+        assert_eq!(v.viewport.line_start, 1);
 
-        v.cursor.set_index(10); // on the second t of text
-        v.cursor_down();
-        assert_eq!(v.cursor.get_index(), 22);
-        v.cursor_down();
-        assert_eq!(v.cursor.get_index(), 26);
-        v.cursor_down();
-        assert_eq!(v.cursor.get_index(), 37);
-        v.cursor_down();
-        assert_eq!(v.cursor.get_index(), 37);
+        // no margin after the last line
+        v.set_cursor_point(100, 0);
+        v.focus_on_cursor();
+        assert_eq!(v.viewport.line_start, 91);
+        v.set_cursor_point(94, 0);
+        v.focus_on_cursor();
+        assert_eq!(v.viewport.line_start, 91);
+        v.move_cursor(Direction::Up, false);
+        assert_eq!(v.viewport.line_start, 90);
+
+        // the margin is limited to half the page
+        v.set_scroll_margin(100);
+        v.set_cursor_point(50, 0);
+        v.focus_on_cursor();
+        assert_eq!(v.viewport.line_start, 46);
+        v.move_cursor(Direction::Down, false);
+        assert_eq!(v.viewport.line_start, 46);
+        v.move_cursor(Direction::Down, false);
+        assert_eq!(v.viewport.line_start, 47);
+        v.set_cursor_point(0, 0);
+        v.focus_on_cursor();
+        assert_eq!(v.viewport.line_start, 0);
     }
     #[test]
-    fn cursor_left() {
-        let b = Rc::new(RefCell::new(Buffer::from_str("text\nhello\n")));
+    fn center_cursor() {
+        let text: String = (0..100).map(|_| "x\n").collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&text)));
         let mut v = View::new(b, GEO);
-        v.cursor_left();
-        assert_eq!(v.cursor.get_index(), 0);
+        assert_eq!(v.page_length(), 9);
+        v.set_cursor_point(50, 0);
+        v.center_cursor();
+        assert_eq!(v.viewport.line_start, 46);
+        v.cursor_to_top();
+        assert_eq!(v.viewport.line_start, 50);
+        v.cursor_to_bottom();
+        assert_eq!(v.viewport.line_start, 41);
+
+        v.set_cursor_point(2, 0);
+        v.center_cursor();
+        assert_eq!(v.viewport.line_start, 0);
+        v.cursor_to_bottom();
+        assert_eq!(v.viewport.line_start, 0);
+
+        v.set_cursor_point(98, 0);
+        v.center_cursor();
+        assert_eq!(v.viewport.line_start, 91);
+
+        let b = Rc::new(RefCell::new(Buffer::from_str("a\nb")));
+        let mut v = View::new(b, GEO);
+        v.set_cursor_point(1, 0);
+        v.center_cursor();
+        assert_eq!(v.viewport.line_start, 0);
+    }
+    #[test]
+    fn edit_transaction() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("world")));
+        let mut v = View::new(b.clone(), GEO);
+        v.cursor.set_index(5);
+        v.edit(|b| {
+            b.insert(0, "hello ");
+            b.insert(11, "!");
+        });
+        assert_eq!(b.borrow().to_string(), "hello world!");
+        assert_eq!(v.undo_depth(), 1);
+        v.undo();
+        assert_eq!(b.borrow().to_string(), "world");
+        assert!(!v.can_undo());
 
         v.cursor.set_index(5);
-        v.cursor_left();
-        assert_eq!(v.cursor.get_index(), 4);
+        v.edit(|b| b.remove(2..5));
+        assert_eq!(v.cursor.get_index(), 2);
+    }
+    #[test]
+    fn draw_highlights() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("fn a(x) {\n\tfoo(x);\r\n}\nfoo\n")));
+        let mut v = View::new(b, GEO);
+        v.search("foo");
+        v.selection = Some(Selection::new(12, 3));
+        v.cursor.set_index(4);
+        let theme = Theme::default();
+        let mut canvas = test_canvas();
+        v.draw(&mut canvas, &theme);
 
-        v.cursor.set_index(2);
-        v.cursor_left();
-        assert_eq!(v.cursor.get_index(), 1);
+        // count the rects drawn with each color
+        let count = |c| drawn_rects(&canvas, Some(rgb(c))).len();
+        assert_eq!(count(theme.find_highlight), 6);
+        assert_eq!(count(theme.bracket_highlight), 2);
+        assert_eq!(count(theme.selection), 9);
+        assert_eq!(drawn_rects(&canvas, None).len(), 18);
+    }
+    #[test]
+    fn draw_selection_to_line_end() {
+        fn selection_rects(v: &View<'_>, theme: &Theme) -> Vec<(f32, f32, f32)> {
+            let mut canvas = test_canvas();
+            v.draw(&mut canvas, theme);
+            drawn_rects(&canvas, Some(rgb(theme.selection)))
+                .into_iter()
+                .map(|(x, y, w, _)| (x, y, w))
+                .collect()
+        }
 
-        v.cursor.set_index(6);
-        v.cursor_left();
-        assert_eq!(v.cursor.get_index(), 5);
+        let theme = Theme::default();
+        let b = Rc::new(RefCell::new(Buffer::from_str("ab\ncd\nef")));
+        let mut v = View::new(b, GEO);
+        v.set_show_line_numbers(false);
+        v.selection = Some(Selection::new(1, 7));
+
+        // b, the newline, c, d, the newline, e
+        v.selection_to_line_end = false;
+        let rects = selection_rects(&v, &theme);
+        assert_eq!(rects.len(), 6);
+        assert_eq!(rects[1], (20.0, 2.0, 10.0));
+        assert_eq!(rects[4], (20.0, 12.0, 10.0));
+
+        v.selection_to_line_end = true;
+        let rects = selection_rects(&v, &theme);
+        assert_eq!(rects.len(), 6);
+        assert_eq!(rects[0], (10.0, 2.0, 10.0));
+        assert_eq!(rects[1], (20.0, 2.0, 80.0));
+        assert_eq!(rects[4], (20.0, 12.0, 80.0));
+        assert_eq!(rects[5], (0.0, 22.0, 10.0));
     }
     #[test]
-    fn cursor_right() {
-        let b = Rc::new(RefCell::new(Buffer::from_str("tt\nh\n")));
+    fn draw_tab_stops() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("\tx\na\tx\nabc\tx\nabcd\tx\nabcdefg\tx\n")));
         let mut v = View::new(b, GEO);
-        v.cursor_right();
-        assert_eq!(v.cursor.get_index(), 1);
-        v.cursor_right();
-        assert_eq!(v.cursor.get_index(), 2);
-        v.cursor_right();
-        assert_eq!(v.cursor.get_index(), 3);
-        v.cursor_right();
-        assert_eq!(v.cursor.get_index(), 4);
-        v.cursor_right();
-        assert_eq!(v.cursor.get_index(), 5);
-        v.cursor_right();
-        assert_eq!(v.cursor.get_index(), 5);
+        v.set_show_line_numbers(false);
+        let mut canvas = test_canvas();
+        v.draw(&mut canvas, &Theme::default());
+        // the column of the x following the tab, on each line
+        let cols: Vec<_> = drawn_chars(&canvas)
+            .into_iter()
+            .filter(|(_, _, c)| *c == 'x')
+            .map(|(x, _, _)| (x / 10.0) as usize)
+            .collect();
+        assert_eq!(cols, vec![4, 4, 4, 8, 8]);
+        for (line, col) in cols.iter().enumerate() {
+            v.set_cursor_point(line, *col);
+            assert_eq!(v.char_at_cursor(), Some('x'));
+        }
     }
     #[test]
-    fn backspace() {
-        let b = Rc::new(RefCell::new(Buffer::from_str("hello")));
+    fn draw_wide_chars() {
+        let b = Rc::new(RefCell::new(Buffer::from_str("a中b\nxyz")));
         let mut v = View::new(b, GEO);
-        v.backspace();
-        assert_eq!(v.to_string(), "hello");
-        v.cursor.set_index(2);
-        v.backspace();
-        assert_eq!(v.to_string(), "hllo");
+        v.set_show_line_numbers(false);
+        v.set_cursor_point(0, 3);
+        assert_eq!(v.index(), 2);
+        v.cursor_down();
+        assert_eq!(v.col_idx(), 3);
+        v.cursor_up();
+        assert_eq!(v.col_idx(), 3);
+
+        let mut canvas = test_canvas();
+        v.draw(&mut canvas, &Theme::default());
+        // the position of each char drawn on the first line
+        assert_eq!(&drawn_chars(&canvas)[..3], &[(0.0, 10.0, 'a'), (10.0, 10.0, '中'), (30.0, 10.0, 'b')]);
     }
     #[test]
-    fn delete_at_cursor() {
-        let b = Rc::new(RefCell::new(Buffer::from_str("hello")));
+    fn draw_visible_lines_only() {
+        fn chars_drawn(v: &View<'_>) -> usize {
+            let mut canvas = test_canvas();
+            v.draw(&mut canvas, &Theme::default());
+            drawn_chars(&canvas).len()
+        }
+
+        let text: String = (0..1000).map(|_| "x\n").collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&text)));
         let mut v = View::new(b, GEO);
-        v.delete_at_cursor();
-        assert_eq!(v.to_string(), "ello");
-        v.cursor.set_index(3);
-        v.delete_at_cursor();
-        assert_eq!(v.to_string(), "ell");
-        v.delete_at_cursor();
-        assert_eq!(v.to_string(), "ell");
+        assert_eq!(chars_drawn(&v), v.page_length() + 1);
+        v.goto_line(995);
+        assert_eq!(chars_drawn(&v), 1000 - v.viewport.line_start);
+
+        let text: String = (0..10000).map(|_| 'x').collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&text)));
+        let mut v = View::new(b, GEO);
+        assert_eq!(chars_drawn(&v), v.viewport.width + 1);
+        v.end(false);
+        assert_eq!(chars_drawn(&v), 10000 - v.viewport.col_start);
+    }
+    #[test]
+    fn line_numbers_gutter() {
+        let text: String = (0..120).map(|i| format!("{}\n", i)).collect();
+        let b = Rc::new(RefCell::new(Buffer::from_str(&text)));
+        let mut v = View::new(b, GEO);
+        assert_eq!(v.gutter_width(), 0);
+        assert_eq!(v.viewport.width, 9);
+        v.set_show_line_numbers(true);
+        assert_eq!(v.gutter_width(), 4);
+        assert_eq!(v.viewport.width, 5);
+        v.click(45, 15, false);
+        assert_eq!(v.cursor.get_line(), 1);
+        assert_eq!(v.cursor.get_col(), 0);
+        v.click(5, 5, false);
+        assert_eq!(v.cursor.get_index(), 0);
     }
 }
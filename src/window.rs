@@ -1,19 +1,24 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::{thread, time};
 
-use syntect::highlighting;
+use clipboard2::Clipboard;
 
 use crate::buffer::Buffer;
 use crate::commands;
+use crate::commands::CLIPBOARD;
+use crate::fuzzy;
 use crate::keybinding;
-use crate::keybinding::KeyBinding;
+use crate::keybinding::{KeyBinding, KeyMap, KeyMatch, KeySequence};
 use crate::nanovg::Canvas;
-use crate::view::{Direction, View};
+use crate::recent::RecentFiles;
+use crate::session::{self, Session, ViewSession};
+use crate::view::{CursorStyle, Direction, View, ViewCmd, ViewDrawState};
 
-use crate::styling::STYLE;
+use crate::theme::Theme;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Geometry {
@@ -25,169 +30,1480 @@ pub struct Geometry {
     pub font_advance: f32,
 }
 
+/// How the visible views share the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitDirection {
+    /// views stacked on top of each other
+    Horizontal,
+    /// views side by side
+    Vertical,
+}
+
+/// Editing mode, when the modal editing is enabled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorMode {
+    /// typed chars are commands: h j k l move the cursor, i enter the insert mode...
+    Normal,
+    /// typed chars are inserted in the buffer
+    Insert,
+}
+
+impl EditorMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            EditorMode::Normal => "NORMAL",
+            EditorMode::Insert => "INSERT",
+        }
+    }
+}
+
+/// What is drawn in the window, compared between two frames to skip useless redraws
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrawState {
+    views: Vec<ViewDrawState>,
+    panes: Vec<usize>,
+    current_pane: usize,
+    split_direction: SplitDirection,
+    prompt: Option<String>,
+    message: Option<String>,
+    palette: Option<(String, usize)>,
+    mode: Option<EditorMode>,
+    recording_macro: bool,
+    theme: Theme,
+    cursor_style: CursorStyle,
+}
+
+/// Decide if the window is drawn at the end of a frame, once all its events are handled,
+/// so a burst of events causes a single redraw
+pub struct RedrawTracker {
+    draw_state: DrawState,
+    forced: bool,
+}
+
+impl RedrawTracker {
+    /// the first frame is always drawn
+    pub fn new(win: &EditorWindow<'_>) -> Self {
+        RedrawTracker {
+            draw_state: win.draw_state(),
+            forced: true,
+        }
+    }
+
+    /// draw the next frame even if nothing changed, after a resize or a refresh request
+    pub fn force(&mut self) {
+        self.forced = true;
+    }
+
+    /// return true if the window changed since it was last drawn, or if a redraw was forced
+    pub fn needs_redraw(&mut self, win: &EditorWindow<'_>) -> bool {
+        let state = win.draw_state();
+        let changed = state != self.draw_state;
+        if changed {
+            self.draw_state = state;
+        }
+        let redraw = changed || self.forced;
+        self.forced = false;
+        redraw
+    }
+}
+
+/// Blink the caret, it stays visible while the user is typing or moving around
+pub struct CaretBlink {
+    // None when the caret doesn't blink
+    interval: Option<time::Duration>,
+    last_activity: time::Instant,
+}
+
+impl CaretBlink {
+    pub fn new(interval: Option<time::Duration>) -> Self {
+        CaretBlink {
+            interval,
+            last_activity: time::Instant::now(),
+        }
+    }
+
+    /// note a key press or a click, the caret is solid for an interval then starts blinking again
+    pub fn reset(&mut self, now: time::Instant) {
+        self.last_activity = now;
+    }
+
+    /// return true if the caret is shown at the given instant
+    pub fn is_visible(&self, now: time::Instant) -> bool {
+        let interval = match self.interval {
+            Some(interval) if interval > time::Duration::from_millis(0) => interval,
+            _ => return true,
+        };
+        let elapsed = now.duration_since(self.last_activity);
+        let elapsed = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
+        let interval = interval.as_secs() * 1000 + u64::from(interval.subsec_millis());
+        (elapsed / interval) % 2 == 0
+    }
+}
+
+/// A step of a macro: a typed char or a command, by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroAction {
+    Char(char),
+    Command(&'static str),
+}
+
+/// Commands controlling the macros, never recorded
+const MACRO_COMMANDS: [&str; 2] = ["ToggleMacroRecording", "PlayMacro"];
+
+/// The command palette: the commands matching a fuzzy search on their name or description
+pub struct Palette {
+    input: String,
+    // indices in EditorWindow::commands, best match first
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+/// A one line input asked to the user, shown at the bottom of the window
+pub struct Prompt {
+    label: &'static str,
+    input: String,
+    accept: fn(char) -> bool,
+    on_validate: fn(&mut EditorWindow<'_>, &str),
+}
+
+/// A search updated as the query is typed, the cursor goes back where it was if the search is cancelled
+pub struct IncrementalSearch {
+    input: String,
+    // cursor index and first visible line of the view before the search
+    start_index: usize,
+    start_line: usize,
+}
+
 pub struct EditorWindow<'v> {
     views: Vec<View<'v>>,
     buffers: Vec<Rc<RefCell<Buffer>>>,
     geometry: Geometry,
     current_view: usize,
+    // views currently displayed, in layout order
+    panes: Vec<usize>,
+    current_pane: usize,
+    split_direction: SplitDirection,
+    prompt: Option<Prompt>,
+    // shown in place of the status bar until the next key press
+    message: Option<String>,
+    palette: Option<Palette>,
+    search: Option<IncrementalSearch>,
+    // name and description of the commands listed in the palette
+    commands: Vec<(&'static str, &'static str)>,
+    show_line_numbers: bool,
+    show_whitespace: bool,
+    show_indent_guides: bool,
+    highlight_current_line: bool,
+    theme: Theme,
+    // caret drawn in insert mode, normal mode always uses a block
+    cursor_style: CursorStyle,
+    // close the brackets and quotes when they are typed
+    auto_close: bool,
+    modal_editing: bool,
+    mode: EditorMode,
+    // first char of a two chars normal mode command, like dd or zz
+    pending_normal_char: Option<char>,
+    // actions recorded so far, None when not recording
+    macro_recording: Option<Vec<MacroAction>>,
+    recorded_macro: Vec<MacroAction>,
+    // actions of the macro being played, run by the event loop
+    macro_queue: Vec<MacroAction>,
+    recent_files: RecentFiles,
+    // font size requested by the zoom commands, applied by the event loop
+    font_size: f32,
+    // set once the user confirmed the quit, the event loop stops
+    quitting: bool,
 }
 
 pub trait WindowCmd {
     fn name(&self) -> &'static str;
     fn desc(&self) -> &'static str;
-    fn keybinding(&self) -> Vec<KeyBinding>;
+    fn keybinding(&self) -> Vec<KeySequence>;
+    /// return true if the command waits for an input typed in the palette, a prompt or a dialog.
+    /// It is never recorded in a macro: the input is not part of it
+    fn is_interactive(&self) -> bool {
+        false
+    }
     fn run(&mut self, _: &mut EditorWindow<'_>);
 }
 
-const FONT_SIZE: f32 = 16.0;
+/// Range of the font size, zooming stops at its bounds
+const MIN_FONT_SIZE: f32 = 6.0;
+const MAX_FONT_SIZE: f32 = 72.0;
+/// Height of the smallest pane a horizontal split can create, in lines
+const MIN_PANE_LINES: f32 = 2.0;
+/// Maximum number of commands listed in the palette
+const PALETTE_ROWS: usize = 10;
+/// Brackets and quotes closed automatically, as (open, close)
+const AUTO_CLOSE_PAIRS: [(char, char); 5] = [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+/// Delay to press the next key of a key sequence
+const KEY_SEQUENCE_TIMEOUT: time::Duration = time::Duration::from_millis(1000);
 
 impl<'v> EditorWindow<'v> {
-    pub fn new<P: AsRef<Path>>(geometry: Geometry, file: Option<P>) -> Self {
-        let mut w = EditorWindow::init(geometry);
-        w.add_new_view(file);
+    /// create a window showing the given file, a missing file is created on the first save.
+    /// The opened files are added to recent_files
+    pub fn new<P: AsRef<Path>>(geometry: Geometry, file: Option<P>, recent_files: RecentFiles) -> io::Result<Self> {
+        let mut w = EditorWindow::init(geometry, recent_files);
+        w.add_new_view(file)?;
+        Ok(w)
+    }
+    /// create a window showing the files of the saved session, or an empty buffer if none could be opened
+    pub fn from_session<P: AsRef<Path>>(geometry: Geometry, path: P, recent_files: RecentFiles) -> Self {
+        let mut w = EditorWindow::init(geometry, recent_files);
+        if let Err(e) = w.restore_session(path.as_ref()) {
+            println!("Unable to restore the session {}: {}", path.as_ref().display(), e);
+        }
+        if w.views.is_empty() {
+            w.add_buffer(Buffer::new());
+        }
         w
     }
-    fn init(geometry: Geometry) -> Self {
+
+    fn init(geometry: Geometry, recent_files: RecentFiles) -> Self {
         let views = Vec::new();
         let buffers = Vec::new();
+        let modal_editing = crate::SETTINGS.read().unwrap().get("modalEditing").unwrap();
         EditorWindow {
             views,
             buffers,
             geometry,
             current_view: 0,
+            panes: Vec::new(),
+            current_pane: 0,
+            split_direction: SplitDirection::Vertical,
+            prompt: None,
+            message: None,
+            palette: None,
+            search: None,
+            commands: Vec::new(),
+            show_line_numbers: crate::SETTINGS.read().unwrap().get("showLineNumbers").unwrap(),
+            show_whitespace: crate::SETTINGS.read().unwrap().get("showWhitespace").unwrap(),
+            show_indent_guides: crate::SETTINGS.read().unwrap().get("showIndentGuides").unwrap(),
+            highlight_current_line: crate::SETTINGS.read().unwrap().get("highlightCurrentLine").unwrap(),
+            theme: match crate::SETTINGS.read().unwrap().get::<String>("theme").unwrap().as_str() {
+                "light" => Theme::solarized_light(),
+                _ => Theme::solarized_dark(),
+            },
+            cursor_style: match crate::SETTINGS.read().unwrap().get::<String>("cursorStyle").unwrap().as_str() {
+                "block" => CursorStyle::Block,
+                "underline" => CursorStyle::Underline,
+                _ => CursorStyle::Bar,
+            },
+            auto_close: crate::SETTINGS.read().unwrap().get("autoCloseBrackets").unwrap(),
+            modal_editing,
+            mode: if modal_editing { EditorMode::Normal } else { EditorMode::Insert },
+            pending_normal_char: None,
+            macro_recording: None,
+            recorded_macro: Vec::new(),
+            macro_queue: Vec::new(),
+            recent_files,
+            font_size: crate::SETTINGS.read().unwrap().get("fontSize").unwrap(),
+            quitting: false,
         }
     }
 
     pub fn get_current_view(&self) -> &View<'_> {
         &self.views[self.current_view]
     }
-    pub fn get_current_view_mut(&mut self) -> &'v mut View<'_> {
+    pub fn get_current_view_mut(&mut self) -> &mut View<'v> {
         &mut self.views[self.current_view]
     }
 
-    pub fn add_new_view<P: AsRef<Path>>(&mut self, file: Option<P>) {
+    /// open the file in a new view, or an empty buffer with this filename if it doesn't exist
+    pub fn add_new_view<P: AsRef<Path>>(&mut self, file: Option<P>) -> io::Result<()> {
         let b = match file {
-            None => Rc::new(RefCell::new(Buffer::new())),
-            Some(file) => Rc::new(RefCell::new(Buffer::from_file(file.as_ref()).expect("File not found"))),
+            None => Buffer::new(),
+            Some(file) => {
+                let b = Buffer::from_file_or_new(file.as_ref())?;
+                self.recent_files.add(file);
+                b
+            }
         };
+        self.add_buffer(b);
+        Ok(())
+    }
+
+    /// open the file in a new buffer and display it in the focused pane
+    pub fn open_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let b = Buffer::from_file(path.as_ref())?;
+        self.recent_files.add(path);
+        self.add_buffer(b);
+        Ok(())
+    }
+
+    /// save the views showing a file, with their cursor and scroll position
+    pub fn save_session<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut session = Session::default();
+        for (i, v) in self.views.iter().enumerate() {
+            let file = match v.get_buffer().borrow().get_filename() {
+                Some(file) => file.to_owned(),
+                None => continue,
+            };
+            if i == self.current_view {
+                session.current_view = session.views.len();
+            }
+            session.views.push(ViewSession {
+                file,
+                cursor: v.index(),
+                first_visible_line: v.first_visible_line(),
+            });
+        }
+        session.save(path)
+    }
+
+    /// open the files of a saved session, restoring their cursor and scroll position.
+    /// The files that no longer exist are skipped
+    pub fn restore_session<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let session = Session::load(path)?;
+        let mut current_view = None;
+        for (i, saved) in session.views.iter().enumerate() {
+            if !saved.file.exists() {
+                continue;
+            }
+            if let Err(e) = self.open_file(&saved.file) {
+                println!("Unable to open {}: {}", saved.file.display(), e);
+                continue;
+            }
+            let v = self.get_current_view_mut();
+            v.set_index(saved.cursor);
+            v.set_first_visible_line(saved.first_visible_line);
+            if i == session.current_view {
+                current_view = Some(self.current_view);
+            }
+        }
+        if let Some(viewid) = current_view {
+            self.show_view(viewid);
+        }
+        Ok(())
+    }
+
+    /// the files opened recently, most recent first
+    pub fn recent_files(&self) -> &[PathBuf] {
+        self.recent_files.files()
+    }
+
+    fn add_buffer(&mut self, b: Buffer) {
+        let b = Rc::new(RefCell::new(b));
         self.buffers.push(b.clone());
-        let mut geometry = self.geometry;
-        //geometry.h -= 15; // footer TODO calculate it
-        let mut v = View::new(b.clone(), geometry);
-        v.detect_syntax();
+        let v = self.create_view(b);
         println!("{:?}", v.detect_indentation());
 
         let viewid = self.views.len();
         self.views.push(v);
+        self.show_view(viewid);
+    }
+
+    fn create_view(&self, b: Rc<RefCell<Buffer>>) -> View<'v> {
+        let mut v = View::new(b, self.text_area());
+        v.set_show_line_numbers(self.show_line_numbers);
+        v.set_show_whitespace(self.show_whitespace);
+        v.set_show_indent_guides(self.show_indent_guides);
+        v.set_highlight_current_line(self.highlight_current_line);
+        v.detect_syntax();
+        v
+    }
+
+    /// display the next (or previous) buffer in the focused pane
+    pub fn cycle_buffer(&mut self, forward: bool) {
+        let n = self.buffers.len();
+        let current = self.get_current_view().get_buffer();
+        let i = self.buffers.iter().position(|b| Rc::ptr_eq(b, &current)).unwrap_or(0);
+        let i = if forward { (i + 1) % n } else { (i + n - 1) % n };
+        let b = self.buffers[i].clone();
+
+        // reuse a view of this buffer not already displayed in an other pane
+        let current_pane = self.current_pane;
+        let viewid = (0..self.views.len()).find(|v| {
+            Rc::ptr_eq(&self.views[*v].get_buffer(), &b)
+                && !self.panes.iter().enumerate().any(|(p, id)| p != current_pane && id == v)
+        });
+        let viewid = match viewid {
+            Some(viewid) => viewid,
+            None => {
+                let v = self.create_view(b);
+                self.views.push(v);
+                self.views.len() - 1
+            }
+        };
+        self.show_view(viewid);
+    }
+
+    /// display the given view in the focused pane
+    fn show_view(&mut self, viewid: usize) {
+        if self.panes.is_empty() {
+            self.panes.push(viewid);
+            self.current_pane = 0;
+        } else {
+            self.panes[self.current_pane] = viewid;
+        }
         self.current_view = viewid;
+        self.layout();
     }
 
-    fn resize(&mut self, width: f32, height: f32) {
-        self.geometry.w = width;
-        self.geometry.h = height;
+    /// split the focused pane in two, the new pane shows the same buffer and get the focus.
+    /// Nothing is done if the panes would become shorter than MIN_PANE_LINES
+    pub fn split(&mut self, direction: SplitDirection) {
+        if direction == SplitDirection::Horizontal {
+            let n = (self.panes.len() + 1) as f32;
+            if self.text_area().h / n < MIN_PANE_LINES * self.geometry.font_height {
+                return;
+            }
+        }
+        let b = self.get_current_view().get_buffer();
+        let line = self.get_current_view().line_idx();
+        let mut v = self.create_view(b);
+        v.goto_line(line);
+
+        let viewid = self.views.len();
+        self.views.push(v);
+        self.split_direction = direction;
+        self.current_pane += 1;
+        self.panes.insert(self.current_pane, viewid);
+        self.current_view = viewid;
+        self.layout();
+    }
+
+    /// give the focus to the next pane
+    pub fn focus_next_split(&mut self) {
+        self.current_pane = (self.current_pane + 1) % self.panes.len();
+        self.current_view = self.panes[self.current_pane];
+    }
+
+    /// close the focused pane. The view is dropped if its buffer is still shown by an other view
+    pub fn close_split(&mut self) {
+        if self.panes.len() < 2 {
+            return;
+        }
+        let viewid = self.panes.remove(self.current_pane);
+        let buffer = self.views[viewid].get_buffer();
+        let shared = self
+            .views
+            .iter()
+            .enumerate()
+            .any(|(i, v)| i != viewid && Rc::ptr_eq(&v.get_buffer(), &buffer));
+        if shared {
+            self.views.remove(viewid);
+            for p in &mut self.panes {
+                if *p > viewid {
+                    *p -= 1;
+                }
+            }
+        }
+        if self.current_pane >= self.panes.len() {
+            self.current_pane = self.panes.len() - 1;
+        }
+        self.current_view = self.panes[self.current_pane];
+        self.layout();
+    }
+
+    /// return the part of the window where the views are drawn, between the tab strip and the status bar
+    fn text_area(&self) -> Geometry {
         let mut geometry = self.geometry;
-        //geometry.h -= 15; // footer TODO calculate it
+        geometry.y += self.tab_strip_height();
+        geometry.h -= self.tab_strip_height() + self.status_bar_height();
+        geometry
+    }
+
+    fn status_bar_height(&self) -> f32 {
+        self.geometry.font_height
+    }
+
+    /// enable or disable the modal editing. The normal mode is entered when enabled
+    pub fn set_modal_editing(&mut self, modal: bool) {
+        self.modal_editing = modal;
+        self.mode = if modal { EditorMode::Normal } else { EditorMode::Insert };
+        self.pending_normal_char = None;
+    }
+
+    /// return true if the modal editing is enabled
+    pub fn is_modal_editing(&self) -> bool {
+        self.modal_editing
+    }
+
+    /// return the current editing mode, always Insert if the modal editing is disabled
+    pub fn mode(&self) -> EditorMode {
+        self.mode
+    }
+
+    /// go back to the normal mode from the insert mode. Return false if there was nothing to do
+    pub fn enter_normal_mode(&mut self) -> bool {
+        if self.modal_editing && self.mode == EditorMode::Insert {
+            self.mode = EditorMode::Normal;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// cancel the topmost transient state: the palette, the prompt, the incremental search, the insert mode
+    /// of the modal editing, the selection, then the search highlights.
+    /// Return false if there was nothing to cancel
+    pub fn cancel(&mut self) -> bool {
+        if self.has_palette() {
+            self.cancel_palette();
+        } else if self.has_prompt() {
+            self.cancel_prompt();
+        } else if self.has_incremental_search() {
+            self.cancel_incremental_search();
+        } else if self.enter_normal_mode() {
+        } else if self.get_current_view().has_selection() || self.get_current_view().has_block_selection() {
+            self.get_current_view_mut().clear_selection();
+        } else if self.get_current_view().has_search() {
+            self.get_current_view_mut().clear_search();
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// handle a char typed in the current view: inserted in insert mode, run as a command in normal mode
+    pub fn type_char(&mut self, ch: char) {
+        if let Some(actions) = &mut self.macro_recording {
+            actions.push(MacroAction::Char(ch));
+        }
+        if self.mode == EditorMode::Insert {
+            self.insert_typed_char(ch);
+            return;
+        }
+        let pending = self.pending_normal_char.take();
+        let v = &mut self.views[self.current_view];
+        match (pending, ch) {
+            (None, 'h') => v.move_cursor(Direction::Left, false),
+            (None, 'j') => v.move_cursor(Direction::Down, false),
+            (None, 'k') => v.move_cursor(Direction::Up, false),
+            (None, 'l') => v.move_cursor(Direction::Right, false),
+            (None, 'i') => self.mode = EditorMode::Insert,
+            (None, 'x') => v.delete_at_cursor(),
+            (None, '{') => v.move_paragraph_up(),
+            (None, '}') => v.move_paragraph_down(),
+            (None, 'd') => self.pending_normal_char = Some('d'),
+            (Some('d'), 'd') => v.delete_line(),
+            (None, 'z') => self.pending_normal_char = Some('z'),
+            (Some('z'), 'z') => v.center_cursor(),
+            (Some('z'), 't') => v.cursor_to_top(),
+            (Some('z'), 'b') => v.cursor_to_bottom(),
+            _ => (),
+        }
+    }
+
+    /// insert a typed char. When auto close is on, an opening bracket or quote is inserted with its closing one,
+    /// or wraps the selection, and a closing one is typed over if it is already under the cursor
+    fn insert_typed_char(&mut self, ch: char) {
+        let auto_close = self.auto_close;
+        let v = &mut self.views[self.current_view];
+        let pair = AUTO_CLOSE_PAIRS.iter().find(|(open, close)| *open == ch || *close == ch);
+        // the typed char is inserted on each line of a block selection, without its pair
+        if let (true, Some(&(open, close))) = (auto_close && !v.has_block_selection(), pair) {
+            if ch == close && !v.has_selection() && v.char_at_cursor() == Some(close) {
+                v.move_cursor(Direction::Right, false);
+                return;
+            }
+            if ch == open && v.has_selection() {
+                v.wrap_selection(open, close);
+                return;
+            }
+            // no pair before a word, and no quote pair just after a word, like in "don't"
+            let before_word = v.char_at_cursor().map_or(false, char::is_alphanumeric);
+            let after_word = open == close && v.char_before_cursor().map_or(false, char::is_alphanumeric);
+            if ch == open && !before_word && !after_word {
+                v.insert_pair(open, close);
+                return;
+            }
+        }
+        v.insert_char(ch);
+    }
+
+    /// set if the brackets and quotes are closed automatically
+    pub fn set_auto_close(&mut self, auto_close: bool) {
+        self.auto_close = auto_close;
+    }
+
+    /// start recording the typed chars and the commands run. Return false if a macro is already recorded
+    pub fn start_macro_recording(&mut self) -> bool {
+        if self.macro_recording.is_some() {
+            return false;
+        }
+        self.macro_recording = Some(Vec::new());
+        true
+    }
+
+    /// stop recording, the recorded actions replace the previous macro
+    pub fn stop_macro_recording(&mut self) {
+        if let Some(actions) = self.macro_recording.take() {
+            self.recorded_macro = actions;
+        }
+    }
+
+    /// start or stop recording a macro
+    pub fn toggle_macro_recording(&mut self) {
+        if !self.start_macro_recording() {
+            self.stop_macro_recording();
+        }
+    }
+
+    /// return true if a macro is being recorded
+    pub fn is_recording_macro(&self) -> bool {
+        self.macro_recording.is_some()
+    }
+
+    /// record a command run, if a macro is being recorded
+    pub fn record_command(&mut self, name: &'static str) {
+        if let Some(actions) = &mut self.macro_recording {
+            if !MACRO_COMMANDS.contains(&name) {
+                actions.push(MacroAction::Command(name));
+            }
+        }
+    }
+
+    /// queue the actions of the last recorded macro, to be run by the event loop.
+    /// Nothing is played while recording
+    pub fn play_macro(&mut self) {
+        if self.macro_recording.is_none() {
+            self.macro_queue.extend(self.recorded_macro.iter().cloned());
+        }
+    }
+
+    /// return the queued macro actions, in order, and empty the queue
+    pub fn take_macro_queue(&mut self) -> Vec<MacroAction> {
+        std::mem::replace(&mut self.macro_queue, Vec::new())
+    }
+
+    /// return the text of the status bar: the file name, a star if modified, and the cursor position
+    pub fn status(&self) -> String {
+        let v = self.get_current_view();
+        let b = v.get_buffer();
+        let b = b.borrow();
+        let filename = b
+            .get_filename()
+            .and_then(|f| f.file_name())
+            .and_then(|f| f.to_str())
+            .unwrap_or("[No Name]");
+        let dirty = if b.is_dirty() { " *" } else { "" };
+        let changed = if b.is_changed_on_disk() { " [changed on disk]" } else { "" };
+        let mode = if self.modal_editing {
+            format!("{}    ", self.mode.name())
+        } else {
+            String::new()
+        };
+        let recording = if self.is_recording_macro() { "REC    " } else { "" };
+        format!(
+            "{}{}{}{}{}    Ln {}, Col {}",
+            recording,
+            mode,
+            filename,
+            dirty,
+            changed,
+            v.line_idx() + 1,
+            v.col_idx() + 1
+        )
+    }
+
+    /// show a message in place of the status bar, until the next key press
+    pub fn show_message<S: Into<String>>(&mut self, message: S) {
+        self.message = Some(message.into());
+    }
+
+    /// hide the message, the status bar is shown again
+    pub fn clear_message(&mut self) {
+        self.message = None;
+    }
+
+    /// return the message shown in place of the status bar
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().map(String::as_str)
+    }
+
+    /// show the number of chars, lines and words of the current buffer
+    pub fn show_stats(&mut self) {
+        let stats = self.get_current_view().get_buffer().borrow().stats();
+        self.show_message(format!(
+            "{} chars, {} lines, {} words",
+            stats.chars, stats.lines, stats.words
+        ));
+    }
+
+    /// flag the buffers whose file was modified by another program
+    pub fn check_external_changes(&mut self) {
+        for b in &self.buffers {
+            let changed = b.borrow().external_change_detected().unwrap_or(false);
+            if changed {
+                b.borrow_mut().mark_changed_on_disk();
+            }
+        }
+    }
+
+    fn tab_strip_height(&self) -> f32 {
+        self.geometry.font_height
+    }
+
+    /// return the geometry of the given pane
+    fn pane_geometry(&self, pane: usize) -> Geometry {
+        let area = self.text_area();
+        let mut geometry = area;
+        let n = self.panes.len() as f32;
+        match self.split_direction {
+            SplitDirection::Horizontal => {
+                geometry.h = (area.h / n).floor();
+                geometry.y = area.y + geometry.h * pane as f32;
+            }
+            SplitDirection::Vertical => {
+                geometry.w = (area.w / n).floor();
+                geometry.x = area.x + geometry.w * pane as f32;
+            }
+        }
+        geometry
+    }
+
+    /// return the pane under the given window position
+    fn pane_at(&self, x: f32, y: f32) -> Option<usize> {
+        (0..self.panes.len()).find(|p| {
+            let g = self.pane_geometry(*p);
+            x >= g.x && x < g.x + g.w && y >= g.y && y < g.y + g.h
+        })
+    }
+
+    /// update the geometry of every view
+    fn layout(&mut self) {
         for i in 0..self.views.len() {
+            let geometry = match self.panes.iter().position(|p| *p == i) {
+                Some(pane) => self.pane_geometry(pane),
+                None => self.text_area(),
+            };
             self.views[i].relayout(geometry);
         }
     }
+
+    /// focus the pane under the given position and move its cursor there
+    pub fn click(&mut self, x: f32, y: f32, expand_selection: bool) {
+        if let Some(pane) = self.pane_at(x, y) {
+            self.current_pane = pane;
+            self.current_view = self.panes[pane];
+        }
+        let g = self.pane_geometry(self.current_pane);
+        self.get_current_view_mut()
+            .click((x - g.x) as _, (y - g.y) as _, expand_selection);
+    }
+
+    /// expand the selection of the focused pane to the given position
+    pub fn drag(&mut self, x: f32, y: f32) {
+        let g = self.pane_geometry(self.current_pane);
+        self.get_current_view_mut().drag((x - g.x) as _, (y - g.y) as _);
+    }
+
+    /// expand the block selection of the focused pane to the given position
+    pub fn drag_block(&mut self, x: f32, y: f32) {
+        let g = self.pane_geometry(self.current_pane);
+        self.get_current_view_mut().drag_block((x - g.x) as _, (y - g.y) as _);
+    }
+
+    /// copy the selection of the current view to the clipboard
+    pub fn copy(&mut self) {
+        if let Some(s) = self.get_current_view().get_selection().filter(|s| !s.is_empty()) {
+            if let Err(e) = CLIPBOARD.lock().unwrap().set_string_contents(s) {
+                println!("Unable to copy to clipboard: {:?}", e);
+            }
+        }
+    }
+
+    /// copy the selection of the current view to the clipboard, then delete it
+    pub fn cut(&mut self) {
+        if let Some(s) = self.get_current_view().get_selection().filter(|s| !s.is_empty()) {
+            match CLIPBOARD.lock().unwrap().set_string_contents(s) {
+                Ok(()) => self.get_current_view_mut().delete_at_cursor(),
+                Err(e) => println!("Unable to copy to clipboard: {:?}", e),
+            }
+        }
+    }
+
+    /// insert the content of the clipboard at the cursor position of the current view
+    pub fn paste(&mut self) {
+        let s = CLIPBOARD.lock().unwrap().get_string_contents();
+        match s {
+            Ok(s) => {
+                if !s.is_empty() {
+                    self.get_current_view_mut().insert(&s)
+                }
+            }
+            Err(e) => println!("Unable to paste from clipboard: {:?}", e),
+        }
+    }
+
+    /// return the window title, with a trailing star if the current buffer is modified
+    pub fn title(&self) -> String {
+        let b = self.get_current_view().get_buffer();
+        let b = b.borrow();
+        let filename = tab_name(&b);
+        if b.is_dirty() {
+            format!("None - {} *", filename)
+        } else {
+            format!("None - {}", filename)
+        }
+    }
+
+    /// change the colors used to draw the window
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// show or hide the line numbers of every view
+    pub fn toggle_line_numbers(&mut self) {
+        self.show_line_numbers = !self.show_line_numbers;
+        for v in &mut self.views {
+            v.set_show_line_numbers(self.show_line_numbers);
+        }
+    }
+
+    /// return true if quitting would lose unsaved changes
+    pub fn should_block_quit(&self) -> bool {
+        self.buffers.iter().any(|b| b.borrow().is_dirty())
+    }
+
+    /// quit the editor. If a buffer is modified, the user is asked to save the buffers, to quit without saving,
+    /// or to cancel the quit
+    pub fn request_quit(&mut self) {
+        if !self.should_block_quit() {
+            self.quitting = true;
+            return;
+        }
+        self.open_prompt(
+            "Unsaved changes. Save (s), quit without saving (q) or cancel (Escape)",
+            |c| "sSqQ".contains(c),
+            |w, input| match input.chars().last() {
+                Some('s') | Some('S') => {
+                    w.save_all();
+                    w.quitting = !w.should_block_quit();
+                }
+                Some('q') | Some('Q') => w.quitting = true,
+                _ => (),
+            },
+        );
+    }
+
+    /// return true once the editor should stop
+    pub fn is_quitting(&self) -> bool {
+        self.quitting
+    }
+
+    /// save every modified buffer, a message lists the ones that could not be saved.
+    /// The untitled buffers are not saved, they need a file name from the save dialog
+    pub fn save_all(&mut self) {
+        let mut failed = Vec::new();
+        for v in &mut self.views {
+            let b = v.get_buffer();
+            if !b.borrow().is_dirty() {
+                continue;
+            }
+            if b.borrow().get_filename().is_none() {
+                failed.push(tab_name(&b.borrow()).to_owned());
+                continue;
+            }
+            if let Err(e) = v.save() {
+                failed.push(format!("{}: {}", tab_name(&b.borrow()), e));
+            }
+        }
+        if !failed.is_empty() {
+            self.show_message(format!("Unable to save {}", failed.join(", ")));
+        }
+    }
+
+    /// change how the caret is drawn in insert mode
+    pub fn set_cursor_style(&mut self, style: CursorStyle) {
+        self.cursor_style = style;
+    }
+
+    /// return how the caret is drawn in the current mode
+    pub fn cursor_style(&self) -> CursorStyle {
+        if self.modal_editing && self.mode == EditorMode::Normal {
+            CursorStyle::Block
+        } else {
+            self.cursor_style
+        }
+    }
+
+    /// show or hide the whitespace markers of every view
+    pub fn toggle_whitespace(&mut self) {
+        self.show_whitespace = !self.show_whitespace;
+        for v in &mut self.views {
+            v.set_show_whitespace(self.show_whitespace);
+        }
+    }
+
+    /// show or hide the indentation guides of every view
+    pub fn toggle_indent_guides(&mut self) {
+        self.show_indent_guides = !self.show_indent_guides;
+        for v in &mut self.views {
+            v.set_show_indent_guides(self.show_indent_guides);
+        }
+    }
+
+    /// highlight the cursor line in every view, or stop highlighting it
+    pub fn toggle_current_line_highlight(&mut self) {
+        self.highlight_current_line = !self.highlight_current_line;
+        for v in &mut self.views {
+            v.set_highlight_current_line(self.highlight_current_line);
+        }
+    }
+
+    /// ask the user for a line of text. on_validate is called with the input when the prompt is validated
+    pub fn open_prompt(
+        &mut self,
+        label: &'static str,
+        accept: fn(char) -> bool,
+        on_validate: fn(&mut EditorWindow<'_>, &str),
+    ) {
+        self.prompt = Some(Prompt {
+            label,
+            input: String::new(),
+            accept,
+            on_validate,
+        });
+    }
+
+    /// return true if a prompt is waiting for user input
+    pub fn has_prompt(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    /// add a char to the prompt input, if accepted by the prompt
+    pub fn prompt_insert_char(&mut self, ch: char) {
+        if let Some(prompt) = &mut self.prompt {
+            if (prompt.accept)(ch) {
+                prompt.input.push(ch);
+            }
+        }
+    }
+
+    /// remove the last char of the prompt input
+    pub fn prompt_backspace(&mut self) {
+        if let Some(prompt) = &mut self.prompt {
+            prompt.input.pop();
+        }
+    }
+
+    /// close the prompt and run its action
+    pub fn validate_prompt(&mut self) {
+        if let Some(prompt) = self.prompt.take() {
+            (prompt.on_validate)(self, &prompt.input);
+        }
+    }
+
+    /// close the prompt without running its action
+    pub fn cancel_prompt(&mut self) {
+        self.prompt = None;
+    }
+
+    /// start an incremental search in the current view, the start position is the only jump recorded
+    pub fn open_incremental_search(&mut self) {
+        let v = &mut self.views[self.current_view];
+        v.push_jump();
+        self.search = Some(IncrementalSearch {
+            input: String::new(),
+            start_index: v.index(),
+            start_line: v.first_visible_line(),
+        });
+    }
+
+    /// return true if an incremental search is waiting for user input
+    pub fn has_incremental_search(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// add a char to the search query, and go to the first match after the cursor
+    pub fn search_insert_char(&mut self, ch: char) {
+        if let Some(search) = &mut self.search {
+            search.input.push(ch);
+        }
+        self.update_incremental_search();
+    }
+
+    /// remove the last char of the search query
+    pub fn search_backspace(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.input.pop();
+        }
+        self.update_incremental_search();
+    }
+
+    /// end the search, leaving the cursor on the current match
+    pub fn validate_incremental_search(&mut self) {
+        self.search = None;
+    }
+
+    /// end the search, the cursor and the scrolling go back to where they were before the search
+    pub fn cancel_incremental_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            let v = &mut self.views[self.current_view];
+            v.clear_search();
+            v.set_index(search.start_index);
+            v.set_first_visible_line(search.start_line);
+        }
+    }
+
+    fn update_incremental_search(&mut self) {
+        if let Some(search) = &self.search {
+            let v = &mut self.views[self.current_view];
+            // every query is searched from the cursor position before the search
+            v.set_index(search.start_index);
+            v.set_first_visible_line(search.start_line);
+            v.search_without_jump(&search.input);
+        }
+    }
+
+    /// set the commands listed in the palette, with their description
+    pub fn set_commands(&mut self, commands: Vec<(&'static str, &'static str)>) {
+        self.commands = commands;
+    }
+
+    /// open the command palette, listing every command
+    pub fn open_palette(&mut self) {
+        self.palette = Some(Palette {
+            input: String::new(),
+            matches: Vec::new(),
+            selected: 0,
+        });
+        self.update_palette_matches();
+    }
+
+    /// return true if the command palette is open
+    pub fn has_palette(&self) -> bool {
+        self.palette.is_some()
+    }
+
+    /// add a char to the palette search
+    pub fn palette_insert_char(&mut self, ch: char) {
+        if let Some(palette) = &mut self.palette {
+            palette.input.push(ch);
+        }
+        self.update_palette_matches();
+    }
+
+    /// remove the last char of the palette search
+    pub fn palette_backspace(&mut self) {
+        if let Some(palette) = &mut self.palette {
+            palette.input.pop();
+        }
+        self.update_palette_matches();
+    }
+
+    /// move the palette selection up (negative delta) or down, wrapping around
+    pub fn palette_select(&mut self, delta: i32) {
+        if let Some(palette) = &mut self.palette {
+            let n = std::cmp::min(palette.matches.len(), PALETTE_ROWS) as i32;
+            if n > 0 {
+                palette.selected = (palette.selected as i32 + delta).rem_euclid(n) as usize;
+            }
+        }
+    }
+
+    /// close the palette and return the name of the selected command, if any
+    pub fn validate_palette(&mut self) -> Option<&'static str> {
+        let palette = self.palette.take()?;
+        palette.matches.get(palette.selected).map(|i| self.commands[*i].0)
+    }
+
+    /// close the palette without running a command
+    pub fn cancel_palette(&mut self) {
+        self.palette = None;
+    }
+
+    fn update_palette_matches(&mut self) {
+        let commands = &self.commands;
+        if let Some(palette) = &mut self.palette {
+            let mut matches: Vec<(i32, usize)> = commands
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (name, desc))| {
+                    let score = std::cmp::max(fuzzy::score(&palette.input, name), fuzzy::score(&palette.input, desc));
+                    score.map(|s| (s, i))
+                })
+                .collect();
+            // best score first, the registration order is kept between equal scores
+            matches.sort_by_key(|(s, i)| (-s, *i));
+            palette.matches = matches.into_iter().map(|(_, i)| i).collect();
+            palette.selected = 0;
+        }
+    }
+
+    /// return what would be drawn in the window
+    pub fn draw_state(&self) -> DrawState {
+        DrawState {
+            views: self.views.iter().map(View::draw_state).collect(),
+            panes: self.panes.clone(),
+            current_pane: self.current_pane,
+            split_direction: self.split_direction,
+            prompt: match (&self.prompt, &self.search) {
+                (Some(p), _) => Some(format!("{}: {}", p.label, p.input)),
+                (None, Some(s)) => Some(format!("Search: {}", s.input)),
+                (None, None) => None,
+            },
+            message: self.message.clone(),
+            palette: self.palette.as_ref().map(|p| (p.input.clone(), p.selected)),
+            mode: if self.modal_editing { Some(self.mode) } else { None },
+            recording_macro: self.is_recording_macro(),
+            theme: self.theme,
+            cursor_style: self.cursor_style(),
+        }
+    }
+
+    /// return the font size the text should be drawn with
+    pub fn font_size(&self) -> f32 {
+        self.font_size
+    }
+
+    /// increase (or decrease) the font size, clamped to a readable range
+    pub fn zoom(&mut self, delta: f32) {
+        self.font_size = (self.font_size + delta).max(MIN_FONT_SIZE).min(MAX_FONT_SIZE);
+    }
+
+    /// go back to the font size of the settings
+    pub fn reset_zoom(&mut self) {
+        self.font_size = crate::SETTINGS.read().unwrap().get("fontSize").unwrap();
+    }
+
+    /// update the geometry after the font changed, the views page length follow the new line height
+    pub fn set_font_metrics(&mut self, font_height: f32, font_advance: f32) {
+        self.geometry.font_height = font_height;
+        self.geometry.font_advance = font_advance;
+        self.layout();
+        for v in &mut self.views {
+            v.focus_on_cursor();
+        }
+    }
+
+    /// show or hide the caret of every view
+    pub fn set_caret_visible(&mut self, visible: bool) {
+        for v in &mut self.views {
+            v.set_caret_visible(visible);
+        }
+    }
+
+    fn resize(&mut self, width: f32, height: f32) {
+        self.geometry.w = width;
+        self.geometry.h = height;
+        self.layout();
+    }
     fn draw(&mut self, canvas: &mut Canvas) {
-        // screen.set_font("gui");
-
-        // let footer_height = screen.get_font_metrics("gui").line_spacing;
-        // let fg = STYLE.theme.settings.foreground.unwrap_or(highlighting::Color::BLACK);
-        // let bg = STYLE.theme.settings.background.unwrap_or(highlighting::Color::WHITE);
-        // screen.set_color(Color::RGB(fg.r, fg.g, fg.b));
-        // screen.move_to(0, self.geometry.h as i32 - footer_height);
-        // screen.draw_rect(self.geometry.w as _, footer_height as _);
-        // screen.set_color(Color::RGB(bg.r, bg.g, bg.b));
-
-        // let (line, col) = self.get_current_view().cursor_as_point();
-        // screen.draw_str(&format!(
-        //     "({},{})    {}    {}",
-        //     line,
-        //     col,
-        //     self.get_current_view().get_syntax(),
-        //     self.get_current_view().get_encoding()
-        // ));
-
-        self.get_current_view().draw(canvas);
+        let cursor_style = self.cursor_style();
+        for v in &mut self.views {
+            v.set_cursor_style(cursor_style);
+        }
+        for (i, viewid) in self.panes.iter().enumerate() {
+            self.views[*viewid].draw(canvas, &self.theme);
+            if i > 0 {
+                let g = self.pane_geometry(i);
+                let color = self.theme.gutter_foreground;
+                canvas.set_color(nanovg::Color::from_rgb(color.r, color.g, color.b));
+                canvas.move_to(g.x, g.y);
+                match self.split_direction {
+                    SplitDirection::Horizontal => canvas.draw_rect(g.w, 1.0),
+                    SplitDirection::Vertical => canvas.draw_rect(1.0, g.h),
+                }
+            }
+        }
+
+        self.draw_tab_strip(canvas);
+        self.draw_palette(canvas);
+
+        // the prompt or the message are shown in place of the status bar
+        let footer = match (&self.prompt, &self.search, &self.message) {
+            (Some(prompt), _, _) => format!("{}: {}", prompt.label, prompt.input),
+            (None, Some(search), _) => format!("Search: {}", search.input),
+            (None, None, Some(message)) => message.clone(),
+            (None, None, None) => self.status(),
+        };
+        let fg = self.theme.foreground;
+        let bg = self.theme.background;
+        let footer_height = self.status_bar_height();
+        canvas.set_color(nanovg::Color::from_rgb(fg.r, fg.g, fg.b));
+        canvas.move_to(0.0, self.geometry.h - footer_height);
+        canvas.draw_rect(self.geometry.w, footer_height);
+        canvas.set_color(nanovg::Color::from_rgb(bg.r, bg.g, bg.b));
+        canvas.move_to(0.0, self.geometry.h - canvas.font_metrics.descender - footer_height * 0.5);
+        canvas.draw_str(&footer);
+    }
+}
+
+impl<'v> EditorWindow<'v> {
+    /// draw the name of every buffer at the top of the window, the current one highlighted
+    fn draw_tab_strip(&self, canvas: &mut Canvas) {
+        let fg = self.theme.foreground;
+        let bg = self.theme.background;
+        let height = self.tab_strip_height();
+        let baseline = height - canvas.font_metrics.descender - height * 0.5;
+        let current = self.get_current_view().get_buffer();
+
+        canvas.set_color(nanovg::Color::from_rgb(bg.r, bg.g, bg.b));
+        canvas.move_to(0.0, 0.0);
+        canvas.draw_rect(self.geometry.w, height);
+
+        let mut x = 0.0;
+        for b in &self.buffers {
+            let name = format!(" {} ", tab_name(&b.borrow()));
+            let w = name.chars().count() as f32 * self.geometry.font_advance;
+            if Rc::ptr_eq(b, &current) {
+                canvas.set_color(nanovg::Color::from_rgb(fg.r, fg.g, fg.b));
+                canvas.move_to(x, 0.0);
+                canvas.draw_rect(w, height);
+                canvas.set_color(nanovg::Color::from_rgb(bg.r, bg.g, bg.b));
+            } else {
+                canvas.set_color(nanovg::Color::from_rgb(fg.r, fg.g, fg.b));
+            }
+            canvas.move_to(x, baseline);
+            canvas.draw_str(&name);
+            x += w;
+        }
+    }
+}
+
+impl<'v> EditorWindow<'v> {
+    /// draw the palette search and the best matching commands over the top of the text area
+    fn draw_palette(&self, canvas: &mut Canvas) {
+        let palette = match &self.palette {
+            Some(palette) => palette,
+            None => return,
+        };
+        let area = self.text_area();
+        let fg = self.theme.foreground;
+        let bg = self.theme.background;
+        let sel = self.theme.selection;
+        let line_height = self.geometry.font_height;
+        let rows = std::cmp::min(palette.matches.len(), PALETTE_ROWS);
+        let x = area.x + self.geometry.font_advance;
+        let w = area.w - 2.0 * self.geometry.font_advance;
+
+        canvas.set_color(nanovg::Color::from_rgb(fg.r, fg.g, fg.b));
+        canvas.move_to(x, area.y);
+        canvas.draw_rect(w, line_height * (rows + 1) as f32 + 2.0);
+        canvas.set_color(nanovg::Color::from_rgb(bg.r, bg.g, bg.b));
+        canvas.move_to(x + 1.0, area.y + 1.0);
+        canvas.draw_rect(w - 2.0, line_height * (rows + 1) as f32);
+
+        let descender = canvas.font_metrics.descender;
+        let baseline = |row: usize| area.y + 1.0 + line_height * (row + 1) as f32 + descender;
+        canvas.set_color(nanovg::Color::from_rgb(fg.r, fg.g, fg.b));
+        canvas.move_to(x + self.geometry.font_advance, baseline(0));
+        canvas.draw_str(&format!("> {}", palette.input));
+
+        for (row, i) in palette.matches.iter().take(rows).enumerate() {
+            let (name, desc) = self.commands[*i];
+            if row == palette.selected {
+                canvas.set_color(nanovg::Color::from_rgb(sel.r, sel.g, sel.b));
+                canvas.move_to(x + 1.0, area.y + 1.0 + line_height * (row + 1) as f32);
+                canvas.draw_rect(w - 2.0, line_height);
+            }
+            canvas.set_color(nanovg::Color::from_rgb(fg.r, fg.g, fg.b));
+            canvas.move_to(x + self.geometry.font_advance, baseline(row + 1));
+            canvas.draw_str(&format!("{}: {}", name, desc));
+        }
+    }
+}
+
+/// Index of a command in the view or the window command list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmdId {
+    View(usize),
+    Window(usize),
+}
+
+/// Every view and window command, indexed by name
+struct Commands {
+    view: Vec<Box<dyn ViewCmd>>,
+    window: Vec<Box<dyn WindowCmd>>,
+    by_name: HashMap<String, CmdId>,
+}
+
+impl Commands {
+    fn new() -> Self {
+        let view = commands::view::get_all();
+        let window = commands::window::get_all();
+        let mut by_name = HashMap::new();
+        for (i, c) in view.iter().enumerate() {
+            by_name.insert(c.name().to_owned(), CmdId::View(i));
+        }
+        for (i, c) in window.iter().enumerate() {
+            by_name.insert(c.name().to_owned(), CmdId::Window(i));
+        }
+        Commands { view, window, by_name }
+    }
+
+    /// return the name and the description of every command
+    fn descriptions(&self) -> Vec<(&'static str, &'static str)> {
+        self.view
+            .iter()
+            .map(|c| (c.name(), c.desc()))
+            .chain(self.window.iter().map(|c| (c.name(), c.desc())))
+            .collect()
+    }
+
+    /// run a command on the window, or on its current view. The command is recorded in the macro being recorded
+    fn run(&mut self, id: CmdId, win: &mut EditorWindow<'_>) {
+        match id {
+            CmdId::View(i) => {
+                if !self.view[i].is_interactive() {
+                    win.record_command(self.view[i].name());
+                }
+                self.view[i].run(&mut win.views[win.current_view]);
+            }
+            CmdId::Window(i) => {
+                if !self.window[i].is_interactive() {
+                    win.record_command(self.window[i].name());
+                }
+                self.window[i].run(win);
+            }
+        }
     }
+
+    fn run_by_name(&mut self, name: &str, win: &mut EditorWindow<'_>) {
+        if let Some(id) = self.by_name.get(name).cloned() {
+            self.run(id, win);
+        }
+    }
+
+    /// run the actions of the macros played since the last call
+    fn run_macro_queue(&mut self, win: &mut EditorWindow<'_>) {
+        for action in win.take_macro_queue() {
+            match action {
+                MacroAction::Char(ch) => win.type_char(ch),
+                MacroAction::Command(name) => self.run_by_name(name, win),
+            }
+        }
+    }
+}
+
+fn tab_name(b: &Buffer) -> &str {
+    b.get_filename()
+        .and_then(|f| f.file_name())
+        .and_then(|f| f.to_str())
+        .unwrap_or("untitled")
 }
 
 pub fn start<P: AsRef<Path>>(file: Option<P>) {
     let mut width = super::SETTINGS.read().unwrap().get::<f32>("width").unwrap();
     let mut height = super::SETTINGS.read().unwrap().get::<f32>("height").unwrap();
+    let scroll_lines = super::SETTINGS.read().unwrap().get::<i32>("scrollLines").unwrap();
+    let natural_scroll = super::SETTINGS.read().unwrap().get::<bool>("naturalScroll").unwrap();
+    // 0 to disable the blinking
+    let blink_interval = super::SETTINGS.read().unwrap().get::<u64>("caretBlinkInterval").unwrap();
+
+    let font_size = super::SETTINGS.read().unwrap().get::<f32>("fontSize").unwrap();
+    let fallback_fonts: Vec<PathBuf> = super::SETTINGS
+        .read()
+        .unwrap()
+        .get::<Vec<String>>("fallbackFonts")
+        .unwrap()
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
 
-    let mut system_window = crate::nanovg::System::new("None", width, height, FONT_SIZE);
+    let mut system_window = crate::nanovg::System::new("None", width, height, font_size, &fallback_fonts);
 
     // create window. TODO: passing font_height as parameter feel off
     let font_height = system_window.canvas.font_metrics.line_height;
     let font_advance = system_window.canvas.font_metrics.advance;
-    let mut win = EditorWindow::new(
-        Geometry {
-            x: 0.0,
-            y: 0.0,
-            w: width,
-            h: height,
-            font_height: font_height,
-            font_advance: font_advance,
-        },
-        file,
-    );
+    let filename = file.as_ref().map(|f| f.as_ref().display().to_string());
+    let geometry = Geometry {
+        x: 0.0,
+        y: 0.0,
+        w: width,
+        h: height,
+        font_height: font_height,
+        font_advance: font_advance,
+    };
+    // the last session is restored when no file is given
+    let session_file = session::user_session_file();
+    let win = match (file, &session_file) {
+        (None, Some(session_file)) if session_file.exists() => {
+            Ok(EditorWindow::from_session(geometry, session_file, RecentFiles::load_user()))
+        }
+        (file, _) => EditorWindow::new(geometry, file, RecentFiles::load_user()),
+    };
+    let mut win = match win {
+        Ok(win) => win,
+        Err(e) => {
+            println!("Unable to open {}: {}", filename.unwrap_or_default(), e);
+            return;
+        }
+    };
+    if let Some(theme) = Theme::load_user_theme() {
+        win.set_theme(theme);
+    }
 
     // create view and windows cmd binding
-    let mut view_cmd = commands::view::get_all();
-    let mut view_cmd_keybinding = HashMap::<KeyBinding, usize>::new();
-    for i in 0..view_cmd.len() {
-        for kb in view_cmd[i].keybinding() {
-            view_cmd_keybinding.insert(kb, i);
+    let mut cmds = Commands::new();
+    let mut keymap = KeyMap::new(KEY_SEQUENCE_TIMEOUT);
+    for (i, c) in cmds.view.iter().enumerate() {
+        for kb in c.keybinding() {
+            keymap.insert(kb, CmdId::View(i));
         }
     }
-    let mut win_cmd = commands::window::get_all();
-    let mut win_cmd_keybinding = HashMap::<KeyBinding, usize>::new();
-    for i in 0..win_cmd.len() {
-        for kb in win_cmd[i].keybinding() {
-            win_cmd_keybinding.insert(kb, i);
+    for (i, c) in cmds.window.iter().enumerate() {
+        for kb in c.keybinding() {
+            keymap.insert(kb, CmdId::Window(i));
         }
     }
+    win.set_commands(cmds.descriptions());
 
     // main loop
     #[derive(Debug,Clone,Copy,PartialEq,Eq)]
     enum MouseState {
         Clicked,
         DoubleClicked,
+        TripleClicked,
         Released,
     }
     use std::time::{Duration, Instant};
-    let mut redraw = true;
     let mut running = true;
     let mut mousex = 0.0;
     let mut mousey = 0.0;
     let mut mouse_state = MouseState::Released;
     let mut last_click_instant = Instant::now();
+    let mut click_count = 0;
+    let mut last_disk_check = Instant::now();
+    let mut title = String::new();
+    let mut frame = RedrawTracker::new(&win);
+    let mut caret_blink = CaretBlink::new(Some(Duration::from_millis(blink_interval)));
     while running {
         let mut resized: Option<glutin::dpi::LogicalSize> = None;
         system_window.events_loop.poll_events(|event| {
             use glutin::{dpi::LogicalPosition, ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent::*};
 
             if let Event::WindowEvent { event, .. } = event {
+                match &event {
+                    ReceivedCharacter(_) | KeyboardInput { .. } | MouseInput { .. } | MouseWheel { .. } => {
+                        caret_blink.reset(Instant::now())
+                    }
+                    _ => (),
+                }
                 match event {
-                    CloseRequested => running = false,
+                    CloseRequested => win.request_quit(),
                     Resized(size) => {
                         resized = Some(size);
                     }
+                    Refresh => frame.force(),
+                    DroppedFile(path) => {
+                        if let Err(e) = win.open_file(&path) {
+                            win.show_message(format!("Unable to open {}: {}", path.display(), e));
+                        }
+                    }
                     ReceivedCharacter(ch) => match ch as u32 {
                         0x00...0x1F => (),
                         0x80...0x9F => (),
                         0x7F => (),
                         _ => {
-                            win.views[win.current_view].insert_char(ch);
-                            redraw = true;
+                            if win.has_palette() {
+                                win.palette_insert_char(ch);
+                            } else if win.has_prompt() {
+                                win.prompt_insert_char(ch);
+                            } else if win.has_incremental_search() {
+                                win.search_insert_char(ch);
+                            } else {
+                                win.type_char(ch);
+                            }
                         }
                     },
                     KeyboardInput { input, .. } => {
                         if input.state == glutin::ElementState::Pressed {
+                            win.clear_message();
                             if let Some(k) = input.virtual_keycode {
                                 let mut km = keybinding::Mod::NONE;
                                 if input.modifiers.ctrl {
@@ -202,13 +1518,46 @@ pub fn start<P: AsRef<Path>>(file: Option<P>) {
                                 if input.modifiers.logo {
                                     km |= keybinding::Mod::LOGO
                                 }
-                                if let Some(cmdid) = view_cmd_keybinding.get(&KeyBinding::new(k, km)) {
-                                    view_cmd[*cmdid].as_mut().run(&mut win.views[win.current_view]);
+                                if win.has_palette() {
+                                    use glutin::VirtualKeyCode::*;
+                                    match k {
+                                        Return | NumpadEnter => {
+                                            if let Some(name) = win.validate_palette() {
+                                                cmds.run_by_name(name, &mut win);
+                                            }
+                                        }
+                                        Escape => win.cancel_palette(),
+                                        Back => win.palette_backspace(),
+                                        Up => win.palette_select(-1),
+                                        Down => win.palette_select(1),
+                                        _ => (),
+                                    }
+                                    return;
+                                }
+                                if win.has_prompt() {
+                                    use glutin::VirtualKeyCode::*;
+                                    match k {
+                                        Return | NumpadEnter => win.validate_prompt(),
+                                        Escape => win.cancel_prompt(),
+                                        Back => win.prompt_backspace(),
+                                        _ => (),
+                                    }
+                                    return;
                                 }
-                                if let Some(cmdid) = win_cmd_keybinding.get(&KeyBinding::new(k, km)) {
-                                    win_cmd[*cmdid].as_mut().run(&mut win);
+                                if win.has_incremental_search() {
+                                    use glutin::VirtualKeyCode::*;
+                                    match k {
+                                        Return | NumpadEnter => win.validate_incremental_search(),
+                                        Escape => win.cancel_incremental_search(),
+                                        Back => win.search_backspace(),
+                                        _ => (),
+                                    }
+                                    return;
+                                }
+                                match keymap.process(KeyBinding::new(k, km), Instant::now()) {
+                                    KeyMatch::Bound(id) => cmds.run(id, &mut win),
+                                    KeyMatch::Pending | KeyMatch::Unbound => (),
                                 }
-                                redraw = true;
                             }
                         }
                     }
@@ -216,13 +1565,9 @@ pub fn start<P: AsRef<Path>>(file: Option<P>) {
                         delta: MouseScrollDelta::LineDelta(_, y),
                         ..
                     } => {
-                        let y = y as i32;
-                        if y > 0 {
-                            win.views[win.current_view].scroll(Direction::Up, y * 3);
-                        } else {
-                            win.views[win.current_view].scroll(Direction::Down, -y * 3);
-                        }
-                        redraw = true;
+                        let delta = y as i32 * scroll_lines;
+                        let delta = if natural_scroll { delta } else { -delta };
+                        win.views[win.current_view].scroll_lines(delta);
                     }
                     CursorMoved {
                         position: LogicalPosition { x, y },
@@ -232,8 +1577,11 @@ pub fn start<P: AsRef<Path>>(file: Option<P>) {
                         mousex = x;
                         mousey = y;
                         if mouse_state == MouseState::Clicked {
-                            win.views[win.current_view].click(mousex as _, mousey as _, true);
-                            redraw = true;
+                            if modifiers.alt {
+                                win.drag_block(mousex as _, mousey as _);
+                            } else {
+                                win.drag(mousex as _, mousey as _);
+                            }
                         }
                     }
                     MouseInput {
@@ -244,14 +1592,26 @@ pub fn start<P: AsRef<Path>>(file: Option<P>) {
                     } => {
                         let duration = last_click_instant.elapsed();
                         if duration < Duration::from_millis(500) {
-                            mouse_state = MouseState::DoubleClicked;
-                            win.views[win.current_view].double_click(mousex as _, mousey as _);
+                            click_count += 1;
                         } else {
-                            mouse_state = MouseState::Clicked;
-                            win.views[win.current_view].click(mousex as _, mousey as _, modifiers.shift);
+                            click_count = 1;
+                        }
+                        match click_count {
+                            1 => {
+                                mouse_state = MouseState::Clicked;
+                                win.click(mousex as _, mousey as _, modifiers.shift);
+                            }
+                            2 => {
+                                mouse_state = MouseState::DoubleClicked;
+                                win.views[win.current_view].double_click(mousex as _, mousey as _);
+                            }
+                            _ => {
+                                mouse_state = MouseState::TripleClicked;
+                                win.views[win.current_view].triple_click();
+                                click_count = 0;
+                            }
                         }
                         last_click_instant = Instant::now();
-                        redraw = true;
                     }
                     MouseInput {
                         button: MouseButton::Left,
@@ -271,13 +1631,39 @@ pub fn start<P: AsRef<Path>>(file: Option<P>) {
             width = system_window.log_width() as _;
             height = system_window.log_height() as _;
             win.resize(width as _, height as _);
-            redraw = true;
+            frame.force();
+        }
+
+        cmds.run_macro_queue(&mut win);
+
+        if win.is_quitting() {
+            running = false;
+        }
+
+        if win.font_size() != system_window.font_size() {
+            system_window.set_font_size(win.font_size());
+            let metrics = &system_window.canvas.font_metrics;
+            win.set_font_metrics(metrics.line_height, metrics.advance);
+            frame.force();
         }
 
-        // redraw only when needed
-        if redraw {
+        if last_disk_check.elapsed() >= Duration::from_secs(1) {
+            win.check_external_changes();
+            last_disk_check = Instant::now();
+        }
+
+        win.set_caret_visible(caret_blink.is_visible(Instant::now()));
+
+        // all the pending events are handled, draw at most once and only when needed
+        if frame.needs_redraw(&win) {
+            let t = win.title();
+            if t != title {
+                system_window.window.set_title(&t);
+                title = t;
+            }
+
             // clear
-            let bg = STYLE.theme.settings.background.unwrap_or(highlighting::Color::BLACK);
+            let bg = win.theme.background;
 
             system_window.canvas.clear(nanovg::Color::from_rgb(bg.r, bg.g, bg.b));
             win.draw(&mut system_window.canvas);
@@ -286,10 +1672,811 @@ pub fn start<P: AsRef<Path>>(file: Option<P>) {
         } else {
             thread::sleep(time::Duration::from_millis(10));
         }
+    }
 
-        redraw = false;
+    if let Some(session_file) = session_file {
+        if let Err(e) = win.save_session(&session_file) {
+            println!("Unable to save the session {}: {}", session_file.display(), e);
+        }
     }
 
     super::SETTINGS.write().unwrap().set("width", width as i64).unwrap();
     super::SETTINGS.write().unwrap().set("height", height as i64).unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::tests::write_with_mtime;
+    use crate::nanovg::tests::{drawn_chars, drawn_rects, test_canvas};
+    use crate::nanovg::{Canvas, MonoFontMetrics};
+    use crate::recent::RecentFiles;
+    use crate::view::{CursorStyle, Direction, View};
+    use crate::window::{
+        CaretBlink, CmdId, Commands, EditorMode, EditorWindow, Geometry, MacroAction, RedrawTracker, SplitDirection,
+    };
+    use std::rc::Rc;
+    use std::{env, fs, io, time};
+
+    const GEO: Geometry = Geometry {
+        x: 0.0,
+        y: 0.0,
+        w: 100.0,
+        h: 100.0,
+        font_advance: 10.0,
+        font_height: 10.0,
+    };
+
+    #[test]
+    fn prompt() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.views[0].insert("a\nb\nc\nd");
+        w.open_prompt("Line", |c| c.is_ascii_digit(), |w, input| {
+            let line: usize = input.parse().unwrap();
+            w.views[w.current_view].goto_line(line - 1);
+        });
+        w.prompt_insert_char('3');
+        w.prompt_insert_char('x');
+        w.prompt_insert_char('2');
+        w.prompt_backspace();
+        assert!(w.has_prompt());
+        w.validate_prompt();
+        assert!(!w.has_prompt());
+        assert_eq!(w.views[0].line_idx(), 2);
+
+        w.open_prompt("Line", |c| c.is_ascii_digit(), |w, _| w.views[w.current_view].goto_line(0));
+        w.cancel_prompt();
+        assert_eq!(w.views[0].line_idx(), 2);
+    }
+    #[test]
+    fn incremental_search() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.views[0].insert("foo\nbar\nfoo bar\nbaz\n\n\n\n\n\n\nbar");
+        w.views[0].set_cursor_point(1, 1);
+        w.open_incremental_search();
+        assert!(w.has_incremental_search());
+        w.search_insert_char('b');
+        assert_eq!(w.views[0].cursor_point(), (2, 4));
+        w.search_insert_char('a');
+        w.search_insert_char('z');
+        assert_eq!(w.views[0].cursor_point(), (3, 0));
+        w.search_backspace();
+        assert_eq!(w.views[0].cursor_point(), (2, 4));
+        assert_eq!(w.draw_state().prompt, Some("Search: ba".to_owned()));
+        w.validate_incremental_search();
+        assert!(!w.has_incremental_search());
+        assert_eq!(w.views[0].cursor_point(), (2, 4));
+        w.views[0].search_next();
+        assert_eq!(w.views[0].cursor_point(), (3, 0));
+        // the typed query left a single jump, at the start of the search
+        w.views[0].jump_back();
+        assert_eq!(w.views[0].cursor_point(), (2, 4));
+        w.views[0].jump_back();
+        assert_eq!(w.views[0].cursor_point(), (1, 1));
+        w.views[0].jump_back();
+        assert_eq!(w.views[0].cursor_point(), (1, 1));
+
+        // the first match after the cursor is far below, cancel restores the cursor and the scrolling
+        w.views[0].set_cursor_point(3, 1);
+        let first_line = w.views[0].first_visible_line();
+        w.open_incremental_search();
+        w.search_insert_char('b');
+        w.search_insert_char('a');
+        w.search_insert_char('r');
+        assert_eq!(w.views[0].cursor_point(), (10, 0));
+        assert!(w.views[0].first_visible_line() > first_line);
+        w.cancel_incremental_search();
+        assert!(!w.has_incremental_search());
+        assert_eq!(w.views[0].cursor_point(), (3, 1));
+        assert_eq!(w.views[0].first_visible_line(), first_line);
+        assert_eq!(w.draw_state().prompt, None);
+    }
+    #[test]
+    fn cancel() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        assert!(!w.cancel());
+        w.views[0].insert("foo bar foo");
+        w.views[0].search("foo");
+        w.views[0].select_all();
+        w.open_palette();
+        w.set_modal_editing(true);
+        w.type_char('i');
+        assert_eq!(w.mode(), EditorMode::Insert);
+
+        // one state at a time, from the top
+        assert!(w.cancel());
+        assert!(!w.has_palette());
+        assert!(w.cancel());
+        assert_eq!(w.mode(), EditorMode::Normal);
+        assert!(w.views[0].has_selection());
+        assert!(w.cancel());
+        assert!(!w.views[0].has_selection());
+        assert!(w.views[0].has_search());
+        assert!(w.cancel());
+        assert!(!w.views[0].has_search());
+        assert!(!w.cancel());
+        assert!(!w.is_quitting());
+        assert_eq!(w.views[0].get_buffer().borrow().to_string(), "foo bar foo");
+    }
+    #[test]
+    fn cursor_style() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.set_cursor_style(CursorStyle::Underline);
+        assert_eq!(w.cursor_style(), CursorStyle::Underline);
+        w.set_modal_editing(true);
+        assert_eq!(w.cursor_style(), CursorStyle::Block);
+        w.type_char('i');
+        assert_eq!(w.cursor_style(), CursorStyle::Underline);
+    }
+    #[test]
+    fn quit() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        assert!(!w.should_block_quit());
+        w.request_quit();
+        assert!(w.is_quitting());
+
+        // a modified buffer asks for a confirmation
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.add_new_view::<&str>(None).unwrap();
+        w.views[0].insert("a");
+        assert!(w.should_block_quit());
+        w.request_quit();
+        assert!(!w.is_quitting());
+        assert!(w.has_prompt());
+        w.cancel_prompt();
+        assert!(!w.is_quitting());
+
+        w.request_quit();
+        w.prompt_insert_char('x');
+        w.prompt_insert_char('q');
+        w.validate_prompt();
+        assert!(w.is_quitting());
+    }
+    #[test]
+    fn quit_after_save() {
+        let path = env::temp_dir().join(format!("nonedit_{}_quit.txt", std::process::id()));
+        fs::write(&path, "a").unwrap();
+        let mut w = EditorWindow::new(GEO, Some(&path), RecentFiles::default()).unwrap();
+        w.views[0].insert("b");
+        w.add_new_view::<&str>(None).unwrap();
+        w.views[1].insert("c");
+
+        // the untitled buffer can not be saved
+        w.request_quit();
+        w.prompt_insert_char('s');
+        w.validate_prompt();
+        assert!(!w.is_quitting());
+        assert_eq!(w.message(), Some("Unable to save untitled"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ba");
+        assert!(w.views[1].get_buffer().borrow().is_dirty());
+
+        w.views[1].undo();
+        assert!(!w.should_block_quit());
+        w.request_quit();
+        assert!(w.is_quitting());
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn split() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        assert_eq!(w.views[0].page_length(), 7);
+        w.split(SplitDirection::Horizontal);
+        assert_eq!(w.views.len(), 2);
+        assert_eq!(w.current_view, 1);
+        assert_eq!(w.views[0].page_length(), 3);
+        assert_eq!(w.views[1].page_length(), 3);
+        w.split(SplitDirection::Horizontal);
+        assert_eq!(w.views[0].page_length(), 1);
+        assert_eq!(w.pane_geometry(2).y, 62.0);
+        assert_eq!(w.pane_at(50.0, 70.0), Some(2));
+
+        w.close_split();
+        assert_eq!(w.views.len(), 2);
+        assert_eq!(w.current_view, 1);
+        w.split(SplitDirection::Vertical);
+        assert_eq!(w.views[0].page_length(), 7);
+        assert_eq!(w.pane_geometry(1).x, 33.0);
+        assert_eq!(w.pane_at(70.0, 10.0), Some(2));
+
+        w.focus_next_split();
+        assert_eq!(w.current_view, 0);
+        w.click(40.0, 10.0, false);
+        assert_eq!(w.current_view, 1);
+        w.close_split();
+        w.close_split();
+        assert_eq!(w.panes, vec![0]);
+        w.close_split();
+        assert_eq!(w.panes, vec![0]);
+        assert_eq!(w.views[0].page_length(), 7);
+    }
+    #[test]
+    fn split_limit() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        for _ in 0..10 {
+            w.split(SplitDirection::Horizontal);
+        }
+        assert_eq!(w.panes.len(), 4);
+        assert_eq!(w.pane_geometry(3).h, 20.0);
+        assert!(w.views.iter().all(|v| v.page_length() == 1));
+
+        // a pane shorter than a line still shows one line
+        let mut v = View::new(w.views[0].get_buffer(), Geometry { h: 5.0, ..GEO });
+        assert_eq!(v.page_length(), 1);
+        v.relayout(Geometry { w: 5.0, h: 0.0, ..GEO });
+        assert_eq!(v.page_length(), 1);
+    }
+    #[test]
+    fn open_file() {
+        let mut p = env::temp_dir();
+        p.push(format!("nonedit_{}_open_file", std::process::id()));
+        fs::write(&p, "hello").unwrap();
+
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.open_file(&p).unwrap();
+        fs::remove_file(&p).unwrap();
+        assert_eq!(w.buffers.len(), 2);
+        assert_eq!(w.views.len(), 2);
+        assert_eq!(w.current_view, 1);
+        assert_eq!(w.panes, vec![1]);
+        assert!(w.open_file(&p).is_err());
+        assert_eq!(w.buffers.len(), 2);
+
+        w.cycle_buffer(true);
+        assert_eq!(w.current_view, 0);
+        w.cycle_buffer(false);
+        assert_eq!(w.current_view, 1);
+
+        // a buffer whose views are all shown in other panes get a new view
+        w.split(SplitDirection::Vertical);
+        w.cycle_buffer(true);
+        assert_eq!(w.panes, vec![1, 0]);
+        w.focus_next_split();
+        w.cycle_buffer(true);
+        assert_eq!(w.panes, vec![3, 0]);
+        assert!(Rc::ptr_eq(&w.views[3].get_buffer(), &w.buffers[0]));
+    }
+    #[test]
+    fn status() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        assert_eq!(w.status(), "[No Name]    Ln 1, Col 1");
+        w.views[0].insert("ab\n\tcd");
+        assert_eq!(w.status(), "[No Name] *    Ln 2, Col 7");
+        w.views[0].set_cursor_point(0, 1);
+        assert_eq!(w.status(), "[No Name] *    Ln 1, Col 2");
+    }
+    #[test]
+    fn stats_message() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.views[0].insert("one two\nthree");
+        let state = w.draw_state();
+        w.show_stats();
+        assert_eq!(w.message(), Some("13 chars, 2 lines, 3 words"));
+        assert_ne!(w.draw_state(), state);
+        w.clear_message();
+        assert_eq!(w.message(), None);
+        assert_eq!(w.draw_state(), state);
+    }
+    #[test]
+    fn open_dropped_files() {
+        let mut paths = Vec::new();
+        for (name, content) in &[("first", &b"one"[..]), ("second", &b"two"[..]), ("binary", &b"\x00\x01"[..])] {
+            let mut p = env::temp_dir();
+            p.push(format!("nonedit_{}_dropped_{}", std::process::id(), name));
+            fs::write(&p, content).unwrap();
+            paths.push(p);
+        }
+
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.open_file(&paths[0]).unwrap();
+        w.open_file(&paths[1]).unwrap();
+        assert_eq!(w.buffers.len(), 3);
+        assert_eq!(w.views.len(), 3);
+        assert_eq!(w.current_view, 2);
+        assert_eq!(w.get_current_view().to_string(), "two");
+
+        let err = w.open_file(&paths[2]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(w.buffers.len(), 3);
+        assert_eq!(w.current_view, 2);
+        assert_eq!(w.recent_files().len(), 2);
+        assert_eq!(w.recent_files()[0], paths[1].canonicalize().unwrap());
+        for p in &paths {
+            fs::remove_file(p).unwrap();
+        }
+    }
+    #[test]
+    fn save_and_restore_session() {
+        let mut paths = Vec::new();
+        for name in &["first", "second", "removed"] {
+            let mut p = env::temp_dir();
+            p.push(format!("nonedit_{}_session_{}", std::process::id(), name));
+            fs::write(&p, "line\n".repeat(40)).unwrap();
+            paths.push(p);
+        }
+        let mut session_file = env::temp_dir();
+        session_file.push(format!("nonedit_{}_session.json", std::process::id()));
+
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        for (p, cursor) in paths.iter().zip(&[7, 52, 3]) {
+            w.open_file(p).unwrap();
+            w.get_current_view_mut().set_index(*cursor);
+        }
+        w.views[2].set_first_visible_line(6);
+        w.show_view(2);
+        w.save_session(&session_file).unwrap();
+        fs::remove_file(&paths[2]).unwrap();
+
+        let w = EditorWindow::from_session(GEO, &session_file, RecentFiles::default());
+        assert_eq!(w.views.len(), 2);
+        assert_eq!(w.views[0].index(), 7);
+        assert_eq!(w.views[1].index(), 52);
+        assert_eq!(w.views[1].first_visible_line(), 6);
+        assert_eq!(w.current_view, 1);
+        assert_eq!(w.views[1].get_buffer().borrow().get_filename(), Some(paths[1].as_path()));
+
+        fs::remove_file(&paths[0]).unwrap();
+        fs::remove_file(&paths[1]).unwrap();
+        fs::remove_file(&session_file).unwrap();
+    }
+    #[test]
+    fn font_zoom() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        let size = w.font_size();
+        w.zoom(2.0);
+        assert_eq!(w.font_size(), size + 2.0);
+        w.zoom(-100.0);
+        assert_eq!(w.font_size(), 6.0);
+        w.zoom(100.0);
+        assert_eq!(w.font_size(), 72.0);
+        w.reset_zoom();
+        assert_eq!(w.font_size(), size);
+    }
+    #[test]
+    fn zoom_max_with_split() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.views[0].insert("one\ntwo\nthree");
+        w.split(SplitDirection::Horizontal);
+        w.zoom(100.0);
+        let size = w.font_size();
+        // every pane is shorter than a line at the maximum size
+        w.set_font_metrics(size * 1.2, size * 0.6);
+        assert!(w.views.iter().all(|v| v.page_length() == 1));
+        w.views[1].move_cursor(Direction::Down, false);
+        w.draw(&mut Canvas::new(MonoFontMetrics {
+            advance: size * 0.6,
+            ascender: size,
+            descender: -size * 0.2,
+            line_height: size * 1.2,
+        }));
+    }
+    #[test]
+    fn font_metrics_relayout() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        // the tab strip and the status bar are one line each
+        assert_eq!(w.get_current_view().page_length(), 7);
+        w.set_font_metrics(20.0, 10.0);
+        assert_eq!(w.get_current_view().page_length(), 2);
+        w.set_font_metrics(5.0, 10.0);
+        assert_eq!(w.get_current_view().page_length(), 17);
+    }
+    #[test]
+    fn caret_blink() {
+        let ms = time::Duration::from_millis;
+        let t0 = time::Instant::now();
+        let mut blink = CaretBlink::new(Some(ms(500)));
+        blink.reset(t0);
+        // solid while typing
+        assert!(blink.is_visible(t0));
+        assert!(blink.is_visible(t0 + ms(499)));
+        // then blinking
+        assert!(!blink.is_visible(t0 + ms(500)));
+        assert!(!blink.is_visible(t0 + ms(999)));
+        assert!(blink.is_visible(t0 + ms(1000)));
+        assert!(!blink.is_visible(t0 + ms(1700)));
+        blink.reset(t0 + ms(1700));
+        assert!(blink.is_visible(t0 + ms(1800)));
+
+        let blink = CaretBlink::new(None);
+        assert!(blink.is_visible(t0 + ms(700)));
+        let blink = CaretBlink::new(Some(ms(0)));
+        assert!(blink.is_visible(t0 + ms(700)));
+    }
+    #[test]
+    fn hidden_caret_redraw() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        let mut frame = RedrawTracker::new(&w);
+        assert!(frame.needs_redraw(&w));
+        w.set_caret_visible(true);
+        assert!(!frame.needs_redraw(&w));
+        w.set_caret_visible(false);
+        assert!(frame.needs_redraw(&w));
+    }
+    #[test]
+    fn indent_guides_redraw() {
+        let mut cmds = Commands::new();
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        let mut frame = RedrawTracker::new(&w);
+        assert!(frame.needs_redraw(&w));
+        w.toggle_indent_guides();
+        assert!(frame.needs_redraw(&w));
+        assert!(!frame.needs_redraw(&w));
+
+        // also when the toggle is replayed from a macro
+        w.toggle_macro_recording();
+        cmds.run_by_name("ToggleIndentGuides", &mut w);
+        w.toggle_macro_recording();
+        assert!(frame.needs_redraw(&w));
+        w.play_macro();
+        cmds.run_macro_queue(&mut w);
+        assert!(frame.needs_redraw(&w));
+    }
+    #[test]
+    fn current_line_highlight() {
+        fn highlighted(w: &mut EditorWindow<'_>) -> bool {
+            let mut canvas = test_canvas();
+            w.draw(&mut canvas);
+            let c = w.theme.line_highlight;
+            !drawn_rects(&canvas, Some(nanovg::Color::from_rgb(c.r, c.g, c.b))).is_empty()
+        }
+
+        let mut cmds = Commands::new();
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        if w.highlight_current_line {
+            w.toggle_current_line_highlight();
+        }
+        assert!(!highlighted(&mut w));
+        let mut frame = RedrawTracker::new(&w);
+        assert!(frame.needs_redraw(&w));
+        cmds.run_by_name("ToggleCurrentLineHighlight", &mut w);
+        assert!(frame.needs_redraw(&w));
+        assert!(highlighted(&mut w));
+        // the new views follow the window
+        w.add_new_view::<&str>(None).unwrap();
+        assert!(highlighted(&mut w));
+    }
+    #[test]
+    fn redraw_once_per_frame() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        let mut frame = RedrawTracker::new(&w);
+        assert!(frame.needs_redraw(&w));
+        assert!(!frame.needs_redraw(&w));
+
+        // many events in a frame
+        let mut draws = 0;
+        for c in "hello".chars() {
+            w.type_char(c);
+        }
+        w.views[0].move_cursor(Direction::Left, false);
+        for _ in 0..3 {
+            if frame.needs_redraw(&w) {
+                draws += 1;
+            }
+        }
+        assert_eq!(draws, 1);
+
+        frame.force();
+        assert!(frame.needs_redraw(&w));
+        assert!(!frame.needs_redraw(&w));
+    }
+    #[test]
+    fn auto_close() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.set_auto_close(true);
+        for c in "f(a[0]) {".chars() {
+            w.type_char(c);
+        }
+        assert_eq!(w.get_current_view().to_string(), "f(a[0]) {}");
+        assert_eq!(w.get_current_view().col_idx(), 9);
+        for c in "}\"don't\"".chars() {
+            w.type_char(c);
+        }
+        assert_eq!(w.get_current_view().to_string(), "f(a[0]) {}\"don't\"");
+        assert_eq!(w.get_current_view().col_idx(), 17);
+
+        // a pair typed before a word is not closed
+        w.get_current_view_mut().set_cursor_point(0, 2);
+        w.type_char('(');
+        assert_eq!(w.get_current_view().to_string(), "f((a[0]) {}\"don't\"");
+
+        // the selection is wrapped
+        w.get_current_view_mut().select_all();
+        w.type_char('[');
+        assert_eq!(w.get_current_view().to_string(), "[f((a[0]) {}\"don't\"]");
+        assert_eq!(w.get_current_view().get_selection(), Some("f((a[0]) {}\"don't\"".to_owned()));
+
+        w.set_auto_close(false);
+        w.get_current_view_mut().clear_selection();
+        w.get_current_view_mut().set_cursor_point(0, 0);
+        w.type_char('(');
+        w.type_char('[');
+        assert_eq!(w.get_current_view().to_string(), "([[f((a[0]) {}\"don't\"]");
+    }
+    #[test]
+    fn modal_editing() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        assert_eq!(w.mode(), EditorMode::Insert);
+        assert!(!w.enter_normal_mode());
+        w.type_char('h');
+        assert_eq!(w.get_current_view().to_string(), "h");
+
+        w.set_modal_editing(true);
+        assert_eq!(w.mode(), EditorMode::Normal);
+        assert!(w.status().starts_with("NORMAL    "));
+        for c in "hjkl".chars() {
+            w.type_char(c);
+        }
+        assert_eq!(w.get_current_view().to_string(), "h");
+        assert_eq!(w.get_current_view().col_idx(), 1);
+
+        w.type_char('i');
+        assert_eq!(w.mode(), EditorMode::Insert);
+        assert!(w.status().starts_with("INSERT    "));
+        for c in "ello\nworld".chars() {
+            w.type_char(c);
+        }
+        assert_eq!(w.get_current_view().to_string(), "hello\nworld");
+        assert!(w.enter_normal_mode());
+        assert!(!w.enter_normal_mode());
+
+        w.type_char('h');
+        w.type_char('k');
+        w.type_char('x');
+        assert_eq!(w.get_current_view().to_string(), "hell\nworld");
+        // an other command between the two d cancels the delete line
+        w.type_char('d');
+        w.type_char('j');
+        w.type_char('d');
+        assert_eq!(w.get_current_view().to_string(), "hell\nworld");
+        w.type_char('d');
+        assert_eq!(w.get_current_view().to_string(), "world");
+
+        w.set_modal_editing(false);
+        assert_eq!(w.mode(), EditorMode::Insert);
+        assert!(w.status().starts_with("[No Name]"));
+    }
+    #[test]
+    fn commands_by_name() {
+        let cmds = Commands::new();
+        // every command is found by its name
+        assert_eq!(cmds.by_name.len(), cmds.view.len() + cmds.window.len());
+        for (i, c) in cmds.view.iter().enumerate() {
+            assert_eq!(cmds.by_name.get(c.name()), Some(&CmdId::View(i)));
+        }
+        for (i, c) in cmds.window.iter().enumerate() {
+            assert_eq!(cmds.by_name.get(c.name()), Some(&CmdId::Window(i)));
+        }
+    }
+    #[test]
+    fn macros() {
+        let mut cmds = Commands::new();
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.get_current_view_mut().insert("ab\ncd");
+        w.get_current_view_mut().set_cursor_point(0, 0);
+
+        // an empty macro does nothing
+        w.toggle_macro_recording();
+        w.toggle_macro_recording();
+        w.play_macro();
+        cmds.run_macro_queue(&mut w);
+        assert_eq!(w.get_current_view().to_string(), "ab\ncd");
+
+        cmds.run_by_name("ToggleMacroRecording", &mut w);
+        assert!(w.is_recording_macro());
+        assert!(w.status().starts_with("REC    "));
+        // no nested recording, and no playback while recording
+        assert!(!w.start_macro_recording());
+        w.play_macro();
+        assert!(w.take_macro_queue().is_empty());
+        w.type_char('>');
+        cmds.run_by_name("Down", &mut w);
+        cmds.run_by_name("Home", &mut w);
+        cmds.run_by_name("ToggleMacroRecording", &mut w);
+        assert!(!w.is_recording_macro());
+        assert_eq!(w.get_current_view().to_string(), ">ab\ncd");
+        assert_eq!(
+            w.recorded_macro,
+            vec![
+                MacroAction::Char('>'),
+                MacroAction::Command("Down"),
+                MacroAction::Command("Home")
+            ]
+        );
+
+        cmds.run_by_name("PlayMacro", &mut w);
+        cmds.run_macro_queue(&mut w);
+        assert_eq!(w.get_current_view().to_string(), ">ab\n>cd");
+        assert_eq!(w.get_current_view().line_idx(), 1);
+    }
+    #[test]
+    fn goto_line_past_end() {
+        let mut cmds = Commands::new();
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.get_current_view_mut().insert("ab\ncd\nef");
+        w.get_current_view_mut().set_cursor_point(1, 1);
+        cmds.run_by_name("GotoLine", &mut w);
+        w.prompt_insert_char('9');
+        w.validate_prompt();
+        assert_eq!(w.message(), Some("No line 9"));
+        assert_eq!(w.get_current_view().index(), 4);
+
+        cmds.run_by_name("GotoLine", &mut w);
+        w.prompt_insert_char('3');
+        w.validate_prompt();
+        assert_eq!(w.get_current_view().index(), 6);
+    }
+    #[test]
+    fn macro_with_prompt() {
+        let mut cmds = Commands::new();
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.get_current_view_mut().insert("ab\ncd\nef");
+        w.get_current_view_mut().set_cursor_point(0, 0);
+
+        w.toggle_macro_recording();
+        w.type_char('>');
+        cmds.run_by_name("GotoLine", &mut w);
+        w.prompt_insert_char('3');
+        w.validate_prompt();
+        w.type_char('<');
+        cmds.run_by_name("CommandPalette", &mut w);
+        w.cancel_palette();
+        w.toggle_macro_recording();
+        assert_eq!(w.get_current_view().to_string(), ">ab\ncd\n<ef");
+        assert_eq!(w.recorded_macro, vec![MacroAction::Char('>'), MacroAction::Char('<')]);
+
+        // the replay does not leave a prompt or the palette open
+        w.get_current_view_mut().set_cursor_point(1, 0);
+        w.play_macro();
+        cmds.run_macro_queue(&mut w);
+        assert!(!w.has_prompt());
+        assert!(!w.has_palette());
+        assert_eq!(w.get_current_view().to_string(), ">ab\n><cd\n<ef");
+
+        // the commands opening a dialog are not recorded either
+        for name in &["Open", "Save", "Quit", "GotoLine", "IncrementalSearch", "CommandPalette"] {
+            let interactive = match cmds.by_name[*name] {
+                CmdId::View(i) => cmds.view[i].is_interactive(),
+                CmdId::Window(i) => cmds.window[i].is_interactive(),
+            };
+            assert!(interactive, "{} is recorded", name);
+        }
+        assert!(!cmds.view.iter().any(|c| c.name() == "SelectAll" && c.is_interactive()));
+    }
+    #[test]
+    fn palette() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.set_commands(vec![
+            ("Save", "Save file"),
+            ("SelectAll", "Select the whole buffer"),
+            ("GotoLine", "Go to the given line"),
+        ]);
+        w.open_palette();
+        assert!(w.has_palette());
+        assert_eq!(w.palette.as_ref().unwrap().matches, vec![0, 1, 2]);
+        w.palette_select(-1);
+        assert_eq!(w.palette.as_ref().unwrap().selected, 2);
+
+        w.palette_insert_char('s');
+        w.palette_insert_char('l');
+        // "Save file" matches on its description
+        assert_eq!(w.palette.as_ref().unwrap().matches, vec![1, 0]);
+        let state = w.draw_state();
+        w.palette_select(1);
+        assert_ne!(w.draw_state(), state);
+        assert_eq!(w.validate_palette(), Some("Save"));
+        assert!(!w.has_palette());
+
+        w.open_palette();
+        w.palette_insert_char('x');
+        assert_eq!(w.validate_palette(), None);
+        w.open_palette();
+        w.palette_insert_char('x');
+        w.palette_backspace();
+        w.cancel_palette();
+        assert!(!w.has_palette());
+    }
+    #[test]
+    fn external_changes() {
+        let mut p = env::temp_dir();
+        p.push(format!("nonedit_{}_external_changes", std::process::id()));
+        fs::write(&p, "hello").unwrap();
+
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.open_file(&p).unwrap();
+        w.check_external_changes();
+        assert_eq!(w.status(), format!("nonedit_{}_external_changes    Ln 1, Col 1", std::process::id()));
+
+        write_with_mtime(&p, "bye", 1);
+        let state = w.draw_state();
+        w.check_external_changes();
+        assert!(w.status().contains(" [changed on disk]"));
+        assert!(w.buffers[1].borrow().is_changed_on_disk());
+        assert!(!w.buffers[0].borrow().is_changed_on_disk());
+        assert_ne!(w.draw_state(), state);
+
+        // the new content is read by the reload command
+        Commands::new().run_by_name("Reload", &mut w);
+        fs::remove_file(&p).unwrap();
+        assert_eq!(w.get_current_view().to_string(), "bye");
+        assert!(!w.status().contains(" [changed on disk]"));
+    }
+    #[test]
+    fn draw_state() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        let state = w.draw_state();
+        w.copy();
+        w.views[0].move_cursor(Direction::Left, false);
+        assert_eq!(w.draw_state(), state);
+
+        w.views[0].insert_char('a');
+        assert_ne!(w.draw_state(), state);
+        let state = w.draw_state();
+        w.views[0].move_cursor(Direction::Left, false);
+        assert_ne!(w.draw_state(), state);
+        let state = w.draw_state();
+        // undone in the same frame, nothing to redraw
+        w.views[0].insert("\n\n\n");
+        w.views[0].undo();
+        assert_eq!(w.draw_state(), state);
+
+        w.views[0].insert("\n\n\n");
+        let state = w.draw_state();
+        w.views[0].scroll_lines(1);
+        assert_ne!(w.draw_state(), state);
+        let state = w.draw_state();
+        w.open_prompt("Line", |c| c.is_ascii_digit(), |_, _| ());
+        assert_ne!(w.draw_state(), state);
+        let state = w.draw_state();
+        w.prompt_insert_char('x');
+        assert_eq!(w.draw_state(), state);
+        w.prompt_insert_char('1');
+        assert_ne!(w.draw_state(), state);
+    }
+    #[test]
+    fn draw_glyph_positions() {
+        let mut w = EditorWindow::new::<&str>(GEO, None, RecentFiles::default()).unwrap();
+        w.views[0].insert("ab\n\tc");
+        w.views[0].set_cursor_point(0, 0);
+        w.views[0].focus_on_cursor();
+        if w.show_line_numbers {
+            w.toggle_line_numbers();
+        }
+        // fixed metrics, no font is loaded
+        let mut canvas = test_canvas();
+        canvas.font_metrics.advance = 7.0;
+        w.geometry.font_advance = 7.0;
+        w.layout();
+        w.draw(&mut canvas);
+        // the glyphs of the text area, between the tab strip and the status bar
+        let glyphs: Vec<_> = drawn_chars(&canvas)
+            .into_iter()
+            .filter(|(_, y, _)| *y > 10.0 && *y <= 90.0)
+            .collect();
+        // the text starts below the tab strip, a tab spans up to the next tab stop
+        let tab: usize = crate::SETTINGS.read().unwrap().get("tabSize").unwrap();
+        assert_eq!(
+            glyphs,
+            vec![(0.0, 20.0, 'a'), (7.0, 20.0, 'b'), (7.0 * tab as f32, 30.0, 'c')]
+        );
+    }
+    #[test]
+    fn draw_current_buffer() {
+        fn drawn_text(w: &mut EditorWindow<'_>) -> String {
+            let mut canvas = test_canvas();
+            w.draw(&mut canvas);
+            drawn_chars(&canvas).into_iter().map(|(_, _, c)| c).collect()
+        }
+
+        // wide enough to show the words without scrolling horizontally
+        let mut w = EditorWindow::new::<&str>(Geometry { w: 300.0, ..GEO }, None, RecentFiles::default()).unwrap();
+        w.views[0].insert("first");
+        w.add_new_view::<&str>(None).unwrap();
+        w.views[1].insert("second");
+        let text = drawn_text(&mut w);
+        assert!(text.contains("second"));
+        assert!(!text.contains("first"));
+
+        w.cycle_buffer(true);
+        let text = drawn_text(&mut w);
+        assert!(text.contains("first"));
+        assert!(!text.contains("second"));
+    }
+}